@@ -0,0 +1,249 @@
+//! Support for system-specific alternate dotfiles.
+//!
+//! Several variants of a dotfile can be stored side by side by appending `##` and a
+//! comma-separated list of conditions to the file name:
+//!
+//! <pre>
+//! .dots
+//! └── home
+//!     └── ferris
+//!         ├── .gitconfig##default
+//!         ├── .gitconfig##os.Linux
+//!         └── .gitconfig##os.Linux,hostname.build01
+//! </pre>
+//!
+//! When deploying, the variant whose conditions all match the current system and which
+//! is the most specific is linked to the plain path, e.g. `/home/ferris/.gitconfig`.
+//!
+//! Supported conditions are:
+//!
+//! - `os.<OS>` or `o.<OS>` - operating system, e.g. `Linux` or `Darwin`
+//! - `arch.<ARCH>` or `a.<ARCH>` - CPU architecture, e.g. `x86_64`
+//! - `user.<USER>` or `u.<USER>` - name of the current user
+//! - `hostname.<HOST>` or `h.<HOST>` - host name of the machine
+//! - `default` - always matches, used as a fallback
+//!
+//! Values are compared case-insensitively. A hostname condition outweighs a user
+//! condition, which outweighs arch, which outweighs os, so `##hostname.build01` is
+//! preferred over `##os.Linux,arch.x86_64`. A stored file without a `##` suffix is only
+//! deployed if no alternate of it matches.
+//!
+//! Templates and encrypted dotfiles are alternates of the plain dotfile they are deployed
+//! to, so `.gitconfig.tmpl##os.Linux` is preferred over `.gitconfig##default`. Variants
+//! deployed to the same path that match equally well, such as `.gitconfig` and
+//! `.gitconfig.tmpl`, are all selected, as there is no telling which one to deploy.
+use std::collections::BTreeMap;
+use std::env;
+use std::path::{Path, PathBuf};
+
+use crate::{crypto, template};
+
+/// Separator between a dotfile's file name and its alternate conditions.
+pub const ALTERNATE_SEPARATOR: &str = "##";
+
+/// Properties of a system used to select alternate dotfiles.
+#[derive(Debug, Clone, PartialEq)]
+pub struct System {
+    /// Operating system name, e.g. `Linux`.
+    pub os: String,
+    /// CPU architecture, e.g. `x86_64`.
+    pub arch: String,
+    /// Host name of the machine.
+    pub hostname: String,
+    /// Name of the current user.
+    pub user: String,
+}
+
+impl System {
+    /// Collect the properties of the running system.
+    pub fn current() -> Self {
+        let os = match env::consts::OS {
+            "linux" => "Linux",
+            "macos" => "Darwin",
+            "freebsd" => "FreeBSD",
+            "openbsd" => "OpenBSD",
+            "netbsd" => "NetBSD",
+            "windows" => "Windows",
+            other => other,
+        };
+
+        let hostname = hostname::get()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+
+        let user = env::var("USER")
+            .or_else(|_| env::var("USERNAME"))
+            .ok()
+            .or_else(|| {
+                dirs::home_dir()
+                    .and_then(|home| home.file_name().map(|n| n.to_string_lossy().into()))
+            })
+            .unwrap_or_default();
+
+        Self {
+            os: os.to_string(),
+            arch: env::consts::ARCH.to_string(),
+            hostname,
+            user,
+        }
+    }
+
+    /// Score how well the alternate conditions in `path`'s file name match the system.
+    ///
+    /// Returns `None` if any condition does not match, and `Some(0)` for a path without
+    /// alternate conditions.
+    pub fn score(&self, path: &Path) -> Option<u32> {
+        let conditions = match conditions(path) {
+            Some(conditions) => conditions,
+            None => return Some(0),
+        };
+
+        let mut score = 0;
+        for condition in conditions.split(',') {
+            let (key, value) = match condition.find('.') {
+                Some(i) => (&condition[..i], &condition[i + 1..]),
+                None => (condition, ""),
+            };
+
+            let (expected, weight) = match key {
+                "default" if value.is_empty() => {
+                    score += 1;
+                    continue;
+                },
+                "o" | "os" => (&self.os, 2),
+                "a" | "arch" => (&self.arch, 4),
+                "u" | "user" => (&self.user, 8),
+                "h" | "hostname" => (&self.hostname, 16),
+                _ => return None,
+            };
+
+            if !value.eq_ignore_ascii_case(expected) {
+                return None;
+            };
+            score += weight;
+        }
+        Some(score)
+    }
+
+    /// Out of all `dotfiles`, keep only the best-matching alternate for each deploy
+    /// path. Alternates that do not match the system are dropped, and alternates that
+    /// match equally well are all kept, so the conflict can be reported.
+    pub fn select(&self, dotfiles: Vec<PathBuf>) -> Vec<PathBuf> {
+        let mut selected: BTreeMap<PathBuf, (u32, Vec<PathBuf>)> = BTreeMap::new();
+
+        for dotfile in dotfiles {
+            let score = match self.score(&dotfile) {
+                Some(score) => score,
+                None => continue,
+            };
+
+            // keyed on the path the dotfile is deployed to, without any extensions
+            let base =
+                crypto::strip_extension(&template::strip_extension(&base_path(&dotfile)));
+            match selected.get_mut(&base) {
+                Some((best, _)) if *best > score => {},
+                Some((best, variants)) if *best == score => variants.push(dotfile),
+                _ => {
+                    let _ = selected.insert(base, (score, vec![dotfile]));
+                },
+            }
+        }
+
+        selected
+            .into_iter()
+            .flat_map(|(_, (_, variants))| variants)
+            .collect()
+    }
+}
+
+fn conditions(path: &Path) -> Option<&str> {
+    let file_name = path.file_name()?.to_str()?;
+    let i = file_name.find(ALTERNATE_SEPARATOR)?;
+
+    Some(&file_name[i + ALTERNATE_SEPARATOR.len()..])
+}
+
+/// Strip alternate conditions from `path`'s file name.
+///
+/// # Examples
+///
+/// ```
+/// use badm::alternates::base_path;
+/// use std::path::{Path, PathBuf};
+///
+/// assert_eq!(
+///     base_path(Path::new("home/ferris/.gitconfig##os.Linux")),
+///     PathBuf::from("home/ferris/.gitconfig")
+/// );
+/// ```
+pub fn base_path(path: &Path) -> PathBuf {
+    match path.file_name().and_then(|name| name.to_str()) {
+        Some(file_name) => match file_name.find(ALTERNATE_SEPARATOR) {
+            Some(i) => path.with_file_name(&file_name[..i]),
+            None => path.to_path_buf(),
+        },
+        None => path.to_path_buf(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn system() -> System {
+        System {
+            os: "Linux".to_string(),
+            arch: "x86_64".to_string(),
+            hostname: "build01".to_string(),
+            user: "ferris".to_string(),
+        }
+    }
+
+    #[test]
+    fn score_test() {
+        let system = system();
+
+        assert_eq!(system.score(Path::new(".gitconfig")), Some(0));
+        assert_eq!(system.score(Path::new(".gitconfig##default")), Some(1));
+        assert_eq!(system.score(Path::new(".gitconfig##os.linux")), Some(2));
+        assert_eq!(
+            system.score(Path::new(".gitconfig##os.Linux,hostname.build01")),
+            Some(18)
+        );
+        assert_eq!(system.score(Path::new(".gitconfig##os.Darwin")), None);
+        assert_eq!(system.score(Path::new(".gitconfig##colour.blue")), None);
+    }
+
+    #[test]
+    fn select_test() {
+        let dotfiles = vec![
+            PathBuf::from("/dots/.gitconfig##default"),
+            PathBuf::from("/dots/.gitconfig##os.Linux,h.build01"),
+            PathBuf::from("/dots/.gitconfig##os.Linux"),
+            PathBuf::from("/dots/.bashrc"),
+            PathBuf::from("/dots/.bashrc##os.Darwin"),
+            PathBuf::from("/dots/.zshrc##user.crab"),
+        ];
+
+        assert_eq!(system().select(dotfiles), vec![
+            PathBuf::from("/dots/.bashrc"),
+            PathBuf::from("/dots/.gitconfig##os.Linux,h.build01"),
+        ]);
+    }
+
+    #[test]
+    fn select_extension_test() {
+        let dotfiles = vec![
+            PathBuf::from("/dots/.vimrc##default"),
+            PathBuf::from("/dots/.vimrc.tmpl##os.Linux"),
+            PathBuf::from("/dots/.netrc.enc"),
+            PathBuf::from("/dots/.netrc"),
+        ];
+
+        assert_eq!(system().select(dotfiles), vec![
+            PathBuf::from("/dots/.netrc.enc"),
+            PathBuf::from("/dots/.netrc"),
+            PathBuf::from("/dots/.vimrc.tmpl##os.Linux"),
+        ]);
+    }
+}
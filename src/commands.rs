@@ -1,81 +1,218 @@
 //! Includes the commands used by the badm crate/application.
 
 use std::fs;
-use std::io::{self, Error, ErrorKind};
+use std::io;
 use std::path::{Path, PathBuf};
+use std::process::Command;
 
-use crate::paths::{is_symlink, join_full_paths};
-use crate::Config;
+use crate::conflict::{backup_path, ConflictPolicy};
+use crate::crypto::{decrypt, encrypt, encrypted_path, Secret, DECRYPTED_MODE};
+use crate::errors::{BadmError, PathContext, Result};
+use crate::executor::{Action, Executor};
+use crate::git::{commit_message, Git};
+use crate::manifest::{
+    hash_bytes, hash_file, hash_path, timestamp, Dotfile, LinkMode, Manifest,
+    MANIFEST_FILE_NAME,
+};
+use crate::paths::{
+    deploy_path, is_hard_linked, is_same_device, is_same_path, is_symlink, read_path,
+    resolve_link, DeployMode, Layout, LinkStyle,
+};
+use crate::template::{render, Variables};
 use crate::FileHandler;
 
-/// Take input from file at path and store in set dotfiles directory.
-pub fn store_dotfile(path: &Path) -> io::Result<PathBuf> {
-    if let Some(dots_dir) = Config::get_dots_dir() {
-        // create destination path
-        let dst_path = join_full_paths(&dots_dir, &path).unwrap();
+/// Name of the script run by [`run_bootstrap`], located at the top of a dotfiles
+/// directory.
+///
+/// [`run_bootstrap`]: fn.run_bootstrap.html
+pub const BOOTSTRAP_FILE_NAME: &str = ".badm-bootstrap";
 
-        // if symlink already exists and points to src file, early return
-        if dst_path.exists() && fs::read_link(&dst_path)? == path {
-            return Ok(dst_path);
-        };
+/// Settings for storing a dotfile with [`store_dotfile`].
+///
+/// [`store_dotfile`]: fn.store_dotfile.html
+#[derive(Debug, Default, PartialEq, Eq, Copy, Clone)]
+pub struct StoreOptions {
+    /// Where the dotfile is placed inside the dotfiles directory.
+    pub layout: Layout,
+}
 
-        // create directory if not available
-        let dst_dir = dst_path.parent().unwrap();
+/// Settings for deploying a dotfile with [`deploy_dotfile`].
+///
+/// [`deploy_dotfile`]: fn.deploy_dotfile.html
+#[derive(Debug, Default, PartialEq, Eq, Copy, Clone)]
+pub struct DeployOptions {
+    /// What to do if the target path is already taken.
+    pub on_conflict: ConflictPolicy,
+    /// How the symlink's target is written.
+    pub link_style: LinkStyle,
+    /// Whether the dotfile is symlinked, copied, or hard linked to the target path.
+    pub mode: DeployMode,
+    /// Whether a copy, rendered template, or decrypted file at the target path that was
+    /// modified since it was deployed is replaced, discarding the changes, instead of
+    /// being treated as a conflict.
+    pub force: bool,
+}
 
-        if !dst_dir.exists() {
-            fs::create_dir_all(dst_dir)?;
-        };
+/// Settings for undeploying a dotfile with [`undeploy_dotfile`].
+///
+/// [`undeploy_dotfile`]: fn.undeploy_dotfile.html
+#[derive(Debug, Default, PartialEq, Eq, Copy, Clone)]
+pub struct UndeployOptions {
+    /// Whether the file backed up when the dotfile was deployed is moved back in place.
+    pub restore_backup: bool,
+}
 
-        // move dotfile to dotfiles directory
-        FileHandler::move_file(&path, &dst_path)?;
+/// Take input from file at path and store in dotfiles directory `dots_dir`, at the path
+/// given by `options.layout`.
+///
+/// If `path` is a directory, it is stored as a whole and recorded as folded, so that it
+/// is deployed as a single symlink. Filesystem changes are applied through `exec`, so no
+/// files are touched in dry-run mode.
+pub fn store_dotfile(
+    path: &Path,
+    dots_dir: &Path,
+    options: StoreOptions,
+    exec: &mut Executor,
+) -> Result<PathBuf> {
+    // create destination path
+    let dst_path = options.layout.stored_path(dots_dir, path);
 
-        Ok(dst_path)
-    } else {
-        let err = io::Error::new(
-            io::ErrorKind::NotFound,
-            "Not able to complete operation because BADM_DIR was not set. Please run \
-             `badm set-dir=<DIR> first.`",
-        );
-        Err(err)
-    }
+    // a folded directory can not be merged with files already stored below it
+    if path.is_dir() && dst_path.exists() {
+        return Err(BadmError::Conflict {
+            path: dst_path,
+            message: "is already stored in dotfiles dir, use --no-fold to store the \
+                      files below it individually"
+                .to_string(),
+        });
+    };
+
+    // if symlink already exists and points to src file, early return
+    if is_symlink(&dst_path) && fs::read_link(&dst_path).at_path(&dst_path)? == path {
+        return Ok(dst_path);
+    };
+
+    // create directory if not available
+    let dst_dir = dst_path.parent().unwrap();
+
+    if !dst_dir.exists() {
+        exec.run(Action::CreateDir {
+            path: dst_dir.to_path_buf(),
+        })?;
+    };
+
+    // move dotfile to dotfiles directory
+    exec.run(Action::Move {
+        src: path.to_path_buf(),
+        dst: dst_path.clone(),
+    })?;
+
+    update_manifest(dots_dir, exec, |manifest| {
+        manifest.insert(Dotfile::new(path, &dst_path, dots_dir)?);
+        Ok(())
+    })?;
+
+    Ok(dst_path)
+}
+
+/// Store the file at `path` with [`store_dotfile`] and deploy it back to `path` with
+/// [`deploy_dotfile`], as `badm stow` does. Returns the path of the stored dotfile.
+///
+/// In dry-run mode, the file is not moved out of the way of its link, so the link is
+/// planned without checking `path` for conflicts, reading the content to copy from the
+/// file that would have been moved.
+///
+/// [`store_dotfile`]: fn.store_dotfile.html
+/// [`deploy_dotfile`]: fn.deploy_dotfile.html
+pub fn stow_dotfile(
+    path: &Path,
+    dots_dir: &Path,
+    store_options: StoreOptions,
+    deploy_options: DeployOptions,
+    exec: &mut Executor,
+) -> Result<PathBuf> {
+    let stored = store_dotfile(path, dots_dir, store_options, exec)?;
+
+    if !exec.is_dry_run() {
+        let _ = deploy_dotfile(&stored, path, dots_dir, deploy_options, exec)?;
+        return Ok(stored);
+    };
+
+    let action = match deploy_options.mode {
+        DeployMode::Symlink => Action::Symlink {
+            src: deploy_options.link_style.link_target(&stored, path),
+            dst: path.to_path_buf(),
+        },
+        DeployMode::Hard if !path.is_file() => {
+            return Err(BadmError::invalid(
+                path,
+                "is not a file, only files can be hard linked",
+            ));
+        },
+        DeployMode::Copy if !path.is_file() => {
+            return Err(BadmError::invalid(
+                path,
+                "is not a file, only files can be deployed as copies",
+            ));
+        },
+        DeployMode::Hard => Action::HardLink {
+            src: stored.clone(),
+            dst: path.to_path_buf(),
+        },
+        DeployMode::Copy => Action::Write {
+            path: path.to_path_buf(),
+            contents: fs::read(path).at_path(path)?,
+            mode: permissions(path)?,
+        },
+    };
+    exec.run(action)?;
+
+    Ok(stored)
 }
 
-/// Dotfile is removed from set dotfiles directory and moved to its symlink location.
-/// The input can either be a dotfile's symlink path or the path of the dotfile itself.
+/// Dotfile is removed from dotfiles directory `dots_dir` and moved to its symlink
+/// location. The input can either be a dotfile's symlink path or the path of the dotfile
+/// itself. A folded directory is restored as a whole.
 ///
-/// Returns destination path.
-pub fn restore_dotfile(path: PathBuf) -> io::Result<PathBuf> {
+/// Returns destination path. Filesystem changes are applied through `exec`, so no files
+/// are touched in dry-run mode.
+pub fn restore_dotfile(
+    path: PathBuf,
+    dots_dir: &Path,
+    exec: &mut Executor,
+) -> Result<PathBuf> {
     // get src and dst paths
     let (src_path, dst_path): (PathBuf, PathBuf) = if is_symlink(&path) {
-        (fs::read_link(&path)?, path)
+        (resolve_link(&path)?, path)
     } else {
-        let restore_path = path.strip_prefix(Config::get_dots_dir().unwrap()).unwrap();
-        let dst_path = PathBuf::from("/").join(restore_path);
+        let dst_path = deploy_path(dots_dir, &path)
+            .map_err(|_| BadmError::OutsideRepo { path: path.clone() })?;
 
         (path, dst_path)
     };
 
-    let is_dotfile = |path: &Path| -> bool {
-        crate::Config::get_dots_dir()
-            .map(|dir| path.starts_with(dir))
-            .unwrap()
-    };
-
     // check to see if src path exists in dotfiles directory, if not: it is invalid input
-    if !is_dotfile(&src_path) {
-        // throw error
-        let err = Error::new(
-            ErrorKind::InvalidInput,
-            "input path not located in dotfiles dir!",
-        );
-        return Err(err);
+    if !src_path.starts_with(dots_dir) {
+        return Err(BadmError::OutsideRepo { path: src_path });
     };
 
-    if dst_path.exists() {
-        fs::remove_file(&dst_path)?;
+    if dst_path.exists() || is_symlink(&dst_path) {
+        exec.run(Action::Remove {
+            path: dst_path.clone(),
+        })?;
     };
 
-    FileHandler::move_file(&src_path, &dst_path)?;
+    exec.run(Action::Move {
+        src: src_path.clone(),
+        dst: dst_path.clone(),
+    })?;
+
+    update_manifest(dots_dir, exec, |manifest| {
+        if let Ok(stored) = src_path.strip_prefix(dots_dir) {
+            let _ = manifest.remove(stored);
+        };
+        Ok(())
+    })?;
 
     Ok(dst_path)
 }
@@ -114,17 +251,787 @@ pub fn restore_dotfile(path: PathBuf) -> io::Result<PathBuf> {
 ///
 /// Directories to replicate the stored dotfile's directory structure will be created if
 /// not found.
+///
+/// The symlink points to `src` by absolute or relative path, depending on
+/// `options.link_style`. An existing symlink to `src` written in the other style is
+/// replaced.
+///
+/// If `dst` is already taken by another file, directory, or symlink,
+/// `options.on_conflict` decides whether it is skipped, backed up, overwritten, or
+/// adopted into the dotfiles directory in place of the stored dotfile. Returns `false` if
+/// the dotfile was not deployed because the conflict was skipped.
+///
+/// With `options.mode` set to [`DeployMode::Copy`], the dotfile is copied to `dst`
+/// instead, see [`deploy_copy`]. With [`DeployMode::Hard`], `dst` is made a hard link to
+/// `src`, which has to be a file located on the same filesystem as `dst`, otherwise
+/// [`BadmError::CrossDevice`] is returned before anything is changed. A file at `dst`
+/// with the same content as `src`, e.g. one whose hard link was broken by a program
+/// replacing it, is relinked without applying `options.on_conflict`.
+///
+/// An unmodified copy or a hard link deployed earlier is replaced without applying
+/// `options.on_conflict` as well.
+///
+/// `src` has to be located in the dotfiles directory `dots_dir`. Filesystem changes are
+/// applied through `exec`, so no files are touched in dry-run mode.
+///
+/// [`DeployMode::Copy`]: ../paths/enum.DeployMode.html#variant.Copy
+/// [`DeployMode::Hard`]: ../paths/enum.DeployMode.html#variant.Hard
+/// [`BadmError::CrossDevice`]: ../errors/enum.BadmError.html#variant.CrossDevice
+/// [`deploy_copy`]: fn.deploy_copy.html
 // REVIEW: not enough checks - need to ensure valid entry.
-pub fn deploy_dotfile(src: &Path, dst: &Path) -> io::Result<()> {
-    // if symlink already exists and points to src file, early return
-    if dst.exists() && fs::read_link(&dst)? == src {
-        return Ok(());
+pub fn deploy_dotfile(
+    src: &Path,
+    dst: &Path,
+    dots_dir: &Path,
+    options: DeployOptions,
+    exec: &mut Executor,
+) -> Result<bool> {
+    let is_hard = match options.mode {
+        DeployMode::Symlink => false,
+        DeployMode::Copy => return deploy_copy(src, dst, dots_dir, options, exec),
+        DeployMode::Hard => true,
     };
 
-    let dst_dir = dst.parent().unwrap();
-    if !dst_dir.exists() {
-        fs::create_dir_all(dst_dir)?;
+    let stored = stored_path(src, dots_dir)?;
+
+    if is_hard && (!src.is_file() || is_symlink(src)) {
+        return Err(BadmError::invalid(
+            src,
+            "is not a file, only files can be hard linked",
+        ));
+    };
+    if is_hard && !is_same_device(src, dst) {
+        return Err(BadmError::CrossDevice {
+            path: dst.to_path_buf(),
+        });
+    };
+
+    let link = options.link_style.link_target(src, dst);
+    let mut backup = None;
+    let mut adopted = false;
+
+    // if dst is already linked to src, only record deployment
+    let is_linked = if is_hard {
+        is_hard_linked(src, dst)
+    } else {
+        is_symlink(dst) && fs::read_link(dst).at_path(dst)? == link
     };
 
-    FileHandler::create_symlink(&src, &dst)
+    if !is_linked {
+        let is_copy = last_deploy(dots_dir, &stored, dst, LinkMode::Copy)?.is_some();
+        // a file with the content of src holds nothing that could be lost
+        let is_stale_link = is_hard
+            && dst.is_file()
+            && !is_symlink(dst)
+            && hash_file(dst)? == hash_file(src)?;
+
+        if (is_symlink(dst) && is_same_path(&resolve_link(dst)?, src))
+            || is_hard_linked(src, dst)
+            || is_copy
+            || is_stale_link
+        {
+            exec.run(Action::Remove {
+                path: dst.to_path_buf(),
+            })?;
+        } else if dst.exists() || is_symlink(dst) {
+            let policy = options.on_conflict.resolve(dst)?;
+
+            match clear_conflict(src, dst, policy, exec)? {
+                Cleared::Skipped => return Ok(false),
+                Cleared::BackedUp(backup_path) => backup = Some(backup_path),
+                Cleared::Removed => {},
+                Cleared::Adopted => adopted = true,
+            }
+        };
+
+        create_parent_dir(dst, exec)?;
+
+        if is_hard {
+            exec.run(Action::HardLink {
+                src: src.to_path_buf(),
+                dst: dst.to_path_buf(),
+            })?;
+        } else {
+            exec.run(Action::Symlink {
+                src: link,
+                dst: dst.to_path_buf(),
+            })?;
+        };
+    };
+
+    let mode = if is_hard {
+        LinkMode::Hard
+    } else {
+        LinkMode::Symlink
+    };
+
+    update_manifest(dots_dir, exec, |manifest| {
+        match manifest.get_mut(&stored) {
+            Some(dotfile) => {
+                dotfile.source = dst.to_path_buf();
+                dotfile.mode = mode;
+                dotfile.deployed_at = Some(timestamp());
+                dotfile.deployed_hash = None;
+
+                if adopted {
+                    dotfile.hash = hash_path(src)?;
+                    dotfile.folded = src.is_dir();
+                };
+                if backup.is_some() {
+                    dotfile.backup = backup;
+                };
+            },
+            None => {
+                let mut dotfile = Dotfile::new(dst, src, dots_dir)?;
+                dotfile.mode = mode;
+                dotfile.deployed_at = Some(timestamp());
+                dotfile.backup = backup;
+                manifest.insert(dotfile);
+            },
+        };
+        Ok(())
+    })?;
+
+    Ok(true)
+}
+
+/// Render the template stored at `src` with `variables` and write the result to `dst`,
+/// see [`template`].
+///
+/// A file at `dst` is only replaced without applying `options.on_conflict` if it was
+/// written by the last render of the template and has not been modified since. Conflicts
+/// can not be adopted, as that would replace the template with rendered content. Returns
+/// `false` if the template was not rendered because the conflict was skipped.
+///
+/// `src` has to be located in the dotfiles directory `dots_dir`. Filesystem changes are
+/// applied through `exec`, so no files are touched in dry-run mode.
+///
+/// [`template`]: ../template/index.html
+pub fn deploy_template(
+    src: &Path,
+    dst: &Path,
+    dots_dir: &Path,
+    variables: &Variables,
+    options: DeployOptions,
+    exec: &mut Executor,
+) -> Result<bool> {
+    let contents = render(src, &read_path(src)?, variables)?;
+
+    let generated = Generated {
+        contents: contents.into_bytes(),
+        mode: LinkMode::Template,
+        permissions: None,
+    };
+    deploy_generated(src, dst, dots_dir, generated, options, exec)
+}
+
+/// Encrypt the file at `path` with `secret` and store it in the dotfiles directory
+/// `dots_dir`, at the path given by `options.layout` with `.enc` appended, see
+/// [`crypto`].
+///
+/// The plaintext file is left at `path`, but made readable only by its owner, and is
+/// recorded as deployed. Returns the path of the encrypted file. Filesystem changes are
+/// applied through `exec`, so no files are touched in dry-run mode.
+///
+/// [`crypto`]: ../crypto/index.html
+pub fn store_encrypted(
+    path: &Path,
+    dots_dir: &Path,
+    secret: &Secret,
+    options: StoreOptions,
+    exec: &mut Executor,
+) -> Result<PathBuf> {
+    if !path.is_file() {
+        return Err(BadmError::invalid(
+            path,
+            "is not a file, only files can be encrypted",
+        ));
+    };
+
+    let dst_path = encrypted_path(&options.layout.stored_path(dots_dir, path));
+    let plaintext = fs::read(path).at_path(path)?;
+
+    create_parent_dir(&dst_path, exec)?;
+
+    exec.run(Action::Write {
+        path: dst_path.clone(),
+        contents: encrypt(path, &plaintext, secret)?,
+        mode: None,
+    })?;
+    // the plaintext is not rewritten, so no copy of it is preserved in the journal
+    exec.run(Action::SetPermissions {
+        path: path.to_path_buf(),
+        mode: DECRYPTED_MODE,
+    })?;
+
+    update_manifest(dots_dir, exec, |manifest| {
+        let mut dotfile = Dotfile::new(path, &dst_path, dots_dir)?;
+        dotfile.mode = LinkMode::Encrypted;
+        dotfile.deployed_at = Some(timestamp());
+        dotfile.deployed_hash = Some(hash_bytes(&plaintext));
+
+        manifest.insert(dotfile);
+        Ok(())
+    })?;
+
+    Ok(dst_path)
+}
+
+/// Decrypt the dotfile stored encrypted at `src` with `secret` and write it to `dst`,
+/// readable only by its owner, see [`crypto`].
+///
+/// Conflicts are handled as by [`deploy_template`], with a file at `dst` only replaced
+/// without applying `options.on_conflict` if it was written by the last deploy and has
+/// not been modified since. Returns `false` if the dotfile was not decrypted because the
+/// conflict was skipped.
+///
+/// [`crypto`]: ../crypto/index.html
+/// [`deploy_template`]: fn.deploy_template.html
+pub fn deploy_encrypted(
+    src: &Path,
+    dst: &Path,
+    dots_dir: &Path,
+    secret: &Secret,
+    options: DeployOptions,
+    exec: &mut Executor,
+) -> Result<bool> {
+    let contents = decrypt(src, &fs::read(src).at_path(src)?, secret)?;
+
+    let generated = Generated {
+        contents,
+        mode: LinkMode::Encrypted,
+        permissions: Some(DECRYPTED_MODE),
+    };
+    deploy_generated(src, dst, dots_dir, generated, options, exec)
+}
+
+/// Copy the dotfile stored at `src` to `dst`, keeping its permissions, for programs that
+/// replace their config files or do not follow symlinks. Only files can be copied.
+///
+/// Conflicts are handled as by [`deploy_template`], with a file at `dst` only replaced
+/// without applying `options.on_conflict` if it was written by the last deploy and has
+/// not been modified since, or if `options.force` is set and it was. A conflict adopted
+/// into the dotfiles directory replaces the stored dotfile, and is recorded as deployed.
+/// Returns `false` if the dotfile was not copied because the conflict was skipped.
+///
+/// Changes made to the copy can be copied back with [`adopt_copy`].
+///
+/// [`deploy_template`]: fn.deploy_template.html
+/// [`adopt_copy`]: fn.adopt_copy.html
+pub fn deploy_copy(
+    src: &Path,
+    dst: &Path,
+    dots_dir: &Path,
+    options: DeployOptions,
+    exec: &mut Executor,
+) -> Result<bool> {
+    if !src.is_file() {
+        return Err(BadmError::invalid(
+            src,
+            "is not a file, only files can be deployed as copies",
+        ));
+    };
+
+    let generated = Generated {
+        contents: fs::read(src).at_path(src)?,
+        mode: LinkMode::Copy,
+        permissions: permissions(src)?,
+    };
+    deploy_generated(src, dst, dots_dir, generated, options, exec)
+}
+
+/// Copy the changes made to the copy of a dotfile deployed at `dst` back to the dotfile
+/// stored in `dots_dir`, the inverse of [`deploy_copy`].
+///
+/// Returns `false` if the copy has not been modified since it was deployed. Fails if the
+/// stored dotfile was changed since as well, unless `force` is set, in which case those
+/// changes are discarded.
+///
+/// Filesystem changes are applied through `exec`, so no files are touched in dry-run
+/// mode.
+///
+/// [`deploy_copy`]: fn.deploy_copy.html
+pub fn adopt_copy(
+    dst: &Path,
+    dots_dir: &Path,
+    force: bool,
+    exec: &mut Executor,
+) -> Result<bool> {
+    let record = Manifest::load(dots_dir)?
+        .dotfiles
+        .into_iter()
+        .find(|dotfile| {
+            dotfile.source == dst
+                && dotfile.mode == LinkMode::Copy
+                && dotfile.deployed_hash.is_some()
+        })
+        .ok_or_else(|| BadmError::invalid(dst, "is not deployed as a copy"))?;
+    let src = dots_dir.join(&record.stored);
+
+    if is_symlink(dst) || !dst.is_file() {
+        return Err(BadmError::Conflict {
+            path: dst.to_path_buf(),
+            message: format!("is no longer a copy of {:?}", src),
+        });
+    };
+
+    let contents = fs::read(dst).at_path(dst)?;
+    let hash = hash_bytes(&contents);
+
+    if record.deployed_hash.as_ref() == Some(&hash) {
+        return Ok(false);
+    };
+
+    if !force && src.exists() && hash_file(&src)? != record.hash {
+        return Err(BadmError::Conflict {
+            path: src,
+            message: format!(
+                "was changed since {:?} was deployed, use --force to replace it",
+                dst
+            ),
+        });
+    };
+
+    exec.run(Action::Write {
+        path: src,
+        contents,
+        mode: permissions(dst)?,
+    })?;
+
+    update_manifest(dots_dir, exec, |manifest| {
+        if let Some(dotfile) = manifest.get_mut(&record.stored) {
+            dotfile.hash = hash.clone();
+            dotfile.deployed_hash = Some(hash);
+        };
+        Ok(())
+    })?;
+
+    Ok(true)
+}
+
+/// Contents generated from a stored dotfile, which are written to its target path
+/// instead of a symlink.
+struct Generated {
+    contents: Vec<u8>,
+    mode: LinkMode,
+    permissions: Option<u32>,
+}
+
+fn deploy_generated(
+    src: &Path,
+    dst: &Path,
+    dots_dir: &Path,
+    generated: Generated,
+    options: DeployOptions,
+    exec: &mut Executor,
+) -> Result<bool> {
+    let Generated {
+        mut contents,
+        mode,
+        permissions,
+    } = generated;
+    let stored = stored_path(src, dots_dir)?;
+
+    let last_deploy = last_deploy(dots_dir, &stored, dst, mode)?;
+    let is_forced = last_deploy.is_none()
+        && options.force
+        && Manifest::load(dots_dir)?
+            .get(&stored)
+            .map(|dotfile| {
+                dotfile.mode == mode
+                    && dotfile.source == dst
+                    && dotfile.deployed_hash.is_some()
+            })
+            .unwrap_or(false)
+        && dst.is_file()
+        && !is_symlink(dst);
+
+    let mut backup = None;
+
+    if last_deploy.as_ref() != Some(&hash_bytes(&contents)) {
+        // an unmodified previous deploy, or a modified one if forced, is rewritten
+        // without further checks
+        if last_deploy.is_none() && !is_forced {
+            if (is_symlink(dst) && is_same_path(&resolve_link(dst)?, src))
+                || is_hard_linked(src, dst)
+            {
+                exec.run(Action::Remove {
+                    path: dst.to_path_buf(),
+                })?;
+            } else if dst.exists() || is_symlink(dst) {
+                let policy = options.on_conflict.resolve(dst)?;
+
+                // only a copy is deployed as it is stored
+                if policy == ConflictPolicy::Adopt {
+                    if mode != LinkMode::Copy {
+                        return Err(BadmError::Conflict {
+                            path: dst.to_path_buf(),
+                            message: format!(
+                                "can not be adopted, {:?} is not deployed as it is \
+                                 stored",
+                                src
+                            ),
+                        });
+                    };
+                    contents = fs::read(dst).at_path(dst)?;
+                };
+
+                match clear_conflict(src, dst, policy, exec)? {
+                    Cleared::Skipped => return Ok(false),
+                    Cleared::BackedUp(backup_path) => backup = Some(backup_path),
+                    Cleared::Removed | Cleared::Adopted => {},
+                }
+            };
+        };
+
+        create_parent_dir(dst, exec)?;
+
+        exec.run(Action::Write {
+            path: dst.to_path_buf(),
+            contents: contents.clone(),
+            mode: permissions,
+        })?;
+    };
+
+    update_manifest(dots_dir, exec, |manifest| {
+        let mut dotfile = match manifest.remove(&stored) {
+            Some(dotfile) => dotfile,
+            None => Dotfile::new(dst, src, dots_dir)?,
+        };
+
+        dotfile.source = dst.to_path_buf();
+        dotfile.mode = mode;
+        dotfile.hash = hash_file(src)?;
+        dotfile.deployed_at = Some(timestamp());
+        dotfile.deployed_hash = Some(hash_bytes(&contents));
+        if backup.is_some() {
+            dotfile.backup = backup;
+        };
+
+        manifest.insert(dotfile);
+        Ok(())
+    })?;
+
+    Ok(true)
+}
+
+/// Remove the deployed dotfile at `dst`, the inverse of [`deploy_dotfile`]. The stored
+/// dotfile in `dots_dir` is left untouched, and stays recorded in the manifest as not
+/// deployed.
+///
+/// Only a symlink pointing into `dots_dir`, or a hard link to a dotfile stored in it, is
+/// removed. A rendered template, decrypted file, or copy is only removed if it has not
+/// been modified since it was written. If
+/// `options.restore_backup` is set, the file backed up when the dotfile was deployed is
+/// moved back to `dst`. Returns `false` if `dst` was not deployed from `dots_dir`.
+///
+/// Filesystem changes are applied through `exec`, so no files are touched in dry-run
+/// mode.
+pub fn undeploy_dotfile(
+    dst: &Path,
+    dots_dir: &Path,
+    options: UndeployOptions,
+    exec: &mut Executor,
+) -> Result<bool> {
+    let manifest = Manifest::load(dots_dir)?;
+
+    let record = if is_symlink(dst) {
+        let src = resolve_link(dst)?;
+        if !src.starts_with(dots_dir) {
+            return Ok(false);
+        };
+
+        let stored = stored_path(&src, dots_dir)?;
+        manifest.get(&stored).cloned()
+    } else {
+        let record = manifest
+            .dotfiles
+            .iter()
+            .find(|dotfile| dotfile.source == dst && dotfile.mode != LinkMode::Symlink);
+
+        let unmodified = record
+            .map(|dotfile| {
+                if dotfile.mode == LinkMode::Hard {
+                    return is_hard_linked(&dots_dir.join(&dotfile.stored), dst);
+                };
+
+                dotfile
+                    .deployed_hash
+                    .as_ref()
+                    .map(|hash| {
+                        dst.is_file() && hash_file(dst).ok().as_ref() == Some(hash)
+                    })
+                    .unwrap_or(false)
+            })
+            .unwrap_or(false);
+        if !unmodified {
+            return Ok(false);
+        };
+        record.cloned()
+    };
+
+    exec.run(Action::Remove {
+        path: dst.to_path_buf(),
+    })?;
+
+    let backup = record
+        .as_ref()
+        .and_then(|dotfile| dotfile.backup.clone())
+        .filter(|backup| {
+            options.restore_backup && (backup.exists() || is_symlink(backup))
+        });
+    if let Some(backup) = &backup {
+        exec.run(Action::Move {
+            src: backup.clone(),
+            dst: dst.to_path_buf(),
+        })?;
+    };
+
+    if let Some(record) = record {
+        update_manifest(dots_dir, exec, |manifest| {
+            if let Some(dotfile) = manifest.get_mut(&record.stored) {
+                dotfile.deployed_at = None;
+                dotfile.deployed_hash = None;
+                if backup.is_some() {
+                    dotfile.backup = None;
+                };
+            };
+            Ok(())
+        })?;
+    };
+
+    Ok(true)
+}
+
+/// Commit the changes `actions` made to the dotfiles directory `dots_dir`, which has to
+/// be a git repository, together with its manifest. The commit message lists the
+/// affected dotfiles, see [`commit_message`]. Returns `false` if none of the actions
+/// changed the dotfiles directory.
+///
+/// [`commit_message`]: ../git/fn.commit_message.html
+pub fn commit_changes(
+    dots_dir: &Path,
+    command: &str,
+    actions: &[Action],
+) -> Result<bool> {
+    // created directories are left out, as they may hold unrelated files
+    let mut paths: Vec<PathBuf> = actions
+        .iter()
+        .flat_map(|action| match action {
+            Action::CreateDir { .. } => vec![],
+            Action::Move { src, dst } => vec![src, dst],
+            Action::Symlink { dst, .. } | Action::HardLink { dst, .. } => vec![dst],
+            Action::Remove { path }
+            | Action::Write { path, .. }
+            | Action::SetPermissions { path, .. } => vec![path],
+        })
+        .filter_map(|path| path.strip_prefix(dots_dir).ok())
+        .filter(|path| *path != Path::new(""))
+        .map(Path::to_path_buf)
+        .collect();
+    paths.sort();
+    paths.dedup();
+
+    if paths.is_empty() {
+        return Ok(false);
+    };
+
+    let git = Git::new(dots_dir);
+    if !git.is_repo() {
+        return Err(BadmError::invalid(dots_dir, "is not a git repository"));
+    };
+
+    let message = commit_message(command, &paths);
+    paths.push(PathBuf::from(MANIFEST_FILE_NAME));
+
+    git.commit(&message, &paths)
+}
+
+/// Set up the dotfiles directory `dots_dir` of a new machine from `source`. A local
+/// directory that is not a git repository is copied, anything else is cloned with git.
+///
+/// Fails if `dots_dir` already exists and is not empty. Returns `true` if `source` was
+/// cloned.
+pub fn init_dots_dir(source: &str, dots_dir: &Path) -> Result<bool> {
+    let is_empty = dots_dir
+        .read_dir()
+        .map(|mut entries| entries.next().is_none())
+        .unwrap_or(!dots_dir.exists());
+
+    if !is_empty {
+        return Err(BadmError::Conflict {
+            path: dots_dir.to_path_buf(),
+            message: "already exists and is not empty".to_string(),
+        });
+    };
+
+    let source_dir = Path::new(source);
+    let is_plain_dir = source_dir.is_dir()
+        && !Git::new(source_dir).is_repo()
+        && !source_dir.join("HEAD").is_file();
+
+    if is_plain_dir {
+        FileHandler::copy_dir(source_dir, dots_dir)?;
+        Ok(false)
+    } else {
+        let _ = Git::clone(source, dots_dir)?;
+        Ok(true)
+    }
+}
+
+/// Run the bootstrap script `.badm-bootstrap` at the top of the dotfiles directory
+/// `dots_dir`, e.g. to install packages a newly deployed configuration depends on. The
+/// script is run inside `dots_dir` and has to be executable. Its standard output is
+/// passed to standard error, like that of hooks.
+///
+/// Returns `false` if there is no bootstrap script. Fails if the script exits
+/// unsuccessfully.
+pub fn run_bootstrap(dots_dir: &Path) -> Result<bool> {
+    let script = dots_dir.join(BOOTSTRAP_FILE_NAME);
+
+    if !script.is_file() {
+        return Ok(false);
+    };
+
+    let status = Command::new(&script)
+        .current_dir(dots_dir)
+        .stdout(io::stderr())
+        .status()
+        .at_path(&script)?;
+
+    if !status.success() {
+        return Err(BadmError::invalid(
+            &script,
+            format!("failed with {}", status),
+        ));
+    };
+    Ok(true)
+}
+
+/// Get the hash of the content written to `dst` by the last deploy in `mode` of the
+/// dotfile stored at `stored` in `dots_dir`, if `dst` has not been modified since.
+fn last_deploy(
+    dots_dir: &Path,
+    stored: &Path,
+    dst: &Path,
+    mode: LinkMode,
+) -> Result<Option<String>> {
+    Ok(Manifest::load(dots_dir)?
+        .get(stored)
+        .filter(|dotfile| dotfile.mode == mode && dotfile.source == dst)
+        .and_then(|dotfile| dotfile.deployed_hash.clone())
+        .filter(|hash| {
+            dst.is_file()
+                && !is_symlink(dst)
+                && hash_file(dst).ok().as_ref() == Some(hash)
+        }))
+}
+
+/// Get the Unix permission bits of the file at `path`. Always `None` on other platforms,
+/// which have no such bits to preserve.
+fn permissions(path: &Path) -> Result<Option<u32>> {
+    let metadata = fs::metadata(path).at_path(path)?;
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+
+        Ok(Some(metadata.permissions().mode() & 0o7777))
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = metadata;
+        Ok(None)
+    }
+}
+
+/// Get the path of `src` relative to the dotfiles directory `dots_dir`.
+fn stored_path(src: &Path, dots_dir: &Path) -> Result<PathBuf> {
+    src.strip_prefix(dots_dir)
+        .map(Path::to_path_buf)
+        .map_err(|_| BadmError::OutsideRepo {
+            path: src.to_path_buf(),
+        })
+}
+
+fn create_parent_dir(path: &Path, exec: &mut Executor) -> Result<()> {
+    let dir = path.parent().unwrap();
+
+    if !dir.exists() {
+        exec.run(Action::CreateDir {
+            path: dir.to_path_buf(),
+        })?;
+    };
+    Ok(())
+}
+
+/// How a taken target path was cleared by [`clear_conflict`].
+///
+/// [`clear_conflict`]: fn.clear_conflict.html
+enum Cleared {
+    Skipped,
+    BackedUp(PathBuf),
+    Removed,
+    Adopted,
+}
+
+/// Clear the taken target path `dst` of the dotfile stored at `src` according to
+/// `policy`, which has to be resolved already.
+fn clear_conflict(
+    src: &Path,
+    dst: &Path,
+    policy: ConflictPolicy,
+    exec: &mut Executor,
+) -> Result<Cleared> {
+    match policy {
+        ConflictPolicy::Skip | ConflictPolicy::Prompt => Ok(Cleared::Skipped),
+        ConflictPolicy::Backup => {
+            let backup_path = backup_path(dst);
+            exec.run(Action::Move {
+                src: dst.to_path_buf(),
+                dst: backup_path.clone(),
+            })?;
+            Ok(Cleared::BackedUp(backup_path))
+        },
+        ConflictPolicy::Overwrite => {
+            exec.run(Action::Remove {
+                path: dst.to_path_buf(),
+            })?;
+            Ok(Cleared::Removed)
+        },
+        ConflictPolicy::Adopt => {
+            if is_symlink(dst) {
+                return Err(BadmError::Conflict {
+                    path: dst.to_path_buf(),
+                    message: "is a symlink and can not be adopted".to_string(),
+                });
+            };
+
+            exec.run(Action::Remove {
+                path: src.to_path_buf(),
+            })?;
+            exec.run(Action::Move {
+                src: dst.to_path_buf(),
+                dst: src.to_path_buf(),
+            })?;
+            Ok(Cleared::Adopted)
+        },
+    }
+}
+
+/// Apply `update` to the manifest of `dots_dir` and save it. The manifest is preserved in
+/// `exec`'s journal before being rewritten, and left untouched in dry-run mode.
+fn update_manifest<F>(dots_dir: &Path, exec: &mut Executor, update: F) -> Result<()>
+where
+    F: FnOnce(&mut Manifest) -> Result<()>,
+{
+    if exec.is_dry_run() {
+        return Ok(());
+    };
+
+    let mut manifest = Manifest::load(dots_dir)?;
+    update(&mut manifest)?;
+
+    exec.preserve(&dots_dir.join(MANIFEST_FILE_NAME))?;
+    manifest.save(dots_dir)
 }
@@ -1,118 +1,619 @@
+use std::collections::BTreeMap;
 use std::convert::TryFrom;
+use std::env;
 use std::fs::{self, File};
-use std::io;
 use std::io::prelude::*;
 use std::path::{Path, PathBuf};
 use std::str::FromStr;
 
-use crate::errors::InputError;
+use crate::errors::{BadmError, PathContext, Result};
+use crate::hooks::{expand_home, Hook};
+use crate::paths::{DeployMode, Layout, LinkStyle};
 use dirs::{config_dir, home_dir};
+use glob::Pattern;
 use serde_derive::{Deserialize, Serialize};
+use toml::value::{Table, Value};
+
+/// Name of the repository stored at [`Config::directory`].
+///
+/// [`Config::directory`]: struct.Config.html#structfield.directory
+pub const DEFAULT_REPO: &str = "default";
+
+/// Environment variable selecting the user config file.
+pub const CONFIG_FILE_VAR: &str = "BADM_CONFIG";
+
+/// Name of the repo-local config file, located at the top of the default dotfiles
+/// directory.
+pub const REPO_CONFIG_FILE_NAME: &str = "badm.toml";
+
+/// Settings that can be overridden by an environment variable named after them, e.g.
+/// `BADM_LINK_STYLE` for `link_style`.
+pub const ENV_SETTINGS: [&str; 4] = ["directory", "link_style", "layout", "key_file"];
 
 /// Handles and saves configuration variables between application calls.
-#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+///
+/// Besides the default dotfiles directory, any number of named dotfiles directories can
+/// be configured:
+///
+/// ```toml
+/// directory = "/home/ferris/.dots"
+/// link_style = "relative"
+/// layout = "home"
+/// key_file = "/home/ferris/.config/badm/key"
+/// target_roots = ["/home/ferris", "/etc/xdg"]
+///
+/// [[repo]]
+/// name = "team-shared"
+/// directory = "/home/ferris/.team-dots"
+/// priority = -10
+///
+/// [[repo]]
+/// name = "personal"
+/// directory = "/home/ferris/.personal-dots"
+/// priority = 10
+/// ```
+///
+/// Variables used to render templated dotfiles, see [`template`], are set globally and
+/// per host name:
+///
+/// ```toml
+/// [variables]
+/// email = "ferris@example.com"
+///
+/// [host.build01]
+/// email = "ferris@work.example.com"
+/// ```
+///
+/// Settings are read from several layers, each overriding the ones before it:
+///
+/// 1. the repo-local config file `badm.toml` at the top of the default dotfiles
+///    directory, which is shared with everyone using the directory and can set anything
+///    but the [`USER_ONLY_SETTINGS`]
+/// 2. the user config file, see [`Config::user_config_file`]
+/// 3. environment variables overriding the settings listed in [`ENV_SETTINGS`]
+///
+/// Tables, such as `variables`, are merged key by key. Any other setting replaces the one
+/// of an earlier layer.
+///
+/// [`template`]: template/index.html
+/// [`Config::user_config_file`]: struct.Config.html#method.user_config_file
+/// [`ENV_SETTINGS`]: constant.ENV_SETTINGS.html
+/// [`USER_ONLY_SETTINGS`]: constant.USER_ONLY_SETTINGS.html
+#[derive(Serialize, Deserialize, Debug, Default, PartialEq, Clone)]
 pub struct Config {
     /// Path of dotfiles directory.
+    #[serde(default)]
     pub directory: PathBuf,
+    /// Additional named dotfiles directories.
+    #[serde(default, rename = "repo", skip_serializing_if = "Vec::is_empty")]
+    pub repos: Vec<Repo>,
+    /// Whether deployed symlinks point to stored dotfiles by absolute or relative path.
+    /// Can be overridden per command with `--link-style`.
+    #[serde(default, skip_serializing_if = "LinkStyle::is_absolute")]
+    pub link_style: LinkStyle,
+    /// How newly stored dotfiles are laid out inside the dotfiles directory. Can be
+    /// overridden per command with `--layout`.
+    #[serde(default, skip_serializing_if = "Layout::is_root")]
+    pub layout: Layout,
+    /// Variables available to all templated dotfiles.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub variables: BTreeMap<String, String>,
+    /// Variables available to templated dotfiles on a single host, keyed by host name.
+    /// They take precedence over [`Config::variables`].
+    ///
+    /// [`Config::variables`]: struct.Config.html#structfield.variables
+    #[serde(default, rename = "host", skip_serializing_if = "BTreeMap::is_empty")]
+    pub hosts: BTreeMap<String, BTreeMap<String, String>>,
+    /// Key file used to encrypt and decrypt encrypted dotfiles, see [`crypto`]. Can be
+    /// overridden per command with `--key-file`; without one, a passphrase is used.
+    ///
+    /// [`crypto`]: crypto/index.html
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub key_file: Option<PathBuf>,
+    /// Directories `badm doctor` scans for symlinks into the dotfiles directories, see
+    /// [`doctor`]. Defaults to the home directory.
+    ///
+    /// [`doctor`]: doctor/index.html
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub target_roots: Vec<PathBuf>,
+    /// Shell commands run before and after `stow`, `deploy`, and `restore`, see
+    /// [`hooks`].
+    ///
+    /// [`hooks`]: hooks/index.html
+    #[serde(default, rename = "hook", skip_serializing_if = "Vec::is_empty")]
+    pub hooks: Vec<Hook>,
+
+    /// Deploy modes of the dotfiles matching a set of globs, see [`ModeRule`].
+    ///
+    /// [`ModeRule`]: struct.ModeRule.html
+    #[serde(default, rename = "mode", skip_serializing_if = "Vec::is_empty")]
+    pub modes: Vec<ModeRule>,
+    /// How dotfiles to be hard linked are deployed instead if their target path is
+    /// located on another filesystem than the dotfiles directory: as a symlink, or as a
+    /// copy.
+    #[serde(default, skip_serializing_if = "DeployMode::is_symlink")]
+    pub hard_link_fallback: DeployMode,
 }
 
-impl Config {
-    pub(crate) fn new<P: AsRef<Path>>(directory: P) -> Result<Self, InputError> {
-        let directory = directory.as_ref().to_path_buf();
+/// Deploy mode of the dotfiles whose target paths match any of a set of globs, such as
+/// the settings of a program that replaces its config file instead of writing through
+/// the symlink, or of one that handles hard links better than symlinks:
+///
+/// ```toml
+/// [[mode]]
+/// mode = "copy"
+/// paths = ["~/.config/Code/User/settings.json"]
+///
+/// [[mode]]
+/// mode = "hard"
+/// paths = ["~/.var/app/**"]
+/// ```
+///
+/// A target path is selected if it, or one of its parent directories, matches one of the
+/// globs; a leading `~` stands for the home directory. If several rules select a path,
+/// the one listed last wins.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+pub struct ModeRule {
+    /// How the selected dotfiles are deployed.
+    pub mode: DeployMode,
+    /// Globs selecting target paths.
+    pub paths: Vec<String>,
+}
 
-        if directory.is_dir() {
-            Ok(Self { directory })
-        } else {
-            Err(InputError::BadInput {
-                err: io::Error::new(
-                    io::ErrorKind::InvalidInput,
-                    "Input to set dots directory is invalid",
-                ),
-            })
-        }
+impl ModeRule {
+    /// Returns `true` if the rule selects the target path `target`.
+    pub fn matches(&self, target: &Path) -> bool {
+        let patterns: Vec<Pattern> = self
+            .paths
+            .iter()
+            .filter_map(|glob| Pattern::new(&expand_home(glob)).ok())
+            .collect();
+
+        target
+            .ancestors()
+            .any(|path| patterns.iter().any(|pattern| pattern.matches_path(path)))
     }
+}
+
+/// A named dotfiles directory.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+pub struct Repo {
+    /// Name used to select the repository with `--repo <NAME>`.
+    pub name: String,
+    /// Path of the repository's dotfiles directory.
+    pub directory: PathBuf,
+    /// When several repositories store a dotfile for the same path, the dotfile from the
+    /// repository with the highest priority is deployed. On equal priorities the
+    /// repository listed last wins. The default directory has a priority of `0`.
+    #[serde(default)]
+    pub priority: i64,
+}
 
+impl Config {
     // REVIEW: how should we handle if a dotfiles directory is already set?
-    /// Sets arg `path` at dotfiles directory, and writes TOML config file.
+    /// Sets arg `path` at dotfiles directory, and writes it to the user config file.
     ///
     /// If path is not available it will try to be created.
-    pub fn set_dots_dir<P: AsRef<Path>>(path: P) -> Result<PathBuf, InputError> {
+    pub fn set_dots_dir<P: AsRef<Path>>(path: P) -> Result<PathBuf> {
         let path = path.as_ref();
 
         if !path.exists() {
-            fs::create_dir_all(path).expect("could not create path");
+            fs::create_dir_all(path).at_path(path)?;
         } else if !path.is_dir() {
-            return Err(InputError::BadInput {
-                err: io::Error::new(
-                    io::ErrorKind::InvalidInput,
-                    "Input to set dots directory is invalid",
-                ),
-            });
+            return Err(BadmError::invalid(
+                path,
+                "Input to set dots directory is invalid",
+            ));
         };
 
-        let config = Self::new(&path)?;
+        let config_file = Self::user_config_file()?;
+        let mut settings = read_settings(&config_file)?;
+        let _ = settings.insert(
+            "directory".to_string(),
+            Value::String(path.to_string_lossy().into_owned()),
+        );
 
-        config
-            .write_toml_config()
-            .expect("could not write toml config");
+        Self::write_settings(&config_file, settings)?;
         Ok(path.to_path_buf())
     }
 
-    /// If config file `.badm.toml` exists, get dotfiles directory path.
-    pub fn get_dots_dir() -> Option<PathBuf> {
-        if let Some(config_path) = Self::get_config_file() {
-            let toml = crate::paths::read_path(&config_path).unwrap();
+    /// Sets arg `path` as the directory of the repository `name`, and writes it to the
+    /// user config file. The repository is added if it is not configured yet.
+    ///
+    /// A default dotfiles directory has to be set before named repositories can be added.
+    /// If path is not available it will try to be created.
+    pub fn set_repo_dir<P: AsRef<Path>>(
+        name: &str,
+        path: P,
+        priority: i64,
+    ) -> Result<PathBuf> {
+        if name == DEFAULT_REPO {
+            return Self::set_dots_dir(path);
+        };
+
+        let path = path.as_ref();
 
-            let config: Self = toml::from_str(&toml).expect("Not able to read config!");
-            Some(config.directory)
+        let _ = Self::load()?;
+
+        if !path.exists() {
+            fs::create_dir_all(path).at_path(path)?;
+        } else if !path.is_dir() {
+            return Err(BadmError::invalid(
+                path,
+                "Input to set dots directory is invalid",
+            ));
+        };
+
+        let config_file = Self::user_config_file()?;
+        let invalid = |message: String| BadmError::InvalidConfig {
+            path: config_file.clone(),
+            message,
+        };
+
+        let repo = Value::try_from(Repo {
+            name: name.to_string(),
+            directory: path.to_path_buf(),
+            priority,
+        })
+        .map_err(|err| invalid(err.to_string()))?;
+
+        let mut settings = read_settings(&config_file)?;
+        let repos = settings
+            .entry("repo")
+            .or_insert_with(|| Value::Array(vec![]))
+            .as_array_mut()
+            .ok_or_else(|| invalid("`repo` has to be a list".to_string()))?;
+
+        match repos
+            .iter_mut()
+            .find(|repo| repo.get("name").and_then(Value::as_str) == Some(name))
+        {
+            Some(existing) => *existing = repo,
+            None => repos.push(repo),
+        };
+
+        Self::write_settings(&config_file, settings)?;
+        Ok(path.to_path_buf())
+    }
+
+    /// Read the settings of all config files and environment variables, see [`Config`].
+    ///
+    /// Fails with [`BadmError::ConfigNotFound`] if no dotfiles directory is set, and with
+    /// [`BadmError::InvalidConfig`] if the settings can not be parsed.
+    ///
+    /// [`Config`]: struct.Config.html
+    /// [`BadmError::ConfigNotFound`]: enum.BadmError.html#variant.ConfigNotFound
+    /// [`BadmError::InvalidConfig`]: enum.BadmError.html#variant.InvalidConfig
+    pub fn load() -> Result<Self> {
+        let config_file = Self::user_config_file()?;
+        let config = Self::from_settings(Self::settings()?, &config_file)?;
+
+        if config.directory.as_os_str().is_empty() {
+            return Err(BadmError::ConfigNotFound { path: config_file });
+        };
+        Ok(config)
+    }
+
+    /// Get the dotfiles directory path from the configuration.
+    pub fn get_dots_dir() -> Result<PathBuf> {
+        Self::load().map(|config| config.directory)
+    }
+
+    /// Get the directory of the repository `name` from the configuration. The name
+    /// `default` refers to the default dotfiles directory.
+    pub fn get_repo_dir(name: &str) -> Result<PathBuf> {
+        Self::load()?
+            .repos()
+            .into_iter()
+            .find(|repo| repo.name == name)
+            .map(|repo| repo.directory)
+            .ok_or_else(|| {
+                BadmError::invalid(
+                    Self::user_config_file().unwrap_or_default(),
+                    format!("no repository named {:?} is configured", name),
+                )
+            })
+    }
+
+    /// Get all configured repositories, including the default dotfiles directory, ordered
+    /// from lowest to highest precedence.
+    pub fn repos(&self) -> Vec<Repo> {
+        let mut repos = vec![Repo {
+            name: DEFAULT_REPO.to_string(),
+            directory: self.directory.clone(),
+            priority: 0,
+        }];
+        repos.extend(self.repos.iter().cloned());
+
+        // stable sort keeps the order of the config file for equal priorities
+        repos.sort_by_key(|repo| repo.priority);
+        repos
+    }
+
+    /// Get the directories scanned for deployed symlinks: [`Config::target_roots`], or
+    /// the home directory if none are configured.
+    ///
+    /// [`Config::target_roots`]: struct.Config.html#structfield.target_roots
+    pub fn target_roots(&self) -> Vec<PathBuf> {
+        if self.target_roots.is_empty() {
+            home_dir().into_iter().collect()
         } else {
-            None
+            self.target_roots.clone()
         }
     }
 
-    /// Search `$HOME` and `$XDG_CONFIG_HOME` for config file path.
-    fn get_config_file() -> Option<PathBuf> {
-        let search_paths = |file_name: &str, dirs_vec: Vec<PathBuf>| -> Option<PathBuf> {
-            for dir in dirs_vec {
-                let possible_file_path = dir.join(file_name);
+    /// Get the mode the dotfile deployed to `target` is deployed in, as set by the last
+    /// of [`Config::modes`] selecting it. Dotfiles are deployed as symlinks by default.
+    ///
+    /// [`Config::modes`]: struct.Config.html#structfield.modes
+    pub fn deploy_mode(&self, target: &Path) -> DeployMode {
+        self.modes
+            .iter()
+            .rev()
+            .find(|rule| rule.matches(target))
+            .map(|rule| rule.mode)
+            .unwrap_or_default()
+    }
 
-                if possible_file_path.exists() {
-                    return Some(possible_file_path);
-                };
-            }
-            None
+    /// Get the mode dotfiles to be hard linked are deployed in if their target path is
+    /// located on another filesystem, see [`Config::hard_link_fallback`].
+    ///
+    /// [`Config::hard_link_fallback`]: struct.Config.html#structfield.hard_link_fallback
+    pub fn hard_link_fallback(&self) -> DeployMode {
+        match self.hard_link_fallback {
+            DeployMode::Hard => DeployMode::Symlink,
+            mode => mode,
+        }
+    }
+
+    /// Find the directory of the repository `path` is stored in.
+    pub fn find_repo_dir(&self, path: &Path) -> Option<PathBuf> {
+        self.repos()
+            .into_iter()
+            .map(|repo| repo.directory)
+            .filter(|dir| path.starts_with(dir))
+            .max_by_key(|dir| dir.components().count())
+    }
+
+    /// Get the merged settings of the repo-local config file, the user config file, and
+    /// environment variables, in increasing order of precedence.
+    pub fn settings() -> Result<Table> {
+        Self::layer_settings(read_settings(&Self::user_config_file()?)?)
+    }
+
+    /// Merge the settings `user` of the user config file with the other layers.
+    fn layer_settings(user: Table) -> Result<Table> {
+        let env = env_settings(env::vars());
+
+        // the repo-local config file is located in the default dotfiles directory
+        let directory = env
+            .get("directory")
+            .or_else(|| user.get("directory"))
+            .and_then(Value::as_str)
+            .map(PathBuf::from);
+        let repo = match directory {
+            Some(directory) => read_repo_settings(&directory)?,
+            None => Table::new(),
         };
 
-        let config_file_name = ".badm.toml";
-        search_paths(config_file_name, vec![
-            home_dir().unwrap(),
-            config_dir().unwrap(),
-        ])
+        Ok(merge_settings(vec![repo, user, env]))
+    }
+
+    /// Get the setting `key` from the merged [`Config::settings`]. Nested settings are
+    /// selected with dotted keys, e.g. `variables.email`.
+    ///
+    /// [`Config::settings`]: struct.Config.html#method.settings
+    pub fn get_setting(key: &str) -> Result<Option<Value>> {
+        let mut value = &Value::Table(Self::settings()?);
+
+        for name in key.split('.') {
+            value = match value.get(name) {
+                Some(value) => value,
+                None => return Ok(None),
+            };
+        }
+        Ok(Some(value.clone()))
     }
 
-    /// Save configuration variables to config file `.badm.toml`. If file cannot be found
-    /// it will be written to $HOME.
+    /// Set the setting `key`, which may be dotted as for [`Config::get_setting`], to
+    /// `value` in the user config file. The value is parsed as TOML, e.g. `10`, `true`,
+    /// or `["/home/ferris"]`, and taken as a string if it is not valid TOML.
+    ///
+    /// The file is only written if the resulting settings are valid. Returns the path of
+    /// the user config file.
     ///
-    /// Valid locations for file location include: `$HOME` and `$XDG_CONFIG_HOME`.
-    pub fn write_toml_config(self) -> io::Result<()> {
-        // check to see if config file already exists, if not default to HOME
-        let config_file_path = match Self::get_config_file() {
-            Some(path) => path,
-            None => home_dir().unwrap().join(".badm.toml"),
+    /// [`Config::get_setting`]: struct.Config.html#method.get_setting
+    pub fn set_setting(key: &str, value: &str) -> Result<PathBuf> {
+        let config_file = Self::user_config_file()?;
+        let value = toml::from_str::<Table>(&format!("value = {}", value))
+            .ok()
+            .and_then(|mut table| table.remove("value"))
+            .unwrap_or_else(|| Value::String(value.to_string()));
+
+        let mut settings = read_settings(&config_file)?;
+        let names: Vec<&str> = key.split('.').collect();
+        let (name, tables) = names.split_last().unwrap_or((&"", &[]));
+
+        let mut table = &mut settings;
+        for table_name in tables {
+            table = table
+                .entry(table_name.to_string())
+                .or_insert_with(|| Value::Table(Table::new()))
+                .as_table_mut()
+                .ok_or_else(|| BadmError::InvalidConfig {
+                    path: config_file.clone(),
+                    message: format!("`{}` is not a table", table_name),
+                })?;
+        }
+        let _ = table.insert(name.to_string(), value);
+
+        Self::write_settings(&config_file, settings)?;
+        Ok(config_file)
+    }
+
+    /// Path of the user config file: `$BADM_CONFIG` if set, otherwise the first existing
+    /// one of `$XDG_CONFIG_HOME/badm/config.toml`, `$HOME/.badm.toml`, and
+    /// `$XDG_CONFIG_HOME/.badm.toml`. If none exists, `$HOME/.badm.toml` is used.
+    pub fn user_config_file() -> Result<PathBuf> {
+        if let Some(path) = env::var_os(CONFIG_FILE_VAR) {
+            return Ok(PathBuf::from(path));
         };
 
-        let toml = toml::to_string(&self).unwrap();
-        let mut file = File::create(config_file_path)?;
+        let search_paths = [
+            config_dir().map(|dir| dir.join("badm").join("config.toml")),
+            home_dir().map(|dir| dir.join(".badm.toml")),
+            config_dir().map(|dir| dir.join(".badm.toml")),
+        ];
+
+        match search_paths.iter().flatten().find(|path| path.exists()) {
+            Some(path) => Ok(path.clone()),
+            None => home_dir()
+                .map(|dir| dir.join(".badm.toml"))
+                .ok_or(BadmError::HomeNotFound),
+        }
+    }
+
+    /// Save configuration variables to the user config file, replacing its contents, see
+    /// [`Config::user_config_file`].
+    ///
+    /// [`Config::user_config_file`]: struct.Config.html#method.user_config_file
+    pub fn write_toml_config(self) -> Result<()> {
+        let config_file_path = Self::user_config_file()?;
 
-        file.write_all(&toml.into_bytes())?;
-        file.sync_data()?;
+        let toml = toml::to_string(&self).map_err(|err| BadmError::InvalidConfig {
+            path: config_file_path.clone(),
+            message: err.to_string(),
+        })?;
 
-        Ok(())
+        write_file(&config_file_path, &toml)
+    }
+
+    fn from_settings(settings: Table, config_file: &Path) -> Result<Self> {
+        Value::Table(settings)
+            .try_into()
+            .map_err(|err: toml::de::Error| BadmError::InvalidConfig {
+                path: config_file.to_path_buf(),
+                message: err.to_string(),
+            })
+    }
+
+    /// Write `settings` to the user config file `config_file`, if they are valid once
+    /// merged with the other config files and environment variables.
+    fn write_settings(config_file: &Path, settings: Table) -> Result<()> {
+        let toml =
+            toml::to_string(&settings).map_err(|err| BadmError::InvalidConfig {
+                path: config_file.to_path_buf(),
+                message: err.to_string(),
+            })?;
+
+        let _ = Self::from_settings(Self::layer_settings(settings)?, config_file)?;
+
+        if let Some(dir) = config_file.parent() {
+            fs::create_dir_all(dir).at_path(dir)?;
+        };
+        write_file(config_file, &toml)
+    }
+}
+
+/// Read the settings of the config file at `path`. A missing file has no settings.
+fn read_settings(path: &Path) -> Result<Table> {
+    if !path.exists() {
+        return Ok(Table::new());
+    };
+
+    let toml = crate::paths::read_path(path)?;
+    toml::from_str(&toml).map_err(|err: toml::de::Error| BadmError::InvalidConfig {
+        path: path.to_path_buf(),
+        message: err.to_string(),
+    })
+}
+
+/// Settings the repo-local config file can not set, as they select dotfiles directories,
+/// run commands, or read and scan paths outside of the dotfiles directory, which a cloned
+/// repository must not be trusted with.
+pub const USER_ONLY_SETTINGS: [&str; 6] = [
+    "directory",
+    "repo",
+    "hook",
+    "key_file",
+    "target_roots",
+    "mode",
+];
+
+/// Read the settings of the repo-local config file located in `dots_dir`, which can not
+/// set any of the [`USER_ONLY_SETTINGS`].
+///
+/// [`USER_ONLY_SETTINGS`]: constant.USER_ONLY_SETTINGS.html
+fn read_repo_settings(dots_dir: &Path) -> Result<Table> {
+    let path = dots_dir.join(REPO_CONFIG_FILE_NAME);
+    let settings = read_settings(&path)?;
+
+    match USER_ONLY_SETTINGS
+        .iter()
+        .find(|name| settings.contains_key(**name))
+    {
+        Some(name) => Err(BadmError::InvalidConfig {
+            path,
+            message: format!("`{}` can only be set in the user config file", name),
+        }),
+        None => Ok(settings),
     }
 }
 
+/// Get the settings overridden by the environment variables `vars`, see
+/// [`ENV_SETTINGS`].
+///
+/// [`ENV_SETTINGS`]: constant.ENV_SETTINGS.html
+fn env_settings<I: IntoIterator<Item = (String, String)>>(vars: I) -> Table {
+    vars.into_iter()
+        .filter_map(|(var, value)| {
+            ENV_SETTINGS
+                .iter()
+                .find(|name| var == env_var(name))
+                .map(|name| (name.to_string(), Value::String(value)))
+        })
+        .collect()
+}
+
+/// Name of the environment variable overriding the setting `name`, e.g.
+/// `BADM_LINK_STYLE`.
+fn env_var(name: &str) -> String {
+    format!("BADM_{}", name.to_uppercase())
+}
+
+/// Merge `layers` of settings, given in increasing order of precedence. Tables are merged
+/// key by key, any other setting of a later layer replaces the earlier one.
+fn merge_settings(layers: Vec<Table>) -> Table {
+    let mut merged = Table::new();
+
+    for layer in layers {
+        merge_table(&mut merged, layer);
+    }
+    merged
+}
+
+fn merge_table(base: &mut Table, layer: Table) {
+    for (key, value) in layer {
+        match (base.get_mut(&key), value) {
+            (Some(Value::Table(base)), Value::Table(table)) => merge_table(base, table),
+            (_, value) => {
+                let _ = base.insert(key, value);
+            },
+        }
+    }
+}
+
+fn write_file(path: &Path, contents: &str) -> Result<()> {
+    File::create(path)
+        .and_then(|mut file| {
+            file.write_all(contents.as_bytes())?;
+            file.sync_data()
+        })
+        .at_path(path)
+}
+
 impl TryFrom<File> for Config {
-    type Error = InputError;
+    type Error = BadmError;
     fn try_from(file: File) -> Result<Self, Self::Error> {
         let mut file = file;
 
@@ -123,10 +624,12 @@ impl TryFrom<File> for Config {
 }
 
 impl TryFrom<PathBuf> for Config {
-    type Error = InputError;
+    type Error = BadmError;
     fn try_from(path: PathBuf) -> Result<Self, Self::Error> {
-        let file = File::open(path)?;
-        Self::try_from(file)
+        File::open(&path)
+            .map_err(BadmError::from)
+            .and_then(Self::try_from)
+            .at_path(&path)
     }
 }
 
@@ -142,12 +645,11 @@ impl FromStr for Config {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use failure::Error;
     use std::fs;
 
     #[ignore]
     #[test]
-    fn set_dots_dir_test() -> Result<(), Error> {
+    fn set_dots_dir_test() -> Result<()> {
         let home_dir = home_dir().unwrap();
         if !home_dir.exists() {
             fs::create_dir_all(&home_dir).expect("could not create home dir");
@@ -169,12 +671,13 @@ mod tests {
 
     #[ignore]
     #[test]
-    fn write_toml_config_test() -> io::Result<()> {
+    fn write_toml_config_test() -> Result<()> {
         let config_path = home_dir().unwrap().join(".badm.toml");
 
         let dots_dir = home_dir().unwrap().join(".dotfiles");
         let expected_config = Config {
             directory: dots_dir,
+            ..Config::default()
         };
 
         let config = expected_config.clone();
@@ -184,6 +687,7 @@ mod tests {
             Ok(config) => config,
             Err(_) => Config {
                 directory: PathBuf::from("/"),
+                ..Config::default()
             },
         };
 
@@ -191,4 +695,117 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn repos_test() -> Result<()> {
+        let config: Config = r#"
+            directory = "/home/ferris/.dots"
+
+            [[repo]]
+            name = "personal"
+            directory = "/home/ferris/.personal-dots"
+            priority = 10
+
+            [[repo]]
+            name = "team-shared"
+            directory = "/home/ferris/.dots/team"
+            priority = -10
+
+            [[repo]]
+            name = "work"
+            directory = "/home/ferris/.work-dots"
+        "#
+        .parse()?;
+
+        let names: Vec<String> = config.repos().into_iter().map(|r| r.name).collect();
+        assert_eq!(names, vec!["team-shared", "default", "work", "personal"]);
+
+        assert_eq!(
+            config.find_repo_dir(Path::new("/home/ferris/.dots/team/home/.vimrc")),
+            Some(PathBuf::from("/home/ferris/.dots/team"))
+        );
+        assert_eq!(
+            config.find_repo_dir(Path::new("/home/ferris/.dots/home/.vimrc")),
+            Some(PathBuf::from("/home/ferris/.dots"))
+        );
+        assert_eq!(config.find_repo_dir(Path::new("/etc/hosts")), None);
+
+        Ok(())
+    }
+
+    #[test]
+    fn deploy_mode_test() -> Result<()> {
+        let config: Config = r#"
+            directory = "/home/ferris/.dots"
+
+            [[mode]]
+            mode = "copy"
+            paths = ["/home/ferris/.config/Code/**", "/home/ferris/.docker"]
+
+            [[mode]]
+            mode = "symlink"
+            paths = ["/home/ferris/.config/Code/User/keybindings.json"]
+        "#
+        .parse()?;
+
+        let mode = |path: &str| config.deploy_mode(Path::new(path));
+        assert_eq!(
+            mode("/home/ferris/.config/Code/User/settings.json"),
+            DeployMode::Copy
+        );
+        assert_eq!(mode("/home/ferris/.docker/config.json"), DeployMode::Copy);
+        assert_eq!(
+            mode("/home/ferris/.config/Code/User/keybindings.json"),
+            DeployMode::Symlink
+        );
+        assert_eq!(mode("/home/ferris/.vimrc"), DeployMode::Symlink);
+
+        Ok(())
+    }
+
+    #[test]
+    fn merge_settings_test() -> Result<()> {
+        let repo: Table = toml::from_str(
+            r#"
+            layout = "home"
+            link_style = "relative"
+
+            [variables]
+            email = "team@example.com"
+            editor = "vim"
+        "#,
+        )?;
+        let user: Table = toml::from_str(
+            r#"
+            directory = "/home/ferris/.dots"
+            target_roots = ["/home/ferris/.local"]
+
+            [variables]
+            email = "ferris@example.com"
+        "#,
+        )?;
+        let env = env_settings(vec![
+            ("BADM_LINK_STYLE".to_string(), "absolute".to_string()),
+            ("BADM_PASSPHRASE".to_string(), "hunter2".to_string()),
+        ]);
+        assert_eq!(env.len(), 1);
+
+        let settings = merge_settings(vec![repo, user, env]);
+        let config: Config = toml::to_string(&settings).unwrap().parse()?;
+
+        assert_eq!(config.directory, PathBuf::from("/home/ferris/.dots"));
+        assert_eq!(config.layout, Layout::Home);
+        assert_eq!(config.link_style, LinkStyle::Absolute);
+        // tables are merged key by key
+        assert_eq!(config.target_roots, vec![PathBuf::from(
+            "/home/ferris/.local"
+        )]);
+        assert_eq!(
+            settings["variables"]["email"].as_str(),
+            Some("ferris@example.com")
+        );
+        assert_eq!(settings["variables"]["editor"].as_str(), Some("vim"));
+
+        Ok(())
+    }
 }
@@ -0,0 +1,199 @@
+//! Policies for deploying a dotfile to a target path that is already taken.
+use std::fmt;
+use std::io::{self, prelude::*};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use crate::manifest::timestamp;
+use crate::paths::is_symlink;
+
+/// What to do with an existing file, directory, or foreign symlink found at a dotfile's
+/// target path when deploying.
+#[derive(Debug, Default, PartialEq, Eq, Copy, Clone)]
+pub enum ConflictPolicy {
+    /// Leave the existing file in place and do not deploy the dotfile.
+    #[default]
+    Skip,
+    /// Move the existing file to a timestamped backup path next to it, see
+    /// [`backup_path`].
+    ///
+    /// [`backup_path`]: fn.backup_path.html
+    Backup,
+    /// Remove the existing file.
+    Overwrite,
+    /// Move the existing file into the dotfiles directory, replacing the stored copy.
+    Adopt,
+    /// Ask which of the other policies to apply for each conflict.
+    Prompt,
+}
+
+impl ConflictPolicy {
+    /// Names accepted by [`ConflictPolicy::from_str`].
+    ///
+    /// [`ConflictPolicy::from_str`]: enum.ConflictPolicy.html#method.from_str
+    pub const VARIANTS: [&'static str; 5] =
+        ["skip", "backup", "overwrite", "adopt", "prompt"];
+
+    /// Resolve [`ConflictPolicy::Prompt`] by asking on stdin which policy to apply to the
+    /// conflict at `dst`. Other policies are returned as they are.
+    ///
+    /// [`ConflictPolicy::Prompt`]: enum.ConflictPolicy.html#variant.Prompt
+    pub fn resolve(self, dst: &Path) -> crate::errors::Result<Self> {
+        match self {
+            Self::Prompt => Ok(prompt(dst, &mut io::stdin().lock(), &mut io::stderr())?),
+            policy => Ok(policy),
+        }
+    }
+}
+
+impl FromStr for ConflictPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "skip" => Ok(Self::Skip),
+            "backup" => Ok(Self::Backup),
+            "overwrite" => Ok(Self::Overwrite),
+            "adopt" => Ok(Self::Adopt),
+            "prompt" => Ok(Self::Prompt),
+            _ => Err(format!("unknown conflict policy {:?}", s)),
+        }
+    }
+}
+
+impl fmt::Display for ConflictPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Self::Skip => "skip",
+            Self::Backup => "backup",
+            Self::Overwrite => "overwrite",
+            Self::Adopt => "adopt",
+            Self::Prompt => "prompt",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// Ask on `output` which policy to apply to the conflict at `dst`, reading the answer
+/// from `input`. Unrecognised answers are asked again, and reaching the end of `input`
+/// skips the conflict.
+pub fn prompt<R: BufRead, W: Write>(
+    dst: &Path,
+    input: &mut R,
+    output: &mut W,
+) -> io::Result<ConflictPolicy> {
+    loop {
+        write!(
+            output,
+            "{:?} already exists: [s]kip, [b]ackup, [o]verwrite, or [a]dopt? ",
+            dst
+        )?;
+        output.flush()?;
+
+        let mut answer = String::new();
+        if input.read_line(&mut answer)? == 0 {
+            return Ok(ConflictPolicy::Skip);
+        };
+
+        match answer.trim().to_lowercase().as_str() {
+            "s" | "skip" => return Ok(ConflictPolicy::Skip),
+            "b" | "backup" => return Ok(ConflictPolicy::Backup),
+            "o" | "overwrite" => return Ok(ConflictPolicy::Overwrite),
+            "a" | "adopt" => return Ok(ConflictPolicy::Adopt),
+            _ => continue,
+        }
+    }
+}
+
+/// Get the path an existing file at `path` is moved to when backed up, e.g.
+/// `.gitconfig.badm-backup.1571011200`. If a backup was already made within the same
+/// second, a counter is appended, e.g. `.gitconfig.badm-backup.1571011200.1`, so that no
+/// backup is ever replaced.
+pub fn backup_path(path: &Path) -> PathBuf {
+    let mut file_name = path.file_name().unwrap_or_default().to_os_string();
+    file_name.push(format!(".badm-backup.{}", timestamp()));
+
+    let backup = path.with_file_name(&file_name);
+    let is_taken = |path: &Path| path.exists() || is_symlink(path);
+
+    if !is_taken(&backup) {
+        return backup;
+    };
+
+    (1..)
+        .map(|counter| {
+            let mut numbered = file_name.clone();
+            numbered.push(format!(".{}", counter));
+            path.with_file_name(numbered)
+        })
+        .find(|backup| !is_taken(backup))
+        .unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn from_str_test() {
+        for name in ConflictPolicy::VARIANTS.iter() {
+            let policy: ConflictPolicy = name.parse().unwrap();
+            assert_eq!(&policy.to_string(), name);
+        }
+
+        assert!("clobber".parse::<ConflictPolicy>().is_err());
+    }
+
+    #[test]
+    fn prompt_test() -> io::Result<()> {
+        let dst = Path::new("/home/ferris/.gitconfig");
+        let mut output = vec![];
+
+        let mut input = io::Cursor::new("x\nB\n");
+        assert_eq!(
+            prompt(dst, &mut input, &mut output)?,
+            ConflictPolicy::Backup
+        );
+
+        let mut input = io::Cursor::new("");
+        assert_eq!(prompt(dst, &mut input, &mut output)?, ConflictPolicy::Skip);
+
+        assert_eq!(
+            String::from_utf8_lossy(&output)
+                .matches("already exists")
+                .count(),
+            3
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn backup_path_taken_test() -> io::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join(".gitconfig");
+
+        let first = backup_path(&path);
+        std::fs::write(&first, "first")?;
+        let second = backup_path(&path);
+        std::fs::write(&second, "second")?;
+        let third = backup_path(&path);
+
+        // backups made within the same second get distinct paths
+        assert_ne!(first, second);
+        assert!(third != first && third != second);
+        assert_eq!(std::fs::read_to_string(&first)?, "first");
+
+        Ok(())
+    }
+
+    #[test]
+    fn backup_path_test() {
+        let backup = backup_path(Path::new("/home/ferris/.gitconfig"));
+
+        assert_eq!(backup.parent(), Some(Path::new("/home/ferris")));
+        assert!(backup
+            .to_string_lossy()
+            .starts_with("/home/ferris/.gitconfig.badm-backup."));
+    }
+}
@@ -0,0 +1,251 @@
+//! Encrypted dotfiles for secrets that should not be stored in plaintext.
+//!
+//! `badm stow --encrypt` stores an encrypted copy of a file, with `.enc` appended to its
+//! name, so the dotfiles directory can be pushed to a shared repository. When deployed,
+//! the stored copy is decrypted and written to the dotfile's path without the `.enc`
+//! suffix, readable only by its owner.
+//!
+//! Files are encrypted with XChaCha20-Poly1305, using a key derived with Argon2id from a
+//! passphrase or the contents of a key file, see [`Secret`]. Every encrypted file has
+//! its own random salt and nonce, which are stored in front of the ciphertext:
+//!
+//! ```text
+//! BADM\x01 | salt (16 bytes) | nonce (24 bytes) | ciphertext and tag
+//! ```
+//!
+//! [`Secret`]: struct.Secret.html
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use argon2::Argon2;
+use chacha20poly1305::aead::rand_core::RngCore;
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng, Payload};
+use chacha20poly1305::{XChaCha20Poly1305, XNonce};
+use zeroize::Zeroizing;
+
+use crate::alternates::base_path;
+use crate::errors::{BadmError, PathContext, Result};
+
+/// File extension marking a stored dotfile as encrypted.
+pub const ENCRYPTED_EXTENSION: &str = "enc";
+
+/// Unix permission bits of decrypted dotfiles.
+pub const DECRYPTED_MODE: u32 = 0o600;
+
+/// Environment variable the passphrase is read from, if set.
+pub const PASSPHRASE_VAR: &str = "BADM_PASSPHRASE";
+
+const MAGIC: &[u8] = b"BADM\x01";
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 24;
+const HEADER_LEN: usize = MAGIC.len() + SALT_LEN + NONCE_LEN;
+
+/// Secret dotfiles are encrypted with: a passphrase, or the contents of a key file. The
+/// secret is wiped from memory when dropped.
+#[derive(Clone)]
+pub struct Secret(Zeroizing<Vec<u8>>);
+
+impl Secret {
+    /// Use `passphrase` as secret.
+    pub fn new(passphrase: &str) -> Result<Self> {
+        if passphrase.is_empty() {
+            return Err(BadmError::Passphrase {
+                message: "must not be empty".to_string(),
+            });
+        };
+        Ok(Self(Zeroizing::new(passphrase.as_bytes().to_vec())))
+    }
+
+    /// Use the contents of the key file at `path` as secret.
+    pub fn from_file(path: &Path) -> Result<Self> {
+        let bytes = fs::read(path).at_path(path)?;
+
+        if bytes.is_empty() {
+            return Err(BadmError::invalid(path, "key file must not be empty"));
+        };
+        Ok(Self(Zeroizing::new(bytes)))
+    }
+
+    /// Read the passphrase from the `BADM_PASSPHRASE` environment variable. Returns
+    /// `None` if it is not set.
+    pub fn from_env() -> Option<Result<Self>> {
+        std::env::var(PASSPHRASE_VAR)
+            .ok()
+            .map(|passphrase| Self::new(&passphrase))
+    }
+
+    /// Ask for the passphrase on the terminal without echoing it. If `confirm` is set,
+    /// the passphrase has to be entered twice.
+    pub fn prompt(confirm: bool) -> Result<Self> {
+        let passphrase =
+            Zeroizing::new(rpassword::read_password_from_tty(Some("passphrase: "))?);
+
+        if confirm {
+            let repeated = Zeroizing::new(rpassword::read_password_from_tty(Some(
+                "repeat passphrase: ",
+            ))?);
+
+            if repeated != passphrase {
+                return Err(BadmError::Passphrase {
+                    message: "repeated passphrase does not match".to_string(),
+                });
+            };
+        };
+
+        Self::new(&passphrase)
+    }
+
+    fn cipher(&self, salt: &[u8]) -> Result<XChaCha20Poly1305> {
+        let mut key = Zeroizing::new([0u8; 32]);
+
+        Argon2::default()
+            .hash_password_into(&self.0, salt, &mut *key)
+            .map_err(|err| BadmError::Passphrase {
+                message: err.to_string(),
+            })?;
+
+        Ok(XChaCha20Poly1305::new((&*key).into()))
+    }
+}
+
+impl fmt::Debug for Secret {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Secret(..)")
+    }
+}
+
+/// Returns `true` if the stored dotfile at `path` is encrypted. Alternate conditions in
+/// the file name are ignored.
+pub fn is_encrypted(path: &Path) -> bool {
+    base_path(path).extension().and_then(|ext| ext.to_str()) == Some(ENCRYPTED_EXTENSION)
+}
+
+/// Append the `.enc` extension to `path`.
+///
+/// # Examples
+///
+/// ```
+/// use badm::crypto::encrypted_path;
+/// use std::path::{Path, PathBuf};
+///
+/// assert_eq!(
+///     encrypted_path(Path::new("home/ferris/.netrc")),
+///     PathBuf::from("home/ferris/.netrc.enc")
+/// );
+/// ```
+pub fn encrypted_path(path: &Path) -> PathBuf {
+    let mut file_name = path.file_name().unwrap_or_default().to_os_string();
+    file_name.push(".");
+    file_name.push(ENCRYPTED_EXTENSION);
+
+    path.with_file_name(file_name)
+}
+
+/// Strip the `.enc` extension from `path`, if present.
+pub fn strip_extension(path: &Path) -> PathBuf {
+    if path.extension().and_then(|ext| ext.to_str()) == Some(ENCRYPTED_EXTENSION) {
+        path.with_extension("")
+    } else {
+        path.to_path_buf()
+    }
+}
+
+/// Encrypt `plaintext`, the contents of the file at `path`, with a key derived from
+/// `secret`.
+pub fn encrypt(path: &Path, plaintext: &[u8], secret: &Secret) -> Result<Vec<u8>> {
+    let mut salt = [0u8; SALT_LEN];
+    OsRng.fill_bytes(&mut salt);
+    let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+
+    let mut data = Vec::with_capacity(HEADER_LEN + plaintext.len() + 16);
+    data.extend_from_slice(MAGIC);
+    data.extend_from_slice(&salt);
+    data.extend_from_slice(&nonce);
+
+    let ciphertext = secret
+        .cipher(&salt)?
+        .encrypt(&nonce, Payload {
+            msg: plaintext,
+            aad: &data,
+        })
+        .map_err(|_| BadmError::invalid(path, "could not encrypt file"))?;
+    data.extend_from_slice(&ciphertext);
+
+    Ok(data)
+}
+
+/// Decrypt `data`, the contents of the file at `path` produced by [`encrypt`], with a key
+/// derived from `secret`.
+///
+/// Fails if `secret` is not the one `data` was encrypted with, or `data` was modified.
+///
+/// [`encrypt`]: fn.encrypt.html
+pub fn decrypt(path: &Path, data: &[u8], secret: &Secret) -> Result<Vec<u8>> {
+    if data.len() < HEADER_LEN || !data.starts_with(MAGIC) {
+        return Err(BadmError::invalid(path, "not encrypted by badm"));
+    };
+
+    let (header, ciphertext) = data.split_at(HEADER_LEN);
+    let salt = &header[MAGIC.len()..MAGIC.len() + SALT_LEN];
+    let nonce = XNonce::from_slice(&header[MAGIC.len() + SALT_LEN..]);
+
+    secret
+        .cipher(salt)?
+        .decrypt(nonce, Payload {
+            msg: ciphertext,
+            aad: header,
+        })
+        .map_err(|_| {
+            BadmError::invalid(
+                path,
+                "wrong passphrase or key file, or the file was modified",
+            )
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encrypt_test() -> Result<()> {
+        let path = Path::new("/dots/.netrc.enc");
+        let secret = Secret::new("correct horse battery staple")?;
+
+        let data = encrypt(path, b"token = hunter2", &secret)?;
+        assert!(!data.windows(7).any(|window| window == b"hunter2"));
+        assert_ne!(data, encrypt(path, b"token = hunter2", &secret)?);
+        assert_eq!(decrypt(path, &data, &secret)?, b"token = hunter2");
+
+        let err = decrypt(path, &data, &Secret::new("wrong")?).unwrap_err();
+        assert_eq!(err.path(), path);
+
+        let mut modified = data.clone();
+        *modified.last_mut().unwrap() ^= 1;
+        assert!(decrypt(path, &modified, &secret).is_err());
+        let err = decrypt(path, b"token = hunter2", &secret).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "\"/dots/.netrc.enc\": not encrypted by badm"
+        );
+
+        assert_eq!(Secret::new("").unwrap_err().exit_code(), 11);
+
+        Ok(())
+    }
+
+    #[test]
+    fn is_encrypted_test() {
+        assert!(is_encrypted(Path::new("/dots/.netrc.enc")));
+        assert!(is_encrypted(Path::new(
+            "/dots/.netrc.enc##hostname.build01"
+        )));
+        assert!(!is_encrypted(Path::new("/dots/.netrc")));
+
+        assert_eq!(
+            strip_extension(Path::new("/dots/.netrc.enc")),
+            PathBuf::from("/dots/.netrc")
+        );
+    }
+}
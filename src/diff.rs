@@ -0,0 +1,488 @@
+//! Unified line diffs between the stored and the deployed content of a dotfile.
+//!
+//! [`FileDiff::new`] compares the content deploying a stored dotfile would write, i.e.
+//! the file itself, the rendered template, or the decrypted file, with the content found
+//! at its target path, in the format of `diff -u`:
+//!
+//! ```text
+//! --- "/home/ferris/.dots/home/ferris/.gitconfig"
+//! +++ "/home/ferris/.gitconfig"
+//! @@ -1,3 +1,3 @@
+//!  [user]
+//!      name = Ferris
+//! -    email = ferris@example.com
+//! +    email = ferris@rust-lang.org
+//! ```
+//!
+//! Lines removed from the stored content are prefixed with `-`, lines added at the
+//! target path with `+`. Content that is not valid UTF-8 is only reported as differing.
+//!
+//! [`FileDiff::new`]: struct.FileDiff.html#method.new
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+use serde_derive::Serialize;
+
+/// Number of unchanged lines shown around each change.
+const CONTEXT: usize = 3;
+
+const RED: &str = "\x1b[31m";
+const GREEN: &str = "\x1b[32m";
+const CYAN: &str = "\x1b[36m";
+const BOLD: &str = "\x1b[1m";
+const RESET: &str = "\x1b[0m";
+
+/// Differences between the stored and the deployed content of a single dotfile.
+#[derive(Serialize, Debug, PartialEq, Eq, Clone)]
+pub struct FileDiff {
+    /// Path of the stored dotfile.
+    pub stored: PathBuf,
+    /// Path the dotfile is deployed to.
+    pub target: PathBuf,
+    /// Lines of the unified diff, without line breaks. Empty if the contents are equal.
+    pub lines: Vec<String>,
+}
+
+impl FileDiff {
+    /// Compare `expected`, the content deploying the dotfile stored at `stored` writes,
+    /// with `actual`, the content found at its target path `target`.
+    pub fn new(stored: &Path, target: &Path, expected: &[u8], actual: &[u8]) -> Self {
+        let lines = if expected == actual {
+            vec![]
+        } else {
+            match (std::str::from_utf8(expected), std::str::from_utf8(actual)) {
+                (Ok(expected), Ok(actual))
+                    if !is_binary(expected) && !is_binary(actual) =>
+                {
+                    let mut lines =
+                        vec![format!("--- {:?}", stored), format!("+++ {:?}", target)];
+                    lines.extend(unified_diff(expected, actual, CONTEXT));
+                    lines
+                },
+                _ => vec![format!("Binary files {:?} and {:?} differ", stored, target)],
+            }
+        };
+
+        Self {
+            stored: stored.to_path_buf(),
+            target: target.to_path_buf(),
+            lines,
+        }
+    }
+
+    /// Returns `true` if the stored and the deployed content differ.
+    pub fn is_changed(&self) -> bool {
+        !self.lines.is_empty()
+    }
+}
+
+fn is_binary(text: &str) -> bool {
+    text.contains('\0')
+}
+
+/// Color `line` of a unified diff with ANSI escape codes, for printing in a terminal.
+pub fn colorize(line: &str) -> String {
+    let color = if line.starts_with("---") || line.starts_with("+++") {
+        BOLD
+    } else if line.starts_with("@@") {
+        CYAN
+    } else if line.starts_with('-') {
+        RED
+    } else if line.starts_with('+') {
+        GREEN
+    } else {
+        return line.to_string();
+    };
+    format!("{}{}{}", color, line, RESET)
+}
+
+/// A single step turning the old lines into the new lines.
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+enum Edit {
+    /// The old line at the first index equals the new line at the second one.
+    Keep(usize, usize),
+    /// The old line at the index is removed.
+    Delete(usize),
+    /// The new line at the index is inserted.
+    Insert(usize),
+}
+
+impl Edit {
+    fn is_change(self) -> bool {
+        !matches!(self, Self::Keep(..))
+    }
+}
+
+/// Get the hunks of the unified diff turning `old` into `new`, with `context` unchanged
+/// lines around each change. A line lacking a line break at the end of its file is
+/// followed by a `\ No newline at end of file` marker.
+fn unified_diff(old: &str, new: &str, context: usize) -> Vec<String> {
+    let old: Vec<&str> = old.split_inclusive('\n').collect();
+    let new: Vec<&str> = new.split_inclusive('\n').collect();
+    let edits = shortest_edit(&old, &new);
+
+    let mut lines = vec![];
+    let mut changes = edits
+        .iter()
+        .enumerate()
+        .filter(|(_, edit)| edit.is_change())
+        .map(|(i, _)| i)
+        .peekable();
+
+    while let Some(first) = changes.next() {
+        // changes separated by at most twice the context share a hunk
+        let mut last = first;
+        while let Some(&next) = changes.peek() {
+            if next - last > 2 * context {
+                break;
+            };
+            last = next;
+            let _ = changes.next();
+        }
+
+        let start = first.saturating_sub(context);
+        let end = (last + context + 1).min(edits.len());
+        let hunk = &edits[start..end];
+
+        // line numbers before the hunk, as the old and new lines are consumed in order
+        let old_before = edits[..start]
+            .iter()
+            .filter(|edit| !matches!(edit, Edit::Insert(_)))
+            .count();
+        let new_before = edits[..start]
+            .iter()
+            .filter(|edit| !matches!(edit, Edit::Delete(_)))
+            .count();
+        let old_len = hunk
+            .iter()
+            .filter(|edit| !matches!(edit, Edit::Insert(_)))
+            .count();
+        let new_len = hunk
+            .iter()
+            .filter(|edit| !matches!(edit, Edit::Delete(_)))
+            .count();
+
+        lines.push(format!(
+            "@@ -{} +{} @@",
+            hunk_range(old_before, old_len),
+            hunk_range(new_before, new_len)
+        ));
+
+        for edit in hunk {
+            let (prefix, line) = match *edit {
+                Edit::Keep(i, _) => (' ', old[i]),
+                Edit::Delete(i) => ('-', old[i]),
+                Edit::Insert(j) => ('+', new[j]),
+            };
+
+            match line.strip_suffix('\n') {
+                Some(line) => lines.push(format!("{}{}", prefix, line)),
+                None => {
+                    lines.push(format!("{}{}", prefix, line));
+                    lines.push("\\ No newline at end of file".to_string());
+                },
+            };
+        }
+    }
+
+    lines
+}
+
+/// Format the range of a hunk header, which omits the length if it is one, and starts
+/// at the line before the hunk if it is empty.
+fn hunk_range(before: usize, len: usize) -> String {
+    match len {
+        0 => format!("{},0", before),
+        1 => format!("{}", before + 1),
+        _ => format!("{},{}", before + 1, len),
+    }
+}
+
+/// Find the shortest sequence of edits turning `old` into `new`, with the linear space
+/// variant of Myers' algorithm, which splits the inputs at the middle snake of an
+/// optimal path and recurses on both halves.
+fn shortest_edit(old: &[&str], new: &[&str]) -> Vec<Edit> {
+    let mut forward = Diagonals::new(old.len() + new.len());
+    let mut backward = Diagonals::new(old.len() + new.len());
+    let mut edits = vec![];
+
+    conquer(
+        old,
+        0..old.len(),
+        new,
+        0..new.len(),
+        &mut forward,
+        &mut backward,
+        &mut edits,
+    );
+
+    // removed lines are listed before the lines replacing them
+    for changes in edits.split_mut(|edit| !edit.is_change()) {
+        changes.sort_by_key(|edit| matches!(edit, Edit::Insert(_)));
+    }
+    edits
+}
+
+/// Furthest reaching x coordinate on each diagonal k = x - y.
+struct Diagonals {
+    v: Vec<usize>,
+    offset: isize,
+}
+
+impl Diagonals {
+    fn new(len: usize) -> Self {
+        let max = (len / 2 + 1) as isize;
+
+        Self {
+            v: vec![0; 2 * max as usize + 3],
+            offset: max + 1,
+        }
+    }
+
+    fn get(&self, k: isize) -> usize {
+        self.v[(k + self.offset) as usize]
+    }
+
+    fn set(&mut self, k: isize, x: usize) {
+        self.v[(k + self.offset) as usize] = x;
+    }
+}
+
+/// Append the edits turning `old[old_range]` into `new[new_range]` to `edits`.
+fn conquer(
+    old: &[&str],
+    mut old_range: Range<usize>,
+    new: &[&str],
+    mut new_range: Range<usize>,
+    forward: &mut Diagonals,
+    backward: &mut Diagonals,
+    edits: &mut Vec<Edit>,
+) {
+    let prefix = common_prefix(&old[old_range.clone()], &new[new_range.clone()]);
+    for i in 0..prefix {
+        edits.push(Edit::Keep(old_range.start + i, new_range.start + i));
+    }
+    old_range.start += prefix;
+    new_range.start += prefix;
+
+    let suffix = common_suffix(&old[old_range.clone()], &new[new_range.clone()]);
+    old_range.end -= suffix;
+    new_range.end -= suffix;
+
+    if old_range.is_empty() || new_range.is_empty() {
+        edits.extend(old_range.clone().map(Edit::Delete));
+        edits.extend(new_range.clone().map(Edit::Insert));
+    } else {
+        let (x, y) = middle_snake(
+            old,
+            old_range.clone(),
+            new,
+            new_range.clone(),
+            forward,
+            backward,
+        );
+
+        conquer(
+            old,
+            old_range.start..x,
+            new,
+            new_range.start..y,
+            forward,
+            backward,
+            edits,
+        );
+        conquer(
+            old,
+            x..old_range.end,
+            new,
+            y..new_range.end,
+            forward,
+            backward,
+            edits,
+        );
+    };
+
+    for i in 0..suffix {
+        edits.push(Edit::Keep(old_range.end + i, new_range.end + i));
+    }
+}
+
+/// Find a point on an optimal path turning `old[old_range]` into `new[new_range]`, both
+/// of which are not empty, by searching from both ends until the paths overlap.
+fn middle_snake(
+    old: &[&str],
+    old_range: Range<usize>,
+    new: &[&str],
+    new_range: Range<usize>,
+    forward: &mut Diagonals,
+    backward: &mut Diagonals,
+) -> (usize, usize) {
+    let old = &old[old_range.clone()];
+    let new = &new[new_range.clone()];
+    let (n, m) = (old.len(), new.len());
+    let delta = n as isize - m as isize;
+    let odd = delta % 2 != 0;
+
+    forward.set(1, 0);
+    backward.set(1, 0);
+
+    for d in 0..=(n + m).div_ceil(2) as isize {
+        for k in (-d..=d).rev().step_by(2) {
+            let mut x = if k == -d || (k != d && forward.get(k - 1) < forward.get(k + 1))
+            {
+                forward.get(k + 1)
+            } else {
+                forward.get(k - 1) + 1
+            };
+            let y = (x as isize - k) as usize;
+            let (x0, y0) = (x, y);
+
+            if x < n && y < m {
+                x += common_prefix(&old[x..], &new[y..]);
+            };
+            forward.set(k, x);
+
+            if odd && (k - delta).abs() < d && x + backward.get(delta - k) >= n {
+                return (old_range.start + x0, new_range.start + y0);
+            };
+        }
+
+        // x and y are counted from the ends of the inputs
+        for k in (-d..=d).rev().step_by(2) {
+            let mut x =
+                if k == -d || (k != d && backward.get(k - 1) < backward.get(k + 1)) {
+                    backward.get(k + 1)
+                } else {
+                    backward.get(k - 1) + 1
+                };
+            let mut y = (x as isize - k) as usize;
+
+            if x < n && y < m {
+                let suffix = common_suffix(&old[..n - x], &new[..m - y]);
+                x += suffix;
+                y += suffix;
+            };
+            backward.set(k, x);
+
+            if !odd && (k - delta).abs() <= d && x + forward.get(delta - k) >= n {
+                return (old_range.start + n - x, new_range.start + m - y);
+            };
+        }
+    }
+
+    unreachable!("the forward and backward paths overlap after (n + m + 1) / 2 rounds")
+}
+
+fn common_prefix(old: &[&str], new: &[&str]) -> usize {
+    old.iter()
+        .zip(new)
+        .take_while(|(old, new)| old == new)
+        .count()
+}
+
+fn common_suffix(old: &[&str], new: &[&str]) -> usize {
+    old.iter()
+        .rev()
+        .zip(new.iter().rev())
+        .take_while(|(old, new)| old == new)
+        .count()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unified_diff_test() {
+        let old = "a\nb\nc\nd\ne\nf\ng\nh\ni\nj\n";
+        let new = "a\nB\nc\nd\ne\nf\ng\nh\ni\nj\nk";
+
+        assert_eq!(unified_diff(old, new, 3), vec![
+            "@@ -1,5 +1,5 @@",
+            " a",
+            "-b",
+            "+B",
+            " c",
+            " d",
+            " e",
+            "@@ -8,3 +8,4 @@",
+            " h",
+            " i",
+            " j",
+            "+k",
+            "\\ No newline at end of file",
+        ]);
+
+        assert_eq!(unified_diff("", "a\n", 3), vec!["@@ -0,0 +1 @@", "+a"]);
+        assert_eq!(unified_diff("a\nb\n", "b\n", 3), vec![
+            "@@ -1,2 +1 @@",
+            "-a",
+            " b",
+        ]);
+    }
+
+    #[test]
+    fn shortest_edit_test() {
+        let old = vec!["a", "b", "c", "a", "b", "b", "a"];
+        let new = vec!["c", "b", "a", "b", "a", "c"];
+        let edits = shortest_edit(&old, &new);
+
+        // the example of Myers' paper takes five edits
+        assert_eq!(edits.iter().filter(|edit| edit.is_change()).count(), 5);
+
+        let mut patched = vec![];
+        for edit in edits {
+            match edit {
+                Edit::Keep(i, j) => {
+                    assert_eq!(old[i], new[j]);
+                    patched.push(old[i]);
+                },
+                Edit::Delete(_) => {},
+                Edit::Insert(j) => patched.push(new[j]),
+            };
+        }
+        assert_eq!(patched, new);
+    }
+
+    #[test]
+    fn unified_diff_large_test() {
+        // completely different inputs need the most rounds to find the edits
+        let old: String = (0..2000).map(|i| format!("old {}\n", i)).collect();
+        let new: String = (0..2000).map(|i| format!("new {}\n", i)).collect();
+        let lines = unified_diff(&old, &new, 3);
+
+        assert_eq!(lines[0], "@@ -1,2000 +1,2000 @@");
+        assert_eq!(lines.len(), 4001);
+        assert_eq!(
+            lines.iter().filter(|line| line.starts_with('-')).count(),
+            2000
+        );
+        assert_eq!(
+            (lines[1].as_str(), lines[2001].as_str()),
+            ("-old 0", "+new 0")
+        );
+    }
+
+    #[test]
+    fn file_diff_test() {
+        let stored = Path::new("/dots/.vimrc");
+        let target = Path::new("/home/ferris/.vimrc");
+
+        assert!(!FileDiff::new(stored, target, b"set nu\n", b"set nu\n").is_changed());
+
+        let diff = FileDiff::new(stored, target, b"set nu\n", b"set rnu\n");
+        assert_eq!(diff.lines, vec![
+            "--- \"/dots/.vimrc\"",
+            "+++ \"/home/ferris/.vimrc\"",
+            "@@ -1 +1 @@",
+            "-set nu",
+            "+set rnu",
+        ]);
+        assert_eq!(colorize(&diff.lines[3]), "\x1b[31m-set nu\x1b[0m");
+
+        let diff = FileDiff::new(stored, target, b"\xff\xfe", b"set nu\n");
+        assert_eq!(diff.lines, vec![
+            "Binary files \"/dots/.vimrc\" and \"/home/ferris/.vimrc\" differ"
+        ]);
+    }
+}
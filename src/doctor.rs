@@ -0,0 +1,303 @@
+//! Finds and repairs deployments that no longer match the dotfiles directories, as left
+//! behind by reorganising a dotfiles directory or by deploying on another layout.
+//!
+//! [`find_links`] scans target roots, the home directory by default, for symlinks
+//! pointing into a dotfiles directory, and [`diagnose`] reports each [`Problem`] with
+//! them and with the stored dotfiles:
+//!
+//! - `dangling` - a symlink points to a path inside a dotfiles directory that no longer
+//!   exists
+//! - `misplaced` - a symlink points to a stored dotfile from a path other than the
+//!   dotfile's target path
+//! - `unlinked` - nothing exists at the target path of a stored dotfile
+//!
+//! Rendered templates and decrypted files are not symlinks, and are reported by `badm
+//! status` instead.
+//!
+//! [`find_links`]: fn.find_links.html
+//! [`diagnose`]: fn.diagnose.html
+//! [`Problem`]: enum.Problem.html
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde_derive::Serialize;
+
+use crate::commands::{deploy_dotfile, DeployOptions};
+use crate::conflict::ConflictPolicy;
+use crate::crypto::is_encrypted;
+use crate::errors::Result;
+use crate::executor::{Action, Executor};
+use crate::paths::{is_symlink, resolve_link};
+use crate::status::LinkStatus;
+use crate::template::is_template;
+
+/// A deployment that does not match the dotfiles directories.
+#[derive(Serialize, Debug, PartialEq, Eq, Clone)]
+#[serde(tag = "problem", rename_all = "snake_case")]
+pub enum Problem {
+    /// A symlink points into a dotfiles directory, but its target no longer exists.
+    Dangling {
+        /// Path of the symlink.
+        link: PathBuf,
+        /// Path the symlink points to.
+        target: PathBuf,
+    },
+    /// A symlink points to a stored dotfile, but is not located at the dotfile's target
+    /// path.
+    Misplaced {
+        /// Path of the symlink.
+        link: PathBuf,
+        /// Path the symlink points to.
+        target: PathBuf,
+    },
+    /// Nothing exists at the target path of a stored dotfile.
+    Unlinked {
+        /// Path of the stored dotfile.
+        stored: PathBuf,
+        /// Target path of the dotfile.
+        target: PathBuf,
+    },
+}
+
+impl Problem {
+    /// Path the problem is located at: the symlink, or the target path of the unlinked
+    /// dotfile.
+    pub fn path(&self) -> &Path {
+        match self {
+            Self::Dangling { link, .. } | Self::Misplaced { link, .. } => link,
+            Self::Unlinked { target, .. } => target,
+        }
+    }
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Dangling { link, target } => {
+                write!(f, "{:<10}{:?} -> {:?}", "dangling", link, target)
+            },
+            Self::Misplaced { link, target } => {
+                write!(f, "{:<10}{:?} -> {:?}", "misplaced", link, target)
+            },
+            Self::Unlinked { stored, target } => {
+                write!(f, "{:<10}{:?} (stored at {:?})", "unlinked", target, stored)
+            },
+        }
+    }
+}
+
+/// Collect the symlinks below the directories `roots` that point into one of the
+/// dotfiles directories `dots_dirs`.
+///
+/// The dotfiles directories themselves are not scanned. Symlinks to directories are
+/// never traversed, and directories that can not be read are skipped.
+pub fn find_links(roots: &[PathBuf], dots_dirs: &[PathBuf]) -> Vec<PathBuf> {
+    let mut links = vec![];
+
+    for root in roots {
+        collect_links(root, dots_dirs, &mut links);
+    }
+
+    links.sort();
+    links.dedup();
+    links
+}
+
+fn collect_links(dir: &Path, dots_dirs: &[PathBuf], links: &mut Vec<PathBuf>) {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(_) => return,
+    };
+
+    for path in entries.filter_map(Result::ok).map(|entry| entry.path()) {
+        if is_symlink(&path) {
+            if points_into(&path, dots_dirs) {
+                links.push(path);
+            };
+        } else if path.is_dir() && !dots_dirs.contains(&path) {
+            collect_links(&path, dots_dirs, links);
+        };
+    }
+}
+
+/// Returns `true` if `link` is a symlink pointing into one of `dots_dirs`.
+fn points_into(link: &Path, dots_dirs: &[PathBuf]) -> bool {
+    resolve_link(link)
+        .map(|target| dots_dirs.iter().any(|dir| target.starts_with(dir)))
+        .unwrap_or(false)
+}
+
+/// Get the problems with the symlinks `links`, as found by [`find_links`], and with the
+/// stored dotfiles of `dots_dirs`. `targets` maps the target path of every dotfile
+/// deployed on this system to the stored dotfile and its dotfiles directory.
+///
+/// Symlinks at target paths are checked even if they are not part of `links`.
+///
+/// [`find_links`]: fn.find_links.html
+pub fn diagnose(
+    links: &[PathBuf],
+    dots_dirs: &[PathBuf],
+    targets: &BTreeMap<PathBuf, (PathBuf, PathBuf)>,
+) -> Vec<Problem> {
+    let mut links: BTreeSet<&PathBuf> = links.iter().collect();
+    links.extend(
+        targets
+            .keys()
+            .filter(|target| points_into(target, dots_dirs)),
+    );
+
+    let mut problems = vec![];
+
+    for link in links {
+        let target = match resolve_link(link) {
+            Ok(target) => target,
+            Err(_) => continue,
+        };
+
+        let is_linked = targets
+            .get(link)
+            .map(|(stored, _)| *stored == target && !is_generated(stored))
+            .unwrap_or(false);
+
+        if !target.exists() {
+            problems.push(Problem::Dangling {
+                link: link.clone(),
+                target,
+            });
+        } else if !is_linked {
+            problems.push(Problem::Misplaced {
+                link: link.clone(),
+                target,
+            });
+        };
+    }
+
+    for (target, (stored, _)) in targets {
+        if !is_generated(stored)
+            && LinkStatus::check(stored, target) == LinkStatus::Missing
+        {
+            problems.push(Problem::Unlinked {
+                stored: stored.clone(),
+                target: target.clone(),
+            });
+        };
+    }
+
+    problems
+}
+
+/// Repair `problem`, as reported by [`diagnose`] for `targets`.
+///
+/// A dangling or misplaced symlink located at the target path of a stored dotfile is
+/// replaced by a symlink to that dotfile, any other one is removed. An unlinked dotfile
+/// is deployed with `options`. Stored dotfiles are never changed.
+///
+/// Filesystem changes are applied through `exec`, so no files are touched in dry-run
+/// mode.
+///
+/// [`diagnose`]: fn.diagnose.html
+pub fn fix(
+    problem: &Problem,
+    targets: &BTreeMap<PathBuf, (PathBuf, PathBuf)>,
+    options: DeployOptions,
+    exec: &mut Executor,
+) -> Result<()> {
+    match problem {
+        Problem::Dangling { link, .. } | Problem::Misplaced { link, .. } => {
+            match targets
+                .get(link)
+                .filter(|(stored, _)| !is_generated(stored))
+            {
+                Some((stored, dots_dir)) => {
+                    // the link points into a dotfiles directory, so nothing is lost
+                    let options = DeployOptions {
+                        on_conflict: ConflictPolicy::Overwrite,
+                        ..options
+                    };
+                    let _ = deploy_dotfile(stored, link, dots_dir, options, exec)?;
+                },
+                None => exec.run(Action::Remove { path: link.clone() })?,
+            };
+        },
+        Problem::Unlinked { stored, target } => {
+            if let Some((_, dots_dir)) = targets.get(target) {
+                let _ = deploy_dotfile(stored, target, dots_dir, options, exec)?;
+            };
+        },
+    };
+    Ok(())
+}
+
+fn is_generated(stored: &Path) -> bool {
+    is_template(stored) || is_encrypted(stored)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(unix)]
+    #[test]
+    fn diagnose_test() -> Result<()> {
+        use std::os::unix::fs::symlink;
+
+        let dir = tempfile::tempdir()?;
+        let dir = fs::canonicalize(dir.path())?;
+        let dots_dir = dir.join("dots");
+        let home = dir.join("home");
+        fs::create_dir_all(&dots_dir)?;
+        fs::create_dir_all(home.join(".config"))?;
+
+        let vimrc = dots_dir.join(".vimrc");
+        let zshrc = dots_dir.join(".zshrc");
+        fs::write(&vimrc, "vimrc")?;
+        fs::write(&zshrc, "zshrc")?;
+
+        symlink(&vimrc, home.join(".vimrc"))?;
+        symlink(dots_dir.join(".old"), home.join(".config/old"))?;
+        symlink(&vimrc, home.join(".config/vimrc"))?;
+        symlink(dir.join("elsewhere"), home.join(".foreign"))?;
+
+        let mut targets = BTreeMap::new();
+        let _ = targets.insert(home.join(".vimrc"), (vimrc.clone(), dots_dir.clone()));
+        let _ = targets.insert(home.join(".zshrc"), (zshrc.clone(), dots_dir.clone()));
+
+        let dots_dirs = vec![dots_dir.clone()];
+        let roots = vec![home.clone()];
+        let links = find_links(&roots, &dots_dirs);
+        assert_eq!(links.len(), 3);
+
+        let problems = diagnose(&links, &dots_dirs, &targets);
+        assert_eq!(problems, vec![
+            Problem::Dangling {
+                link: home.join(".config/old"),
+                target: dots_dir.join(".old"),
+            },
+            Problem::Misplaced {
+                link: home.join(".config/vimrc"),
+                target: vimrc,
+            },
+            Problem::Unlinked {
+                stored: zshrc.clone(),
+                target: home.join(".zshrc"),
+            },
+        ]);
+
+        let mut exec = Executor::new();
+        for problem in problems.iter() {
+            fix(problem, &targets, DeployOptions::default(), &mut exec)?;
+        }
+
+        assert!(!is_symlink(&home.join(".config/vimrc")));
+        assert!(!is_symlink(&home.join(".config/old")));
+        assert_eq!(fs::read_link(home.join(".zshrc"))?, zshrc);
+        assert!(is_symlink(&home.join(".foreign")));
+        assert!(
+            diagnose(&find_links(&roots, &dots_dirs), &dots_dirs, &targets).is_empty()
+        );
+
+        Ok(())
+    }
+}
@@ -1,22 +1,369 @@
+//! Errors of badm's operations, and the exit codes of the `badm` process failing with
+//! them.
+use std::error;
+use std::fmt;
 use std::io;
+use std::path::{Path, PathBuf};
 
-#[allow(missing_docs)]
-#[derive(Debug, Fail)]
-pub enum InputError {
-    #[fail(display = "Could not parse toml: {:?}", err)]
-    InvalidToml { err: toml::de::Error },
-    #[fail(display = "Could not parse input: {:?}: ", err)]
-    BadInput { err: io::Error },
+/// Result of badm's operations.
+pub type Result<T, E = BadmError> = std::result::Result<T, E>;
+
+/// Errors of badm's operations, each carrying the path it concerns.
+///
+/// Errors converted from an [`io::Error`] without context have an empty path, use
+/// [`PathContext::at_path`] to attach one.
+///
+/// [`io::Error`]: https://doc.rust-lang.org/std/io/struct.Error.html
+/// [`PathContext::at_path`]: trait.PathContext.html#tymethod.at_path
+#[derive(Debug)]
+pub enum BadmError {
+    /// No dotfiles directory is set, as no configuration file was found or none of the
+    /// settings set one.
+    ConfigNotFound {
+        /// Path of the user configuration file.
+        path: PathBuf,
+    },
+    /// The configuration file could not be read or written.
+    InvalidConfig {
+        /// Path of the configuration file.
+        path: PathBuf,
+        /// What is wrong with it.
+        message: String,
+    },
+    /// A path that has to be located inside a dotfiles directory is not.
+    OutsideRepo {
+        /// The offending path.
+        path: PathBuf,
+    },
+    /// A path is already taken.
+    Conflict {
+        /// The taken path.
+        path: PathBuf,
+        /// What is in the way.
+        message: String,
+    },
+    /// Access to a path was denied.
+    PermissionDenied {
+        /// The inaccessible path.
+        path: PathBuf,
+    },
+    /// A path can not be moved or linked across filesystems.
+    CrossDevice {
+        /// The path on the other filesystem.
+        path: PathBuf,
+    },
+    /// The journal of an interrupted run has to be recovered first, or could not be
+    /// written or rolled back.
+    Journal {
+        /// Journal directory.
+        path: PathBuf,
+        /// What went wrong.
+        message: String,
+    },
+    /// Invalid input, such as a wrong passphrase, an undefined template variable, or a
+    /// failing git command or bootstrap script.
+    Invalid {
+        /// The offending path, or the directory the command was run in.
+        path: PathBuf,
+        /// What is wrong.
+        message: String,
+    },
+    /// Any other I/O error.
+    Io {
+        /// The path the error occurred at.
+        path: PathBuf,
+        /// The underlying error.
+        err: io::Error,
+    },
+    /// The home directory of the current user could not be located.
+    HomeNotFound,
+    /// The passphrase is unusable, e.g. as it is empty or was not repeated correctly.
+    Passphrase {
+        /// What is wrong with it.
+        message: String,
+    },
+    /// A hook set to abort on failure failed.
+    Hook {
+        /// Command line of the hook.
+        command: String,
+        /// How it failed.
+        message: String,
+    },
+    /// The report of a command could not be serialized, e.g. as it holds a path that is
+    /// not valid UTF-8.
+    Output {
+        /// What went wrong.
+        message: String,
+    },
+}
+
+impl BadmError {
+    /// Create an [`BadmError::Invalid`] error about `path`.
+    ///
+    /// [`BadmError::Invalid`]: enum.BadmError.html#variant.Invalid
+    pub fn invalid<P: AsRef<Path>, S: Into<String>>(path: P, message: S) -> Self {
+        Self::Invalid {
+            path: path.as_ref().to_path_buf(),
+            message: message.into(),
+        }
+    }
+
+    /// Classify `err`, which occurred at `path`, by its kind.
+    pub fn from_io(err: io::Error, path: &Path) -> Self {
+        let path = path.to_path_buf();
+
+        match err.kind() {
+            io::ErrorKind::PermissionDenied => Self::PermissionDenied { path },
+            io::ErrorKind::CrossesDevices => Self::CrossDevice { path },
+            _ => Self::Io { path, err },
+        }
+    }
+
+    /// The path the error concerns. Empty if it is not known or the error concerns no
+    /// path.
+    pub fn path(&self) -> &Path {
+        match self.path_ref() {
+            Some(path) => path,
+            None => Path::new(""),
+        }
+    }
+
+    fn path_ref(&self) -> Option<&PathBuf> {
+        match self {
+            Self::ConfigNotFound { path }
+            | Self::InvalidConfig { path, .. }
+            | Self::OutsideRepo { path }
+            | Self::Conflict { path, .. }
+            | Self::PermissionDenied { path }
+            | Self::CrossDevice { path }
+            | Self::Journal { path, .. }
+            | Self::Invalid { path, .. }
+            | Self::Io { path, .. } => Some(path),
+            Self::HomeNotFound
+            | Self::Passphrase { .. }
+            | Self::Hook { .. }
+            | Self::Output { .. } => None,
+        }
+    }
+
+    fn path_mut(&mut self) -> Option<&mut PathBuf> {
+        match self {
+            Self::ConfigNotFound { path }
+            | Self::InvalidConfig { path, .. }
+            | Self::OutsideRepo { path }
+            | Self::Conflict { path, .. }
+            | Self::PermissionDenied { path }
+            | Self::CrossDevice { path }
+            | Self::Journal { path, .. }
+            | Self::Invalid { path, .. }
+            | Self::Io { path, .. } => Some(path),
+            Self::HomeNotFound
+            | Self::Passphrase { .. }
+            | Self::Hook { .. }
+            | Self::Output { .. } => None,
+        }
+    }
+
+    /// Set the path of the error to `path`, unless it is already known or the error
+    /// concerns no path.
+    pub fn or_path(mut self, path: &Path) -> Self {
+        match self.path_ref() {
+            Some(known) if known.as_os_str().is_empty() => {},
+            _ => return self,
+        };
+
+        match self {
+            Self::Io { err, .. } => Self::from_io(err, path),
+            _ => {
+                if let Some(known) = self.path_mut() {
+                    *known = path.to_path_buf();
+                };
+                self
+            },
+        }
+    }
+
+    /// Exit code of the process when failing with the error:
+    ///
+    /// | error              | code |
+    /// |--------------------|------|
+    /// | `Io`, `Output`     | 1    |
+    /// | `Invalid`          | 2    |
+    /// | `ConfigNotFound`   | 3    |
+    /// | `InvalidConfig`    | 4    |
+    /// | `OutsideRepo`      | 5    |
+    /// | `Conflict`         | 6    |
+    /// | `PermissionDenied` | 7    |
+    /// | `CrossDevice`      | 8    |
+    /// | `Journal`          | 9    |
+    /// | `HomeNotFound`     | 10   |
+    /// | `Passphrase`       | 11   |
+    /// | `Hook`             | 12   |
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Self::Io { .. } | Self::Output { .. } => 1,
+            Self::Invalid { .. } => 2,
+            Self::ConfigNotFound { .. } => 3,
+            Self::InvalidConfig { .. } => 4,
+            Self::OutsideRepo { .. } => 5,
+            Self::Conflict { .. } => 6,
+            Self::PermissionDenied { .. } => 7,
+            Self::CrossDevice { .. } => 8,
+            Self::Journal { .. } => 9,
+            Self::HomeNotFound => 10,
+            Self::Passphrase { .. } => 11,
+            Self::Hook { .. } => 12,
+        }
+    }
+
+    fn kind(&self) -> io::ErrorKind {
+        match self {
+            Self::ConfigNotFound { .. } | Self::HomeNotFound => io::ErrorKind::NotFound,
+            Self::InvalidConfig { .. } => io::ErrorKind::InvalidData,
+            Self::OutsideRepo { .. } | Self::Invalid { .. } | Self::Passphrase { .. } => {
+                io::ErrorKind::InvalidInput
+            },
+            Self::Conflict { .. } => io::ErrorKind::AlreadyExists,
+            Self::PermissionDenied { .. } => io::ErrorKind::PermissionDenied,
+            Self::CrossDevice { .. } => io::ErrorKind::CrossesDevices,
+            Self::Journal { .. } | Self::Hook { .. } => io::ErrorKind::Other,
+            Self::Output { .. } => io::ErrorKind::InvalidData,
+            Self::Io { err, .. } => err.kind(),
+        }
+    }
+}
+
+impl fmt::Display for BadmError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::ConfigNotFound { path } => write!(
+                f,
+                "no dotfiles directory is set in {:?}, run `badm set-dir <DIR>` first",
+                path
+            ),
+            Self::InvalidConfig { path, message } => {
+                write!(f, "invalid config {:?}: {}", path, message)
+            },
+            Self::OutsideRepo { path } => {
+                write!(f, "{:?} is not located in a dotfiles directory", path)
+            },
+            Self::Conflict { path, message } => write!(f, "{:?} {}", path, message),
+            Self::PermissionDenied { path } => write!(f, "permission denied: {:?}", path),
+            Self::CrossDevice { path } => {
+                write!(f, "{:?} is located on another filesystem", path)
+            },
+            Self::Journal { path, message } => {
+                write!(f, "journal {:?}: {}", path, message)
+            },
+            Self::Invalid { path, message } if path.as_os_str().is_empty() => {
+                write!(f, "{}", message)
+            },
+            Self::Invalid { path, message } => write!(f, "{:?}: {}", path, message),
+            Self::Io { path, err } if path.as_os_str().is_empty() => write!(f, "{}", err),
+            Self::Io { path, err } => write!(f, "{:?}: {}", path, err),
+            Self::HomeNotFound => write!(f, "could not locate the home directory"),
+            Self::Passphrase { message } => write!(f, "passphrase: {}", message),
+            Self::Hook { command, message } => {
+                write!(f, "hook `{}` {}", command, message)
+            },
+            Self::Output { message } => write!(f, "could not write report: {}", message),
+        }
+    }
 }
 
-impl From<io::Error> for InputError {
+impl error::Error for BadmError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Self::Io { err, .. } => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for BadmError {
     fn from(err: io::Error) -> Self {
-        Self::BadInput { err }
+        // errors raised as a BadmError are passed back unchanged
+        if err.get_ref().is_some_and(|inner| inner.is::<Self>()) {
+            if let Some(Ok(err)) = err.into_inner().map(|inner| inner.downcast::<Self>())
+            {
+                return *err;
+            };
+            unreachable!("checked to be a BadmError");
+        };
+        Self::from_io(err, Path::new(""))
     }
 }
 
-impl From<toml::de::Error> for InputError {
+impl From<toml::de::Error> for BadmError {
     fn from(err: toml::de::Error) -> Self {
-        Self::InvalidToml { err }
+        Self::InvalidConfig {
+            path: PathBuf::new(),
+            message: err.to_string(),
+        }
+    }
+}
+
+impl From<BadmError> for io::Error {
+    fn from(err: BadmError) -> Self {
+        match err {
+            BadmError::Io { path, err } if path.as_os_str().is_empty() => err,
+            err => Self::new(err.kind(), err),
+        }
+    }
+}
+
+/// Attaches the path an I/O error occurred at.
+pub trait PathContext<T> {
+    /// Convert an I/O error into a [`BadmError`] about `path`, see
+    /// [`BadmError::from_io`].
+    ///
+    /// [`BadmError`]: enum.BadmError.html
+    /// [`BadmError::from_io`]: enum.BadmError.html#method.from_io
+    fn at_path(self, path: &Path) -> Result<T>;
+}
+
+impl<T> PathContext<T> for io::Result<T> {
+    fn at_path(self, path: &Path) -> Result<T> {
+        self.map_err(|err| BadmError::from(err).or_path(path))
+    }
+}
+
+impl<T> PathContext<T> for Result<T> {
+    fn at_path(self, path: &Path) -> Result<T> {
+        self.map_err(|err| err.or_path(path))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn from_io_test() {
+        let path = Path::new("/home/ferris/.gitconfig");
+
+        let err: io::Result<()> = Err(io::ErrorKind::PermissionDenied.into());
+        let err = err.at_path(path).unwrap_err();
+        assert_eq!(err.exit_code(), 7);
+        assert_eq!(err.path(), path);
+
+        let err = BadmError::from(io::Error::from(io::ErrorKind::CrossesDevices));
+        assert_eq!(err.path(), Path::new(""));
+        assert_eq!(err.or_path(path).exit_code(), 8);
+
+        // converting to an io::Error and back keeps the variant
+        let err = io::Error::from(BadmError::OutsideRepo {
+            path: path.to_path_buf(),
+        });
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        assert_eq!(BadmError::from(err).exit_code(), 5);
+    }
+
+    #[test]
+    fn pathless_test() {
+        let err = BadmError::HomeNotFound.or_path(Path::new("/home/ferris"));
+        assert_eq!(err.path(), Path::new(""));
+        assert_eq!(err.exit_code(), 10);
+        assert_eq!(err.to_string(), "could not locate the home directory");
     }
 }
@@ -0,0 +1,248 @@
+//! Gitignore-style exclude patterns used to keep files out of the dotfiles directory and
+//! away from deployment.
+use std::path::{Component, Path, PathBuf};
+
+use glob::{MatchOptions, Pattern, PatternError};
+
+use crate::commands::BOOTSTRAP_FILE_NAME;
+use crate::config::REPO_CONFIG_FILE_NAME;
+use crate::errors::BadmError;
+use crate::manifest::MANIFEST_FILE_NAME;
+
+/// Name of the file inside the dotfiles directory that holds exclude patterns.
+pub const IGNORE_FILE_NAME: &str = ".badmignore";
+
+const MATCH_OPTIONS: MatchOptions = MatchOptions {
+    case_sensitive: true,
+    require_literal_separator: true,
+    require_literal_leading_dot: false,
+};
+
+#[derive(Debug, Clone)]
+struct ExcludePattern {
+    pattern: Pattern,
+    negated: bool,
+    dir_only: bool,
+    anchored: bool,
+}
+
+impl ExcludePattern {
+    fn parse(line: &str) -> Result<Option<Self>, PatternError> {
+        let line = line.trim_end();
+
+        if line.is_empty() || line.starts_with('#') {
+            return Ok(None);
+        };
+
+        let (negated, line) = match line.strip_prefix('!') {
+            Some(rest) => (true, rest),
+            None => (false, line.strip_prefix('\\').unwrap_or(line)),
+        };
+
+        let (dir_only, line) = match line.strip_suffix('/') {
+            Some(rest) => (true, rest),
+            None => (false, line),
+        };
+
+        // a slash anywhere but the end anchors the pattern to the dotfiles directory
+        let anchored = line.contains('/');
+        let line = line.trim_start_matches('/');
+
+        if line.is_empty() {
+            return Ok(None);
+        };
+
+        Ok(Some(Self {
+            pattern: Pattern::new(line)?,
+            negated,
+            dir_only,
+            anchored,
+        }))
+    }
+
+    fn matches(&self, path: &Path, is_dir: bool) -> bool {
+        if self.dir_only && !is_dir {
+            return false;
+        };
+
+        if self.anchored {
+            self.pattern.matches_path_with(path, MATCH_OPTIONS)
+        } else {
+            path.file_name()
+                .map(|name| {
+                    self.pattern
+                        .matches_with(&name.to_string_lossy(), MATCH_OPTIONS)
+                })
+                .unwrap_or(false)
+        }
+    }
+}
+
+/// Collection of gitignore-style patterns.
+///
+/// Patterns are matched against paths relative to the dotfiles directory, which mirrors
+/// the directory hierarchy starting at `/`. The supported syntax is a subset of
+/// `.gitignore`:
+///
+/// - blank lines and lines starting with `#` are ignored
+/// - a leading `!` re-includes paths excluded by a previous pattern
+/// - a trailing `/` only matches directories
+/// - a pattern containing a `/` is anchored to the top of the dotfiles directory,
+///   otherwise it is matched against the file name at any depth
+/// - `*`, `?`, `[...]` and `**` wildcards are supported
+///
+/// If a directory is excluded, every path below it is excluded as well.
+///
+/// # Examples
+///
+/// ```
+/// use badm::exclude::ExcludePatterns;
+/// use std::path::Path;
+///
+/// let mut patterns = ExcludePatterns::default();
+/// patterns.add("README*").unwrap();
+/// patterns.add("*.swp").unwrap();
+///
+/// assert!(patterns.is_excluded(Path::new("README.md"), false));
+/// assert!(patterns.is_excluded(Path::new("home/ferris/.vimrc.swp"), false));
+/// assert!(!patterns.is_excluded(Path::new("home/ferris/.vimrc"), false));
+/// ```
+#[derive(Debug, Default, Clone)]
+pub struct ExcludePatterns {
+    patterns: Vec<ExcludePattern>,
+}
+
+impl ExcludePatterns {
+    /// Read patterns from the `.badmignore` file located in `dots_dir`. The ignore file
+    /// itself, badm's manifest, the bootstrap script, and the repo-local config file are
+    /// always excluded.
+    pub fn from_dots_dir(dots_dir: &Path) -> Result<Self, BadmError> {
+        let mut patterns = Self::default();
+        let ignore_file = dots_dir.join(IGNORE_FILE_NAME);
+
+        patterns.add_line(&ignore_file, &format!("/{}", IGNORE_FILE_NAME))?;
+        patterns.add_line(&ignore_file, &format!("/{}*", MANIFEST_FILE_NAME))?;
+        patterns.add_line(&ignore_file, &format!("/{}", BOOTSTRAP_FILE_NAME))?;
+        patterns.add_line(&ignore_file, &format!("/{}", REPO_CONFIG_FILE_NAME))?;
+
+        if ignore_file.is_file() {
+            for line in crate::paths::read_path(&ignore_file)?.lines() {
+                patterns.add_line(&ignore_file, line)?;
+            }
+        };
+
+        Ok(patterns)
+    }
+
+    /// Add a single pattern. Blank lines and comments are accepted and ignored.
+    pub fn add(&mut self, pattern: &str) -> Result<(), PatternError> {
+        if let Some(pattern) = ExcludePattern::parse(pattern)? {
+            self.patterns.push(pattern);
+        };
+        Ok(())
+    }
+
+    fn add_line(&mut self, ignore_file: &Path, line: &str) -> Result<(), BadmError> {
+        self.add(line).map_err(|err| {
+            BadmError::invalid(
+                ignore_file,
+                format!("invalid exclude pattern {:?}: {}", line, err.msg),
+            )
+        })
+    }
+
+    /// Returns `true` if no patterns have been added.
+    pub fn is_empty(&self) -> bool {
+        self.patterns.is_empty()
+    }
+
+    /// Check whether `path`, relative to the dotfiles directory, is excluded. `is_dir`
+    /// should be set if `path` is a directory, so that patterns ending in `/` can match.
+    ///
+    /// A leading root directory is ignored, so absolute paths of files that have not yet
+    /// been stored can be checked as well.
+    pub fn is_excluded(&self, path: &Path, is_dir: bool) -> bool {
+        let components: Vec<Component> = path
+            .components()
+            .filter(|c| !matches!(c, Component::RootDir | Component::Prefix(_)))
+            .collect();
+
+        let mut current = PathBuf::new();
+
+        for (i, component) in components.iter().enumerate() {
+            current.push(component);
+
+            let is_last = i == components.len() - 1;
+            let excluded = self.last_match(&current, !is_last || is_dir);
+
+            if excluded {
+                return true;
+            };
+        }
+        false
+    }
+
+    fn last_match(&self, path: &Path, is_dir: bool) -> bool {
+        self.patterns
+            .iter()
+            .rev()
+            .find(|pattern| pattern.matches(path, is_dir))
+            .map(|pattern| !pattern.negated)
+            .unwrap_or(false)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn patterns(lines: &[&str]) -> ExcludePatterns {
+        let mut patterns = ExcludePatterns::default();
+        for line in lines {
+            patterns.add(line).unwrap();
+        }
+        patterns
+    }
+
+    #[test]
+    fn unanchored_pattern_matches_any_depth() {
+        let patterns = patterns(&["*.swp", "LICENSE"]);
+
+        assert!(patterns.is_excluded(Path::new("LICENSE"), false));
+        assert!(patterns.is_excluded(Path::new("home/ferris/.bashrc.swp"), false));
+        assert!(!patterns.is_excluded(Path::new("home/ferris/.bashrc"), false));
+    }
+
+    #[test]
+    fn anchored_pattern_matches_from_root() {
+        let patterns = patterns(&["/README.md", "home/*/.cache/"]);
+
+        assert!(patterns.is_excluded(Path::new("README.md"), false));
+        assert!(!patterns.is_excluded(Path::new("home/ferris/README.md"), false));
+        assert!(patterns.is_excluded(Path::new("/home/ferris/.cache/nvim/log"), false));
+    }
+
+    #[test]
+    fn dir_only_and_negated_patterns() {
+        let patterns = patterns(&["# comment", "", ".github/", "*.md", "!KEEP.md"]);
+
+        assert!(patterns.is_excluded(Path::new(".github/workflows/ci.yml"), false));
+        assert!(!patterns.is_excluded(Path::new("home/ferris/.github"), false));
+        assert!(patterns.is_excluded(Path::new("CHANGELOG.md"), false));
+        assert!(!patterns.is_excluded(Path::new("KEEP.md"), false));
+    }
+
+    #[test]
+    fn from_dots_dir_test() -> Result<(), BadmError> {
+        let dots_dir = tempfile::tempdir()?;
+        std::fs::write(dots_dir.path().join(IGNORE_FILE_NAME), "README*\n")?;
+
+        let patterns = ExcludePatterns::from_dots_dir(dots_dir.path())?;
+
+        assert!(patterns.is_excluded(Path::new(IGNORE_FILE_NAME), false));
+        assert!(patterns.is_excluded(Path::new("README.md"), false));
+        assert!(!patterns.is_excluded(Path::new("home/ferris/.gitconfig"), false));
+
+        Ok(())
+    }
+}
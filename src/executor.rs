@@ -0,0 +1,216 @@
+//! Filesystem actions performed by commands, and the executor that applies or plans them.
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use serde_derive::{Deserialize, Serialize};
+
+use crate::errors::Result;
+use crate::journal::Journal;
+use crate::FileHandler;
+
+/// A single change to the filesystem made by a command.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum Action {
+    /// Create a directory and all of its missing parents.
+    CreateDir {
+        /// Directory to create.
+        path: PathBuf,
+    },
+    /// Move a file.
+    Move {
+        /// Current path of the file.
+        src: PathBuf,
+        /// New path of the file.
+        dst: PathBuf,
+    },
+    /// Create a symlink at `dst` pointing to `src`.
+    Symlink {
+        /// Path the symlink points to.
+        src: PathBuf,
+        /// Path of the symlink.
+        dst: PathBuf,
+    },
+    /// Create a hard link at `dst` to the file at `src`.
+    HardLink {
+        /// Path of the linked file.
+        src: PathBuf,
+        /// Path of the new link.
+        dst: PathBuf,
+    },
+    /// Remove a file, symlink, or directory tree.
+    Remove {
+        /// Path to remove.
+        path: PathBuf,
+    },
+    /// Write a file, replacing any file at `path`.
+    Write {
+        /// Path of the file.
+        path: PathBuf,
+        /// Content of the file, which is not recorded in journals or reports.
+        #[serde(skip)]
+        contents: Vec<u8>,
+        /// Unix permission bits of the file, e.g. `0o600`, or `None` for the default
+        /// permissions.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        mode: Option<u32>,
+    },
+    /// Set the Unix permission bits of a file, leaving its content untouched.
+    SetPermissions {
+        /// Path of the file.
+        path: PathBuf,
+        /// Unix permission bits of the file, e.g. `0o600`.
+        mode: u32,
+    },
+}
+
+impl fmt::Display for Action {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::CreateDir { path } => write!(f, "mkdir   {:?}", path),
+            Self::Move { src, dst } => write!(f, "move    {:?} -> {:?}", src, dst),
+            Self::Symlink { src, dst } => write!(f, "symlink {:?} -> {:?}", dst, src),
+            Self::HardLink { src, dst } => write!(f, "link    {:?} -> {:?}", dst, src),
+            Self::Remove { path } => write!(f, "remove  {:?}", path),
+            Self::Write { path, .. } => write!(f, "write   {:?}", path),
+            Self::SetPermissions { path, mode } => {
+                write!(f, "chmod   {:?} {:o}", path, mode)
+            },
+        }
+    }
+}
+
+/// Applies the actions of commands to the filesystem, or only records them when running
+/// in dry-run mode.
+///
+/// Every action passed to [`Executor::run`] is recorded, so the actions taken by a
+/// command, or that would have been taken, can be listed afterwards.
+///
+/// If a [`Journal`] is attached, each action is recorded in it before being applied, so
+/// the changes made by a failed batch can be rolled back.
+///
+/// [`Executor::run`]: struct.Executor.html#method.run
+/// [`Journal`]: ../journal/struct.Journal.html
+#[derive(Debug, Default, Clone)]
+pub struct Executor {
+    dry_run: bool,
+    actions: Vec<Action>,
+    journal: Option<Journal>,
+}
+
+impl Executor {
+    /// Create an executor that applies actions to the filesystem.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create an executor that only records actions without touching the filesystem.
+    pub fn dry_run() -> Self {
+        Self {
+            dry_run: true,
+            ..Self::default()
+        }
+    }
+
+    /// Builder method to record every action in `journal` before applying it. Has no
+    /// effect in dry-run mode.
+    pub fn journal(mut self, journal: Journal) -> Self {
+        if !self.dry_run {
+            self.journal = Some(journal);
+        };
+        self
+    }
+
+    /// Returns `true` if actions are only recorded.
+    pub fn is_dry_run(&self) -> bool {
+        self.dry_run
+    }
+
+    /// Apply `action`, unless running in dry-run mode, and record it.
+    pub fn run(&mut self, action: Action) -> Result<()> {
+        if !self.dry_run {
+            match &mut self.journal {
+                Some(journal) => journal.apply(&action)?,
+                None => FileHandler::apply(&action)?,
+            };
+        };
+
+        self.actions.push(action);
+        Ok(())
+    }
+
+    /// Preserve the file at `path` in the journal before it is rewritten outside of an
+    /// action, as is done for manifests.
+    pub fn preserve(&mut self, path: &Path) -> Result<()> {
+        match &mut self.journal {
+            Some(journal) if !self.dry_run => journal.preserve(path),
+            _ => Ok(()),
+        }
+    }
+
+    /// Complete the batch, discarding the journal.
+    pub fn commit(&mut self) -> Result<()> {
+        match self.journal.take() {
+            Some(journal) => journal.commit(),
+            None => Ok(()),
+        }
+    }
+
+    /// Revert the changes recorded in the journal. Without a journal, nothing is
+    /// reverted.
+    pub fn rollback(&mut self) -> Result<()> {
+        match self.journal.take() {
+            Some(journal) => journal.rollback(),
+            None => Ok(()),
+        }
+    }
+
+    /// Actions applied, or planned in dry-run mode, so far.
+    pub fn actions(&self) -> &[Action] {
+        &self.actions
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn dry_run_test() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let src = dir.path().join("src");
+        let dst = dir.path().join("nested/dst");
+        fs::write(&src, "")?;
+
+        let actions = [
+            Action::CreateDir {
+                path: dst.parent().unwrap().to_path_buf(),
+            },
+            Action::Move {
+                src: src.clone(),
+                dst: dst.clone(),
+            },
+            Action::Remove { path: dst.clone() },
+        ];
+
+        let mut executor = Executor::dry_run();
+        for action in actions.iter().cloned() {
+            executor.run(action)?;
+        }
+
+        assert_eq!(executor.actions(), &actions);
+        assert!(src.exists());
+        assert!(!dst.parent().unwrap().exists());
+
+        let mut executor = Executor::new();
+        for action in actions.iter().take(2).cloned() {
+            executor.run(action)?;
+        }
+
+        assert!(!src.exists());
+        assert!(dst.exists());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,185 @@
+//! Runs git inside a dotfiles directory, which is usually a git repository.
+//!
+//! The `git` executable found on the `PATH` is used, so the user's git configuration,
+//! credentials, and hooks apply as usual.
+use std::ffi::OsStr;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::{Command, ExitStatus};
+
+use crate::errors::{BadmError, Result};
+
+/// A git working tree, usually a dotfiles directory.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Git {
+    dir: PathBuf,
+}
+
+impl Git {
+    /// Run git inside of `dir`.
+    pub fn new(dir: &Path) -> Self {
+        Self {
+            dir: dir.to_path_buf(),
+        }
+    }
+
+    /// Clone the repository at `url`, which may also be a local path, into `dir`.
+    pub fn clone(url: &str, dir: &Path) -> Result<Self> {
+        let output = Command::new("git")
+            .args(["clone", "--quiet", url])
+            .arg(dir)
+            .output()
+            .map_err(|err| not_run(dir, &err))?;
+
+        if !output.status.success() {
+            return Err(BadmError::invalid(
+                dir,
+                format!(
+                    "could not clone {:?}: {}",
+                    url,
+                    String::from_utf8_lossy(&output.stderr).trim()
+                ),
+            ));
+        };
+
+        Ok(Self::new(dir))
+    }
+
+    /// Directory git is run in.
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Returns `true` if the directory is the top of a git working tree.
+    pub fn is_repo(&self) -> bool {
+        self.dir.join(".git").exists()
+    }
+
+    /// Run git with `args`, passing through stdin, stdout, and stderr. Returns git's exit
+    /// status.
+    pub fn status<I, S>(&self, args: I) -> Result<ExitStatus>
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<OsStr>,
+    {
+        self.command(args)
+            .status()
+            .map_err(|err| not_run(&self.dir, &err))
+    }
+
+    /// Run git with `args` and return its standard output. Fails with git's error message
+    /// if git exits unsuccessfully.
+    pub fn output<I, S>(&self, args: I) -> Result<String>
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<OsStr>,
+    {
+        let output = self
+            .command(args)
+            .output()
+            .map_err(|err| not_run(&self.dir, &err))?;
+
+        if !output.status.success() {
+            return Err(BadmError::invalid(
+                &self.dir,
+                format!(
+                    "git failed: {}",
+                    String::from_utf8_lossy(&output.stderr).trim()
+                ),
+            ));
+        };
+
+        Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+    }
+
+    /// Commit the changes to `paths`, relative to the directory, with `message`. Changes
+    /// to other paths, whether staged or not, are left alone. Returns `false` if none of
+    /// the paths changed.
+    pub fn commit(&self, message: &str, paths: &[PathBuf]) -> Result<bool> {
+        // paths that were removed before ever being committed are unknown to git
+        let mut paths = paths.to_vec();
+        paths.retain(|path| self.dir.join(path).exists() || self.is_tracked(path));
+
+        if paths.is_empty() {
+            return Ok(false);
+        };
+
+        let _ = self.output(args(&["add", "--all", "--"], &paths))?;
+
+        let unchanged = self
+            .status(args(&["diff", "--cached", "--quiet", "--"], &paths))?
+            .success();
+        if unchanged {
+            return Ok(false);
+        };
+
+        let _ = self.output(args(&["commit", "--quiet", "-m", message, "--"], &paths))?;
+        Ok(true)
+    }
+
+    /// Fetch and rebase onto the upstream branch, stashing uncommitted changes, such as
+    /// an updated manifest, in the meantime.
+    pub fn pull(&self) -> Result<()> {
+        self.output(["pull", "--quiet", "--rebase", "--autostash"])
+            .map(|_| ())
+    }
+
+    /// Push the current branch to its upstream branch.
+    pub fn push(&self) -> Result<()> {
+        self.output(["push", "--quiet"]).map(|_| ())
+    }
+
+    fn is_tracked(&self, path: &Path) -> bool {
+        self.output(args(&["ls-files", "--"], &[path.to_path_buf()]))
+            .map(|files| !files.is_empty())
+            .unwrap_or(false)
+    }
+
+    fn command<I, S>(&self, args: I) -> Command
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<OsStr>,
+    {
+        let mut command = Command::new("git");
+        let _ = command.current_dir(&self.dir).args(args);
+        command
+    }
+}
+
+fn not_run(dir: &Path, err: &io::Error) -> BadmError {
+    BadmError::invalid(dir, format!("could not run git: {}", err))
+}
+
+fn args<'a>(args: &'a [&'a str], paths: &'a [PathBuf]) -> Vec<&'a OsStr> {
+    args.iter()
+        .map(OsStr::new)
+        .chain(paths.iter().map(|path| path.as_os_str()))
+        .collect()
+}
+
+/// Generate a commit message for the changes `command` made to `paths`, e.g.
+/// `badm stow: 2 dotfiles` followed by a list of the paths.
+///
+/// # Examples
+///
+/// ```
+/// use badm::git::commit_message;
+/// use std::path::PathBuf;
+///
+/// assert_eq!(
+///     commit_message("stow", &[PathBuf::from("home/ferris/.gitconfig")]),
+///     "badm stow: home/ferris/.gitconfig"
+/// );
+/// ```
+pub fn commit_message(command: &str, paths: &[PathBuf]) -> String {
+    match paths {
+        [path] => format!("badm {}: {}", command, path.display()),
+        paths => {
+            let mut message = format!("badm {}: {} dotfiles\n", command, paths.len());
+            for path in paths {
+                message.push_str(&format!("\n- {}", path.display()));
+            }
+            message
+        },
+    }
+}
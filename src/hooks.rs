@@ -0,0 +1,279 @@
+//! Shell commands run before and after `stow`, `deploy`, and `restore`.
+//!
+//! Hooks are configured in `.badm.toml`, see [`Config`]:
+//!
+//! ```toml
+//! [[hook]]
+//! on = "post-deploy"
+//! run = "fc-cache -f"
+//! paths = ["~/.local/share/fonts/**"]
+//!
+//! [[hook]]
+//! on = "pre-stow"
+//! run = "git -C ~/.dots pull --ff-only"
+//! abort_on_failure = true
+//! ```
+//!
+//! A hook without `paths` runs every time its command runs. Otherwise it only runs if one
+//! of the paths affected by the command, or one of their parent directories, matches one
+//! of the globs; a leading `~` stands for the home directory. Hooks are run with `sh -c`
+//! and receive the following environment variables:
+//!
+//! - `BADM_HOOK` - event the hook runs on, e.g. `post-deploy`
+//! - `BADM_PATHS` - affected paths matching the hook's globs, separated by newlines
+//!
+//! A failing hook is reported and otherwise ignored, unless `abort_on_failure` is set, in
+//! which case the command fails and its changes are rolled back.
+//!
+//! [`Config`]: ../struct.Config.html
+use std::fmt;
+use std::io;
+use std::path::PathBuf;
+use std::process::{Command, ExitStatus};
+
+use glob::Pattern;
+use serde_derive::{Deserialize, Serialize};
+
+use crate::errors::{BadmError, Result};
+use crate::executor::Executor;
+use crate::manifest::is_false;
+use crate::report::Report;
+
+/// Point in a command at which a hook runs.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Copy, Clone)]
+#[serde(rename_all = "kebab-case")]
+pub enum Event {
+    /// Before files are stored.
+    PreStow,
+    /// After files were stored.
+    PostStow,
+    /// Before dotfiles are deployed.
+    PreDeploy,
+    /// After dotfiles were deployed.
+    PostDeploy,
+    /// Before dotfiles are restored.
+    PreRestore,
+    /// After dotfiles were restored.
+    PostRestore,
+}
+
+impl fmt::Display for Event {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Self::PreStow => "pre-stow",
+            Self::PostStow => "post-stow",
+            Self::PreDeploy => "pre-deploy",
+            Self::PostDeploy => "post-deploy",
+            Self::PreRestore => "pre-restore",
+            Self::PostRestore => "post-restore",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// A shell command run on an [`Event`].
+///
+/// [`Event`]: enum.Event.html
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+pub struct Hook {
+    /// Event the hook runs on.
+    pub on: Event,
+    /// Shell command to run.
+    pub run: String,
+    /// Globs selecting the paths the hook runs for. If empty, the hook runs for all
+    /// paths.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub paths: Vec<String>,
+    /// Whether a failure of the hook fails the command it runs for.
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub abort_on_failure: bool,
+}
+
+impl Hook {
+    /// Get the paths of `paths` the hook runs for. A path is selected if it, or one of
+    /// its parent directories, matches one of the hook's globs.
+    pub fn matching_paths<'a>(&self, paths: &'a [PathBuf]) -> Vec<&'a PathBuf> {
+        if self.paths.is_empty() {
+            return paths.iter().collect();
+        };
+
+        let patterns: Vec<Pattern> = self
+            .paths
+            .iter()
+            .filter_map(|glob| Pattern::new(&expand_home(glob)).ok())
+            .collect();
+
+        paths
+            .iter()
+            .filter(|path| {
+                path.ancestors()
+                    .any(|path| patterns.iter().any(|pattern| pattern.matches_path(path)))
+            })
+            .collect()
+    }
+
+    /// Run the hook for `paths` on `event`. The hook's standard output is passed to
+    /// standard error, so it never ends up in the middle of the `--format json` report.
+    pub fn run(&self, event: Event, paths: &[&PathBuf]) -> Result<ExitStatus> {
+        let paths: Vec<String> = paths
+            .iter()
+            .map(|path| path.to_string_lossy().into_owned())
+            .collect();
+
+        let status = Command::new("sh")
+            .arg("-c")
+            .arg(&self.run)
+            .env("BADM_HOOK", event.to_string())
+            .env("BADM_PATHS", paths.join("\n"))
+            .stdout(io::stderr())
+            .status()?;
+        Ok(status)
+    }
+}
+
+/// Run the hooks of `hooks` registered for `event` that select any of `paths`, in order.
+/// In dry-run mode, the hooks are only listed as messages of `report`.
+///
+/// Failing hooks are recorded as warnings of `report`. Fails on the first failing hook
+/// that has `abort_on_failure` set.
+pub fn run_hooks(
+    hooks: &[Hook],
+    event: Event,
+    paths: &[PathBuf],
+    exec: &Executor,
+    report: &mut Report,
+) -> Result<()> {
+    for hook in hooks.iter().filter(|hook| hook.on == event) {
+        let matching_paths = hook.matching_paths(paths);
+
+        if matching_paths.is_empty() {
+            continue;
+        };
+
+        if exec.is_dry_run() {
+            report.message(format!("[dry-run] {} hook `{}`", event, hook.run));
+            continue;
+        };
+
+        let message = match hook.run(event, &matching_paths) {
+            Ok(status) if status.success() => continue,
+            Ok(status) => format!("failed on {} with {}", event, status),
+            Err(err) => format!("could not be run on {}: {}", event, err),
+        };
+        let err = BadmError::Hook {
+            command: hook.run.clone(),
+            message,
+        };
+
+        if hook.abort_on_failure {
+            return Err(err);
+        };
+        report.warning(err.to_string());
+    }
+    Ok(())
+}
+
+/// Replace a leading `~` in `glob` with the home directory.
+pub(crate) fn expand_home(glob: &str) -> String {
+    match (glob.strip_prefix('~'), dirs::home_dir()) {
+        (Some(rest), Some(home)) if rest.is_empty() || rest.starts_with('/') => {
+            format!("{}{}", Pattern::escape(&home.to_string_lossy()), rest)
+        },
+        _ => glob.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn hook(run: &str, paths: &[&str]) -> Hook {
+        Hook {
+            on: Event::PostDeploy,
+            run: run.to_string(),
+            paths: paths.iter().map(|glob| glob.to_string()).collect(),
+            abort_on_failure: false,
+        }
+    }
+
+    #[test]
+    fn matching_paths_test() {
+        let paths = vec![
+            PathBuf::from("/home/ferris/.tmux.conf"),
+            PathBuf::from("/home/ferris/.local/share/fonts/FiraCode.ttf"),
+            PathBuf::from("/home/ferris/.config/fontconfig"),
+        ];
+
+        assert_eq!(hook("true", &[]).matching_paths(&paths).len(), 3);
+        assert_eq!(
+            hook("true", &["/home/*/.tmux.conf"]).matching_paths(&paths),
+            vec![&paths[0]]
+        );
+        assert_eq!(
+            hook("true", &["/home/ferris/.local/share/fonts"]).matching_paths(&paths),
+            vec![&paths[1]]
+        );
+        assert!(hook("true", &["/etc/**"]).matching_paths(&paths).is_empty());
+    }
+
+    #[test]
+    fn run_hooks_test() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let log = dir.path().join("log");
+        let paths = vec![PathBuf::from("/a"), PathBuf::from("/b")];
+
+        let mut report = Report::new("deploy", false);
+        let mut hooks = vec![
+            hook(
+                &format!("echo \"$BADM_HOOK\" \"$BADM_PATHS\" > {:?}", log),
+                &["/b"],
+            ),
+            hook("exit 1", &[]),
+        ];
+        run_hooks(
+            &hooks,
+            Event::PostDeploy,
+            &paths,
+            &Executor::new(),
+            &mut report,
+        )?;
+        assert_eq!(fs::read_to_string(&log)?, "post-deploy /b\n");
+        assert_eq!(report.warnings.len(), 1);
+
+        run_hooks(
+            &hooks,
+            Event::PreDeploy,
+            &paths,
+            &Executor::new(),
+            &mut report,
+        )?;
+        run_hooks(
+            &hooks,
+            Event::PostDeploy,
+            &paths,
+            &Executor::dry_run(),
+            &mut report,
+        )?;
+        assert_eq!(report.messages.len(), 2);
+
+        hooks[1].abort_on_failure = true;
+        let exec = Executor::new();
+        let err =
+            run_hooks(&hooks, Event::PostDeploy, &paths, &exec, &mut report).unwrap_err();
+        assert_eq!(err.exit_code(), 12);
+
+        Ok(())
+    }
+
+    #[test]
+    fn config_test() {
+        let hook: Hook = toml::from_str(
+            "on = \"post-deploy\"\nrun = \"fc-cache -f\"\npaths = [\"~/.fonts/**\"]\n",
+        )
+        .unwrap();
+
+        assert_eq!(hook.on, Event::PostDeploy);
+        assert!(!hook.abort_on_failure);
+    }
+}
@@ -0,0 +1,522 @@
+//! Write-ahead journal of the filesystem changes made by a batch of commands.
+//!
+//! Before an [`Action`] is applied, it is appended to `journal.jsonl` inside the journal
+//! directory, `~/.badm-journal` by default. Files that an action removes or replaces, as
+//! well as manifests that are about to be rewritten, are first preserved inside the
+//! journal directory. If the batch fails, or a previous run was interrupted, every
+//! recorded change can be reverted in reverse order with [`Journal::rollback`]. Once a
+//! batch has completed, [`Journal::commit`] removes the journal directory.
+//!
+//! [`Action`]: ../executor/enum.Action.html
+//! [`Journal::rollback`]: struct.Journal.html#method.rollback
+//! [`Journal::commit`]: struct.Journal.html#method.commit
+use std::fmt;
+use std::fs::{self, File, OpenOptions};
+use std::io::{prelude::*, BufReader};
+use std::path::{Path, PathBuf};
+
+use serde_derive::{Deserialize, Serialize};
+
+use crate::errors::{BadmError, PathContext, Result};
+use crate::executor::Action;
+use crate::manifest::timestamp;
+use crate::paths::{is_hard_linked, is_symlink};
+use crate::FileHandler;
+
+/// Name of the journal directory located in the home directory.
+pub const JOURNAL_DIR_NAME: &str = ".badm-journal";
+
+const JOURNAL_FILE_NAME: &str = "journal.jsonl";
+
+/// A single record of the journal.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+#[serde(tag = "entry", rename_all = "snake_case")]
+pub enum Entry {
+    /// Start of a batch.
+    Begin {
+        /// Command line arguments the batch was started with.
+        args: Vec<String>,
+        /// Unix timestamp of when the batch was started.
+        started_at: u64,
+    },
+    /// An action that is about to be applied.
+    Apply {
+        /// The recorded action.
+        action: Action,
+    },
+    /// A path whose current state is preserved inside the journal directory before it
+    /// is removed or rewritten.
+    Preserve {
+        /// Preserved path.
+        path: PathBuf,
+        /// Location of the preserved copy, or `None` if nothing existed at `path`.
+        copy: Option<PathBuf>,
+    },
+    /// The Unix permission bits of a path before they are changed.
+    Permissions {
+        /// Path whose permissions are changed.
+        path: PathBuf,
+        /// Previous permission bits.
+        mode: u32,
+    },
+}
+
+/// Journal of the changes made by a single batch.
+#[derive(Debug, Clone)]
+pub struct Journal {
+    dir: PathBuf,
+    entries: Vec<Entry>,
+}
+
+impl Journal {
+    /// Get the default journal directory, `~/.badm-journal`.
+    pub fn default_dir() -> Option<PathBuf> {
+        dirs::home_dir().map(|home| home.join(JOURNAL_DIR_NAME))
+    }
+
+    /// Start a new journal in `dir` for a batch run with `args`.
+    ///
+    /// Fails if `dir` already holds the journal of an interrupted batch, which has to be
+    /// recovered first.
+    pub fn begin(dir: &Path, args: Vec<String>) -> Result<Self> {
+        if dir.join(JOURNAL_FILE_NAME).exists() {
+            return Err(BadmError::Journal {
+                path: dir.to_path_buf(),
+                message: "found the journal of an interrupted run, run `badm recover` \
+                          first"
+                    .to_string(),
+            });
+        };
+
+        fs::create_dir_all(dir).map_err(|err| journal_error(dir, &err))?;
+
+        let mut journal = Self {
+            dir: dir.to_path_buf(),
+            entries: vec![],
+        };
+        journal.append(Entry::Begin {
+            args,
+            started_at: timestamp(),
+        })?;
+
+        Ok(journal)
+    }
+
+    /// Open the journal left behind in `dir` by an interrupted batch. Returns `None` if
+    /// there is nothing to recover.
+    ///
+    /// A partially written last entry, as left by a crash, is ignored.
+    pub fn open(dir: &Path) -> Result<Option<Self>> {
+        let path = dir.join(JOURNAL_FILE_NAME);
+
+        if !path.exists() {
+            return Ok(None);
+        };
+
+        let file = File::open(&path).map_err(|err| journal_error(dir, &err))?;
+
+        let mut entries = vec![];
+        for line in BufReader::new(file).lines() {
+            let line = line.map_err(|err| journal_error(dir, &err))?;
+
+            match serde_json::from_str(&line) {
+                Ok(entry) => entries.push(entry),
+                Err(_) => break,
+            }
+        }
+
+        Ok(Some(Self {
+            dir: dir.to_path_buf(),
+            entries,
+        }))
+    }
+
+    /// Command line arguments the batch was started with.
+    pub fn args(&self) -> &[String] {
+        match self.entries.first() {
+            Some(Entry::Begin { args, .. }) => args,
+            _ => &[],
+        }
+    }
+
+    /// Entries recorded so far.
+    pub fn entries(&self) -> &[Entry] {
+        &self.entries
+    }
+
+    fn append(&mut self, entry: Entry) -> Result<()> {
+        let mut line = serde_json::to_string(&entry)
+            .map_err(|err| journal_error(&self.dir, &err))?;
+        line.push('\n');
+
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(self.dir.join(JOURNAL_FILE_NAME))
+            .and_then(|mut file| {
+                file.write_all(line.as_bytes())?;
+                file.sync_data()
+            })
+            .map_err(|err| journal_error(&self.dir, &err))?;
+
+        self.entries.push(entry);
+        Ok(())
+    }
+
+    /// Record and apply `action`. Removed paths are moved into the journal directory
+    /// instead, so they can be brought back on rollback, and files about to be written
+    /// or replaced by a move are preserved first, as are the permissions of files about
+    /// to be changed.
+    pub fn apply(&mut self, action: &Action) -> Result<()> {
+        match action {
+            Action::Remove { path } => self.preserve_path(path, true),
+            action => {
+                match action {
+                    Action::Write { path, .. } => self.preserve(path)?,
+                    Action::Move { dst, .. } => self.preserve(dst)?,
+                    Action::SetPermissions { path, .. } => {
+                        self.preserve_permissions(path)?
+                    },
+                    _ => {},
+                };
+
+                self.append(Entry::Apply {
+                    action: action.clone(),
+                })?;
+                FileHandler::apply(action)
+            },
+        }
+    }
+
+    /// Preserve a copy of the file at `path`, which is about to be rewritten. Only the
+    /// first call for each path preserves a copy, so rollback restores the state from
+    /// before the batch.
+    pub fn preserve(&mut self, path: &Path) -> Result<()> {
+        let preserved = self.entries.iter().any(|entry| match entry {
+            Entry::Preserve {
+                path: preserved, ..
+            } => preserved == path,
+            _ => false,
+        });
+
+        if preserved {
+            return Ok(());
+        };
+
+        self.preserve_path(path, false)
+    }
+
+    fn preserve_permissions(&mut self, path: &Path) -> Result<()> {
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+
+            let mode = fs::metadata(path).at_path(path)?.permissions().mode() & 0o7777;
+            self.append(Entry::Permissions {
+                path: path.to_path_buf(),
+                mode,
+            })
+        }
+        #[cfg(not(unix))]
+        {
+            // permissions are not changed on other platforms
+            let _ = path;
+            Ok(())
+        }
+    }
+
+    fn preserve_path(&mut self, path: &Path, remove: bool) -> Result<()> {
+        let exists = path.exists() || is_symlink(path);
+        let copy = if exists {
+            Some(self.dir.join(self.entries.len().to_string()))
+        } else {
+            None
+        };
+
+        self.append(Entry::Preserve {
+            path: path.to_path_buf(),
+            copy: copy.clone(),
+        })?;
+
+        match copy {
+            Some(copy) if remove => FileHandler::move_file(path, &copy),
+            Some(copy) => copy_path(path, &copy),
+            None => Ok(()),
+        }
+    }
+
+    /// Revert every recorded change in reverse order, then remove the journal directory.
+    ///
+    /// Changes that were recorded but never applied, as happens when a batch is
+    /// interrupted, are skipped. If a change can not be reverted, the journal is kept so
+    /// that rollback can be retried.
+    pub fn rollback(self) -> Result<()> {
+        for entry in self.entries.iter().rev() {
+            let result = match entry {
+                Entry::Begin { .. } => Ok(()),
+                Entry::Apply { action } => revert(action),
+                Entry::Preserve { path, copy } => match copy {
+                    Some(copy) if copy.exists() || is_symlink(copy) => {
+                        // a directory can not be renamed over whatever replaced it
+                        if copy.is_dir() && !is_symlink(copy) && path.exists() {
+                            FileHandler::remove(path)?;
+                        };
+                        FileHandler::move_file(copy, path)
+                    },
+                    None if path.is_file() => fs::remove_file(path).at_path(path),
+                    _ => Ok(()),
+                },
+                Entry::Permissions { path, mode } if path.exists() => {
+                    FileHandler::set_permissions(path, *mode)
+                },
+                Entry::Permissions { .. } => Ok(()),
+            };
+
+            result.map_err(|err| BadmError::Journal {
+                path: self.dir.clone(),
+                message: format!("could not roll back: {}", err),
+            })?;
+        }
+
+        self.commit()
+    }
+
+    /// Mark the batch as completed by removing the journal directory.
+    pub fn commit(self) -> Result<()> {
+        fs::remove_dir_all(&self.dir).map_err(|err| journal_error(&self.dir, &err))
+    }
+}
+
+fn journal_error<E: fmt::Display>(dir: &Path, err: &E) -> BadmError {
+    BadmError::Journal {
+        path: dir.to_path_buf(),
+        message: err.to_string(),
+    }
+}
+
+/// Copy the file, symlink, or directory tree at `path` to `copy`. Symlinks are recreated
+/// rather than followed, so dangling links are preserved as well.
+fn copy_path(path: &Path, copy: &Path) -> Result<()> {
+    let file_type = fs::symlink_metadata(path).at_path(path)?.file_type();
+
+    if file_type.is_symlink() {
+        let link = fs::read_link(path).at_path(path)?;
+        FileHandler::create_symlink(&link, copy)
+    } else if file_type.is_dir() {
+        FileHandler::copy_dir(path, copy)
+    } else {
+        FileHandler::copy_file(path, copy)
+    }
+}
+
+/// Revert `action` if it was applied.
+fn revert(action: &Action) -> Result<()> {
+    match action {
+        Action::CreateDir { path } => {
+            // only empty directories are removed, anything else was there before
+            let _ = fs::remove_dir(path);
+        },
+        Action::Move { src, dst } => {
+            let moved =
+                !(src.exists() || is_symlink(src)) && (dst.exists() || is_symlink(dst));
+            if moved {
+                FileHandler::move_file(dst, src)?;
+            };
+        },
+        Action::Symlink { src, dst } => {
+            if is_symlink(dst) && fs::read_link(dst).at_path(dst)? == *src {
+                fs::remove_file(dst).at_path(dst)?;
+            };
+        },
+        Action::HardLink { src, dst } => {
+            if is_hard_linked(src, dst) {
+                fs::remove_file(dst).at_path(dst)?;
+            };
+        },
+        // reverted by restoring the preserved paths and permissions
+        Action::Remove { .. } | Action::Write { .. } | Action::SetPermissions { .. } => {
+        },
+    };
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rollback_test() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let dir = dir.path();
+        let journal_dir = dir.join("journal");
+
+        let file = dir.join("file");
+        let moved = dir.join("nested/file");
+        let removed = dir.join("removed");
+        let manifest = dir.join("manifest");
+        fs::write(&file, "file")?;
+        fs::write(&removed, "removed")?;
+        fs::write(&manifest, "before")?;
+
+        let mut journal = Journal::begin(&journal_dir, vec!["badm".to_string()])?;
+        assert!(Journal::begin(&journal_dir, vec![]).is_err());
+
+        journal.apply(&Action::CreateDir {
+            path: dir.join("nested"),
+        })?;
+        journal.apply(&Action::Move {
+            src: file.clone(),
+            dst: moved.clone(),
+        })?;
+        journal.apply(&Action::Symlink {
+            src: moved.clone(),
+            dst: file.clone(),
+        })?;
+        journal.apply(&Action::HardLink {
+            src: moved.clone(),
+            dst: dir.join("linked"),
+        })?;
+        journal.apply(&Action::Remove {
+            path: removed.clone(),
+        })?;
+        journal.preserve(&manifest)?;
+        fs::write(&manifest, "after")?;
+        journal.preserve(&manifest)?;
+        fs::write(&manifest, "after again")?;
+
+        assert!(!removed.exists());
+
+        let journal = Journal::open(&journal_dir)?.expect("journal not found");
+        assert_eq!(journal.args(), ["badm"]);
+        journal.rollback()?;
+
+        assert!(!is_symlink(&file));
+        assert_eq!(fs::read_to_string(&file)?, "file");
+        assert!(!dir.join("nested").exists());
+        assert!(!dir.join("linked").exists());
+        assert_eq!(fs::read_to_string(&removed)?, "removed");
+        assert_eq!(fs::read_to_string(&manifest)?, "before");
+        assert!(Journal::open(&journal_dir)?.is_none());
+
+        Ok(())
+    }
+
+    #[test]
+    fn rollback_move_test() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let dir = dir.path();
+        let journal_dir = dir.join("journal");
+
+        let file = dir.join("file");
+        let stored = dir.join("stored");
+        fs::write(&file, "new")?;
+        fs::write(&stored, "old")?;
+
+        let mut journal = Journal::begin(&journal_dir, vec![])?;
+        journal.apply(&Action::Move {
+            src: file.clone(),
+            dst: stored.clone(),
+        })?;
+        assert_eq!(fs::read_to_string(&stored)?, "new");
+        journal.rollback()?;
+
+        assert_eq!(fs::read_to_string(&file)?, "new");
+        assert_eq!(fs::read_to_string(&stored)?, "old");
+
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn rollback_write_over_symlink_test() -> Result<()> {
+        use std::os::unix::fs::symlink;
+
+        let dir = tempfile::tempdir()?;
+        let dir = dir.path();
+        let journal_dir = dir.join("journal");
+
+        let target = dir.join("target");
+        let linked = dir.join("linked");
+        let dangling = dir.join("dangling");
+        fs::write(&target, "target")?;
+        symlink(&target, &linked)?;
+        symlink(dir.join("missing"), &dangling)?;
+
+        let mut journal = Journal::begin(&journal_dir, vec![])?;
+        for path in [&linked, &dangling] {
+            journal.apply(&Action::Write {
+                path: path.clone(),
+                contents: b"written".to_vec(),
+                mode: None,
+            })?;
+            assert!(!is_symlink(path));
+        }
+        journal.rollback()?;
+
+        assert_eq!(fs::read_link(&linked)?, target);
+        assert_eq!(fs::read_link(&dangling)?, dir.join("missing"));
+        assert_eq!(fs::read_to_string(&target)?, "target");
+
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn rollback_set_permissions_test() -> Result<()> {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir()?;
+        let dir = dir.path();
+        let journal_dir = dir.join("journal");
+
+        let file = dir.join("file");
+        fs::write(&file, "file")?;
+        fs::set_permissions(&file, fs::Permissions::from_mode(0o640))?;
+
+        let mut journal = Journal::begin(&journal_dir, vec![])?;
+        for mode in [0o600, 0o755] {
+            journal.apply(&Action::SetPermissions {
+                path: file.clone(),
+                mode,
+            })?;
+        }
+        assert_eq!(fs::metadata(&file)?.permissions().mode() & 0o777, 0o755);
+        journal.rollback()?;
+
+        assert_eq!(fs::metadata(&file)?.permissions().mode() & 0o777, 0o640);
+
+        Ok(())
+    }
+
+    #[test]
+    fn rollback_skips_unapplied_actions_test() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let dir = dir.path();
+        let journal_dir = dir.join("journal");
+
+        let file = dir.join("file");
+        fs::write(&file, "file")?;
+
+        let mut journal = Journal::begin(&journal_dir, vec![])?;
+        journal.append(Entry::Apply {
+            action: Action::Move {
+                src: file.clone(),
+                dst: dir.join("moved"),
+            },
+        })?;
+
+        // simulate a crash while writing the next entry
+        let mut log = OpenOptions::new()
+            .append(true)
+            .open(journal_dir.join(JOURNAL_FILE_NAME))?;
+        log.write_all(b"{\"entry\":\"app")?;
+
+        let journal = Journal::open(&journal_dir)?.expect("journal not found");
+        assert_eq!(journal.entries().len(), 2);
+        journal.rollback()?;
+
+        assert_eq!(fs::read_to_string(&file)?, "file");
+
+        Ok(())
+    }
+}
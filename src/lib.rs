@@ -10,9 +10,9 @@
 //!
 //! - ferris has created a directory to store their dotfiles at `~/.dots`
 //! - `badm set-dir ~/.dots` sets the BADM dotfiles dir at `~/.dots`
-//! - badm will search for a badm config file at one of the two valid locations: `$HOME`
-//!   and `$XDG_CONFIG_HOME`. If the config file not found, badm will create it under
-//!   `$HOME`
+//! - badm stores its settings in a config file at `$XDG_CONFIG_HOME/badm/config.toml` or
+//!   `$HOME/.badm.toml`. If neither is found, badm creates it under `$HOME`, see
+//!   [`Config`]
 //!
 //! <pre>
 //! /home
@@ -51,6 +51,27 @@
 //!   dotfile's directory structure will be created if not found.
 //! - `badm restore <FILE>` - restore the stored file from the dotfiles directory and
 //!   replace the symlink with the original file
+//! - `badm status` - report whether each stored dotfile is correctly linked at its target
+//!   path
+//! - `badm diff [<FILE>]` - show how the content at the target paths of copied,
+//!   templated, or otherwise drifted dotfiles differs from the stored dotfiles
+//!
+//! Every command accepts `--dry-run` to print the actions it would take without changing
+//! any files, see [`Executor`].
+//!
+//! `stow` and `deploy` skip files matching the gitignore-style patterns found in the
+//! dotfiles directory's `.badmignore` file, as well as any `--exclude <GLOB>` patterns.
+//! System-specific variants of a dotfile can be stored side by side, see [`alternates`].
+//! Dotfiles ending in `.tmpl` are rendered with per-machine variables, see [`template`],
+//! and `stow --encrypt` stores secrets encrypted, see [`crypto`].
+//! Several named dotfiles directories can be configured and selected with
+//! `--repo <NAME>`, see [`Config`].
+//!
+//! [`alternates`]: alternates/index.html
+//! [`crypto`]: crypto/index.html
+//! [`template`]: template/index.html
+//! [`Config`]: struct.Config.html
+//! [`Executor`]: executor/struct.Executor.html
 
 #![cfg_attr(test, deny(warnings))]
 #![deny(clippy::all)]
@@ -60,7 +81,6 @@
     missing_debug_implementations,
     missing_docs,
     missing_copy_implementations,
-    missing_docs,
     nonstandard_style,
     trivial_casts,
     trivial_numeric_casts,
@@ -68,41 +88,65 @@
     unused_extern_crates,
     unused_import_braces,
     unused_qualifications,
-    unused_results,
-    unused_qualifications
+    unused_results
 )]
 
+pub mod alternates;
 pub mod commands;
 pub(crate) mod config;
-mod errors;
+pub mod conflict;
+pub mod crypto;
+pub mod diff;
+pub mod doctor;
+pub mod errors;
+pub mod exclude;
+pub mod executor;
+pub mod git;
+pub mod hooks;
+pub mod journal;
+pub mod manifest;
 pub mod paths;
+pub mod report;
+pub mod status;
+pub mod template;
 
-pub use crate::config::Config;
-pub use crate::errors::InputError;
-
-#[macro_use] extern crate failure;
+pub use crate::commands::{DeployOptions, StoreOptions, UndeployOptions};
+pub use crate::config::{
+    Config, ModeRule, Repo, CONFIG_FILE_VAR, DEFAULT_REPO, ENV_SETTINGS,
+    REPO_CONFIG_FILE_NAME,
+};
+pub use crate::conflict::ConflictPolicy;
+pub use crate::errors::BadmError;
+pub use crate::exclude::ExcludePatterns;
+pub use crate::executor::{Action, Executor};
+pub use crate::journal::Journal;
+pub use crate::manifest::{Dotfile, LinkMode, Manifest};
+pub use crate::paths::{DeployMode, Layout, LinkStyle};
 
 use std::fs::{self, File};
-use std::io::{self, prelude::*, BufWriter};
+use std::io::{self, prelude::*, BufReader, BufWriter};
 use std::path::{Path, PathBuf};
 
-// TODO: create dotfile struct
+use crate::errors::{PathContext, Result};
 
 /// Struct used to traverse directories and collect entries located within.
-#[derive(Debug)]
+#[derive(Debug, Default)]
 pub struct DirScanner {
     entries: Vec<PathBuf>,
     recursive: bool,
+    exclude: ExcludePatterns,
+    folded: Vec<PathBuf>,
 }
 
 impl DirScanner {
     /// Given a directory, traverse path and get entries located within `dir`.
     /// If the [`DirScanner::recursive`] method is not called before `get_entries`, it
-    /// will only traverse one level below.
+    /// will only traverse one level below. Symlinks to directories, such as deployed
+    /// folded directories, are collected as single entries and never traversed.
     ///
     /// [`DirScanner::recursive`]: struct.DirScanner.html/#method.recursive
-    pub fn get_entries(mut self, dir: &Path) -> io::Result<Vec<PathBuf>> {
-        self.collect_entries(dir)?;
+    pub fn get_entries(mut self, dir: &Path) -> Result<Vec<PathBuf>> {
+        self.collect_entries(dir, dir)?;
 
         self.entries = self
             .entries
@@ -126,14 +170,37 @@ impl DirScanner {
         self
     }
 
-    fn collect_entries(&mut self, dir: &Path) -> io::Result<()> {
+    /// Builder method to skip entries matching `patterns`. Patterns are matched against
+    /// entry paths relative to the directory being scanned.
+    pub fn exclude(mut self, patterns: ExcludePatterns) -> Self {
+        self.exclude = patterns;
+        self
+    }
+
+    /// Builder method to collect the directories `dirs` as single entries instead of
+    /// traversing them, as is done for folded directories which are deployed as a single
+    /// symlink. Paths are relative to the directory being scanned.
+    pub fn folded<I: IntoIterator<Item = PathBuf>>(mut self, dirs: I) -> Self {
+        self.folded.extend(dirs);
+        self
+    }
+
+    fn collect_entries(&mut self, root: &Path, dir: &Path) -> Result<()> {
         if dir.is_dir() {
-            for entry in fs::read_dir(dir)? {
-                let path = entry.map(|e| e.path())?;
+            for entry in fs::read_dir(dir).at_path(dir)? {
+                let path = entry.map(|e| e.path()).at_path(dir)?;
+                let is_dir = path.is_dir() && !paths::is_symlink(&path);
 
-                if path.is_dir() && self.recursive {
-                    if !path.ends_with(".git") {
-                        self.collect_entries(&path)?;
+                let relative_path = path.strip_prefix(root).unwrap_or(&path);
+                if self.exclude.is_excluded(relative_path, is_dir) {
+                    continue;
+                };
+
+                if is_dir && self.recursive {
+                    if self.folded.iter().any(|folded| folded == relative_path) {
+                        self.entries.push(path)
+                    } else if !path.ends_with(".git") {
+                        self.collect_entries(root, &path)?;
                     }
                 } else {
                     self.entries.push(path)
@@ -144,15 +211,6 @@ impl DirScanner {
     }
 }
 
-impl Default for DirScanner {
-    fn default() -> Self {
-        Self {
-            entries: vec![],
-            recursive: false,
-        }
-    }
-}
-
 #[derive(Copy, Clone, Debug)]
 /// Moves, stores, and creates files and symlinks.
 pub struct FileHandler;
@@ -160,23 +218,268 @@ pub struct FileHandler;
 impl FileHandler {
     /// Store a file in the dotfiles directory, create a symlink at the original
     /// source of the stowed file.
-    pub fn store_file(src: &Path, dst: &Path) -> io::Result<()> {
+    pub fn store_file(src: &Path, dst: &Path) -> Result<()> {
         Self::move_file(src, dst)?;
         Self::create_symlink(dst, src)
     }
 
-    /// Read file at path src and write to created/truncated file at path dst.
-    pub fn move_file(src: &Path, dst: &Path) -> io::Result<()> {
-        // read file path to String
-        let contents = crate::paths::read_path(src)?;
+    /// Apply a single filesystem `action`.
+    pub fn apply(action: &Action) -> Result<()> {
+        match action {
+            Action::CreateDir { path } => fs::create_dir_all(path).at_path(path),
+            Action::Move { src, dst } => Self::move_file(src, dst),
+            Action::Symlink { src, dst } => Self::create_symlink(src, dst),
+            Action::HardLink { src, dst } => Self::create_hard_link(src, dst),
+            Action::Remove { path } => Self::remove(path),
+            Action::Write {
+                path,
+                contents,
+                mode,
+            } => Self::write_file(path, contents, *mode),
+            Action::SetPermissions { path, mode } => Self::set_permissions(path, *mode),
+        }
+    }
 
-        // write String contents to dst file
-        let dst_file = File::create(dst)?;
-        let mut writer = BufWriter::new(dst_file);
-        writer.write_all(contents.as_bytes())?;
+    /// Set the Unix permission bits of the file at `path` to `mode`. Does nothing on
+    /// other platforms.
+    pub fn set_permissions(path: &Path, mode: u32) -> Result<()> {
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
 
-        // remove file at src location
-        fs::remove_file(&src)
+            fs::set_permissions(path, fs::Permissions::from_mode(mode)).at_path(path)
+        }
+        #[cfg(not(unix))]
+        {
+            let _ = (path, mode);
+            Ok(())
+        }
+    }
+
+    /// Remove the file, symlink, or directory tree at `path`. Symlinks are never
+    /// followed.
+    pub fn remove(path: &Path) -> Result<()> {
+        fs::symlink_metadata(path)
+            .and_then(|metadata| {
+                if metadata.is_dir() {
+                    fs::remove_dir_all(path)
+                } else {
+                    fs::remove_file(path)
+                }
+            })
+            .at_path(path)
+    }
+
+    /// Move file or directory at path src to path dst, replacing any file at dst.
+    ///
+    /// The file is renamed if possible. If src and dst are located on different
+    /// filesystems, the file is copied with [`FileHandler::copy_file`], or
+    /// [`FileHandler::copy_dir`] for directories, and then removed from src.
+    ///
+    /// [`FileHandler::copy_file`]: struct.FileHandler.html#method.copy_file
+    /// [`FileHandler::copy_dir`]: struct.FileHandler.html#method.copy_dir
+    pub fn move_file(src: &Path, dst: &Path) -> Result<()> {
+        match fs::rename(src, dst) {
+            Err(ref err) if err.kind() == io::ErrorKind::CrossesDevices => {},
+            result => return result.at_path(src),
+        };
+
+        if fs::symlink_metadata(src).at_path(src)?.is_dir() {
+            Self::copy_dir(src, dst)?;
+            fs::remove_dir_all(src).at_path(src)
+        } else {
+            Self::copy_file(src, dst)?;
+
+            // remove file at src location
+            fs::remove_file(src).at_path(src)
+        }
+    }
+
+    /// Recursively copy directory at path src to path dst. Files are copied with
+    /// [`FileHandler::copy_file`], and symlinks are recreated rather than followed.
+    ///
+    /// [`FileHandler::copy_file`]: struct.FileHandler.html#method.copy_file
+    pub fn copy_dir(src: &Path, dst: &Path) -> Result<()> {
+        fs::create_dir_all(dst).at_path(dst)?;
+
+        for entry in fs::read_dir(src).at_path(src)? {
+            let entry = entry.at_path(src)?;
+            let src_path = entry.path();
+            let dst_path = dst.join(entry.file_name());
+            let file_type = entry.file_type().at_path(&src_path)?;
+
+            if file_type.is_dir() {
+                Self::copy_dir(&src_path, &dst_path)?;
+            } else if file_type.is_symlink() {
+                let link = fs::read_link(&src_path).at_path(&src_path)?;
+                Self::create_symlink(&link, &dst_path)?;
+            } else {
+                Self::copy_file(&src_path, &dst_path)?;
+            }
+        }
+
+        fs::metadata(src)
+            .and_then(|metadata| fs::set_permissions(dst, metadata.permissions()))
+            .at_path(dst)
+    }
+
+    /// Copy the contents of file at path src to path dst, replacing any file at dst.
+    ///
+    /// Contents are streamed, so files of any size and encoding can be copied. The
+    /// permissions, access/modification times, and on Unix platforms the extended
+    /// attributes of src are preserved, as is its ownership if the current user is
+    /// permitted to change it. The copy is written to a temporary file next to dst that
+    /// is created with the permissions of src and renamed once complete, so dst is never
+    /// left partially written or readable by more users than src.
+    pub fn copy_file(src: &Path, dst: &Path) -> Result<()> {
+        let metadata = fs::metadata(src).at_path(src)?;
+        let tmp_path = Self::tmp_path(dst)?;
+
+        // a temporary file left behind by an interrupted copy is replaced
+        if tmp_path.exists() || paths::is_symlink(&tmp_path) {
+            fs::remove_file(&tmp_path).at_path(&tmp_path)?;
+        };
+
+        let result = Self::copy_with_metadata(src, &tmp_path, &metadata)
+            .and_then(|_| fs::rename(&tmp_path, dst));
+
+        if result.is_err() {
+            let _ = fs::remove_file(&tmp_path);
+        };
+        result.at_path(dst)
+    }
+
+    /// Write `contents` to a file at path dst, replacing any file at dst. The contents
+    /// are written to a temporary file next to dst first, which is then renamed to dst.
+    ///
+    /// On Unix platforms, the file is created with the permission bits `mode` if given,
+    /// before any contents are written to it.
+    pub fn write_file(dst: &Path, contents: &[u8], mode: Option<u32>) -> Result<()> {
+        let tmp_path = Self::tmp_path(dst)?;
+
+        let mut options = fs::OpenOptions::new();
+        let _ = options.write(true).create(true).truncate(true);
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+
+            if let Some(mode) = mode {
+                let _ = options.mode(mode);
+            };
+        }
+        #[cfg(not(unix))]
+        let _ = mode;
+
+        let result = options
+            .open(&tmp_path)
+            .and_then(|mut file| {
+                #[cfg(unix)]
+                {
+                    use std::os::unix::fs::PermissionsExt;
+
+                    // the mode passed on creation is restricted by the umask
+                    if let Some(mode) = mode {
+                        file.set_permissions(fs::Permissions::from_mode(mode))?;
+                    };
+                }
+
+                file.write_all(contents)?;
+                file.sync_all()
+            })
+            .and_then(|_| fs::rename(&tmp_path, dst));
+
+        if result.is_err() {
+            let _ = fs::remove_file(&tmp_path);
+        };
+        result.at_path(dst)
+    }
+
+    fn tmp_path(dst: &Path) -> Result<PathBuf> {
+        let file_name = dst
+            .file_name()
+            .ok_or_else(|| BadmError::invalid(dst, "destination has no file name"))?;
+        let mut tmp_name = std::ffi::OsString::from(".");
+        tmp_name.push(file_name);
+        tmp_name.push(".badm-tmp");
+
+        Ok(dst.with_file_name(tmp_name))
+    }
+
+    fn copy_with_metadata(
+        src: &Path,
+        dst: &Path,
+        metadata: &fs::Metadata,
+    ) -> io::Result<()> {
+        let mut options = fs::OpenOptions::new();
+        let _ = options.write(true).create_new(true);
+
+        // the copy is never accessible to more users than src, even while it is written
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+
+            let _ = options.mode(metadata.permissions().mode() & 0o7777);
+        }
+
+        let mut reader = BufReader::new(File::open(src)?);
+        let mut writer = BufWriter::new(options.open(dst)?);
+
+        let _ = io::copy(&mut reader, &mut writer)?;
+        let file = writer.into_inner().map_err(|err| err.into_error())?;
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::{fchown, MetadataExt};
+
+            // changing ownership requires privileges the current user might not have
+            match fchown(&file, Some(metadata.uid()), Some(metadata.gid())) {
+                Err(ref err) if err.kind() == io::ErrorKind::PermissionDenied => {},
+                result => result?,
+            };
+
+            Self::copy_xattrs(reader.get_ref(), &file)?;
+        }
+
+        let times = fs::FileTimes::new()
+            .set_accessed(metadata.accessed()?)
+            .set_modified(metadata.modified()?);
+        file.set_times(times)?;
+
+        // the mode passed on creation is restricted by the umask
+        file.set_permissions(metadata.permissions())?;
+        file.sync_all()
+    }
+
+    /// Copy the extended attributes of file `src` to file `dst`. Attributes are skipped
+    /// if the filesystem of either file does not support them, or if the current user
+    /// is not permitted to set them, as for attributes in the `security` or `trusted`
+    /// namespaces.
+    #[cfg(unix)]
+    fn copy_xattrs(src: &File, dst: &File) -> io::Result<()> {
+        use xattr::FileExt;
+
+        let skip = |err: &io::Error| {
+            matches!(
+                err.kind(),
+                io::ErrorKind::Unsupported | io::ErrorKind::PermissionDenied
+            )
+        };
+
+        let names = match src.list_xattr() {
+            Err(ref err) if skip(err) => return Ok(()),
+            result => result?,
+        };
+
+        for name in names {
+            if let Some(value) = src.get_xattr(&name)? {
+                match dst.set_xattr(&name, &value) {
+                    Err(ref err) if skip(err) => {},
+                    result => result?,
+                };
+            };
+        }
+        Ok(())
     }
 
     /// Create a symlink at "dst" pointing to "src."
@@ -186,11 +489,19 @@ impl FileHandler {
     ///
     /// [`std::os::unix::fs::symlink`]: std/os/unix/fs/fn.symlink.html
     /// [`std::os::windows::fs::symlink_file`]: std/os/windows/fs/fn.symlink_file.html
-    pub fn create_symlink(src: &Path, dst: &Path) -> io::Result<()> {
+    pub fn create_symlink(src: &Path, dst: &Path) -> Result<()> {
         #[cfg(not(target_os = "windows"))] use std::os::unix::fs::symlink;
 
         #[cfg(target_os = "windows")]
         use std::os::windows::fs::symlink_file as symlink;
-        symlink(src, dst)
+        symlink(src, dst).at_path(dst)
+    }
+
+    /// Create a hard link at "dst" to the file at "src", which has to be located on the
+    /// same filesystem. Fails with [`BadmError::CrossDevice`] otherwise.
+    ///
+    /// [`BadmError::CrossDevice`]: errors/enum.BadmError.html#variant.CrossDevice
+    pub fn create_hard_link(src: &Path, dst: &Path) -> Result<()> {
+        fs::hard_link(src, dst).at_path(dst)
     }
 }
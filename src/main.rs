@@ -1,21 +1,39 @@
 use glob::glob;
+use std::collections::BTreeMap;
+use std::env;
 use std::fs;
-use std::io;
+use std::io::{self, IsTerminal};
+use std::mem;
 use std::path::{Path, PathBuf};
+use std::process;
 
 #[macro_use] extern crate clap;
 
 use clap::{App, AppSettings, Arg, ArgMatches};
-use failure::Error;
 
+use badm::alternates::System;
 use badm::commands;
+use badm::crypto::{self, Secret};
+use badm::diff::{self, FileDiff};
+use badm::doctor;
+use badm::errors::{PathContext, Result};
+use badm::git::Git;
+use badm::hooks::{run_hooks, Event};
+use badm::journal::Entry;
 use badm::paths;
-use badm::{Config, DirScanner};
+use badm::report::{Format, Report};
+use badm::status::DotfileStatus;
+use badm::template;
+use badm::{
+    BadmError, Config, ConflictPolicy, DeployMode, DeployOptions, DirScanner,
+    ExcludePatterns, Executor, Journal, Layout, LinkMode, LinkStyle, Manifest,
+    StoreOptions, UndeployOptions,
+};
 
 fn validate_paths(paths: Vec<PathBuf>) -> Vec<PathBuf> {
     paths
         .into_iter()
-        .filter(|path| path.is_file() && !paths::is_symlink(path))
+        .filter(|path| (path.is_file() || path.is_dir()) && !paths::is_symlink(path))
         .map(|path| {
             if path.is_relative() {
                 fs::canonicalize(path)
@@ -27,7 +45,202 @@ fn validate_paths(paths: Vec<PathBuf>) -> Vec<PathBuf> {
         .collect::<Vec<PathBuf>>()
 }
 
-fn main() -> Result<(), Error> {
+fn exclude_arg<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("exclude")
+        .help(
+            "gitignore-style pattern of files to skip, in addition to the patterns \
+             found in the dotfiles directory's .badmignore file",
+        )
+        .long("exclude")
+        .value_name("GLOB")
+        .takes_value(true)
+        .multiple(true)
+        .number_of_values(1)
+}
+
+fn link_style_arg<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("link-style")
+        .help(
+            "write symlink targets as absolute paths, or relative to the symlink's \
+             directory; defaults to the link_style setting, see `badm config`",
+        )
+        .long("link-style")
+        .value_name("STYLE")
+        .takes_value(true)
+        .possible_values(&LinkStyle::VARIANTS)
+}
+
+fn mode_arg<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("mode")
+        .help(
+            "deploy dotfiles as symlinks, as copies for programs that replace or do not \
+             follow symlinks, or as hard links if located on the same filesystem as the \
+             dotfiles directory; defaults to the mode settings of the user config file",
+        )
+        .long("mode")
+        .visible_alias("link")
+        .value_name("MODE")
+        .takes_value(true)
+        .possible_values(&DeployMode::VARIANTS)
+}
+
+fn commit_arg<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("commit")
+        .help(
+            "commit the changes to the dotfiles directory with git, listing the \
+             affected dotfiles in the commit message",
+        )
+        .long("commit")
+}
+
+fn force_arg<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("force")
+        .help(
+            "replace copies, rendered templates, and decrypted files that were modified \
+             since they were deployed, discarding the changes",
+        )
+        .long("force")
+}
+
+fn key_file_arg<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("key-file")
+        .help(
+            "encrypt and decrypt dotfiles with the contents of this file instead of a \
+             passphrase; defaults to the key_file setting of the user config file",
+        )
+        .long("key-file")
+        .value_name("FILE")
+        .takes_value(true)
+}
+
+/// Get the secret to encrypt and decrypt dotfiles with: the key file passed through
+/// `--key-file` or configured, the passphrase set in `BADM_PASSPHRASE`, or else a
+/// passphrase read from the terminal, which is asked twice if `confirm` is set.
+fn read_secret(values: &ArgMatches, confirm: bool) -> Result<Secret> {
+    let key_file = values
+        .value_of("key-file")
+        .map(PathBuf::from)
+        .or_else(|| Config::load().ok().and_then(|config| config.key_file));
+
+    match key_file {
+        Some(key_file) => Secret::from_file(&key_file),
+        None => Secret::from_env().unwrap_or_else(|| Secret::prompt(confirm)),
+    }
+}
+
+/// Get the link style passed through `--link-style`, falling back to the configured link
+/// style.
+fn link_style(values: &ArgMatches) -> LinkStyle {
+    value_t!(values, "link-style", LinkStyle).unwrap_or_else(|_| {
+        Config::load()
+            .map(|config| config.link_style)
+            .unwrap_or_default()
+    })
+}
+
+/// Get the mode the dotfile of `dots_dir` deployed to `dst` is deployed in: the mode
+/// passed through `--mode`, falling back to the mode configured for `dst`.
+///
+/// A hard link can not cross filesystems, so if `dst` is located on another filesystem
+/// than `dots_dir`, the configured fallback mode is used instead and a warning is added
+/// to `report`.
+fn deploy_mode(
+    config: &Config,
+    values: &ArgMatches,
+    dots_dir: &Path,
+    dst: &Path,
+    report: &mut Report,
+) -> DeployMode {
+    let mode =
+        value_t!(values, "mode", DeployMode).unwrap_or_else(|_| config.deploy_mode(dst));
+
+    if mode != DeployMode::Hard || paths::is_same_device(dots_dir, dst) {
+        return mode;
+    };
+
+    let fallback = config.hard_link_fallback();
+    report.warning(format!(
+        "{:?} is located on another filesystem than {:?}, deploying a {} instead of a \
+         hard link",
+        dst, dots_dir, fallback
+    ));
+    fallback
+}
+
+/// Get the layout passed through `--layout`, falling back to the configured layout.
+fn layout(values: &ArgMatches) -> Layout {
+    value_t!(values, "layout", Layout).unwrap_or_else(|_| {
+        Config::load()
+            .map(|config| config.layout)
+            .unwrap_or_default()
+    })
+}
+
+/// Combine patterns from the dotfiles directory's `.badmignore` file with patterns passed
+/// through `--exclude`.
+fn exclude_patterns(dots_dir: &Path, values: &ArgMatches) -> Result<ExcludePatterns> {
+    let mut patterns = ExcludePatterns::from_dots_dir(dots_dir)?;
+
+    if let Some(globs) = values.values_of("exclude") {
+        for glob in globs {
+            patterns.add(glob).map_err(|err| {
+                BadmError::invalid(glob, format!("invalid exclude pattern: {}", err.msg))
+            })?;
+        }
+    };
+
+    Ok(patterns)
+}
+
+/// Replace directories in `paths` with the files located below them.
+fn expand_dirs(paths: Vec<PathBuf>) -> Result<Vec<PathBuf>> {
+    let mut expanded = vec![];
+
+    for path in paths {
+        if path.is_dir() {
+            let entries = DirScanner::default().recursive().get_entries(&path)?;
+            expanded.append(&mut validate_paths(entries));
+        } else {
+            expanded.push(path);
+        }
+    }
+    Ok(expanded)
+}
+
+/// Collect every stored dotfile of `dots_dir`, keeping folded directories as single
+/// entries.
+fn scan_repo(dots_dir: &Path, exclude: ExcludePatterns) -> Result<Vec<PathBuf>> {
+    DirScanner::default()
+        .recursive()
+        .exclude(exclude)
+        .folded(Manifest::load(dots_dir)?.folded_dirs())
+        .get_entries(dots_dir)
+}
+
+/// Get the dotfiles directory of the repository selected with `--repo`, or the default
+/// dotfiles directory if no repository was selected.
+fn repo_dir(values: &ArgMatches) -> Result<PathBuf> {
+    match values.value_of("repo") {
+        Some(name) => Config::get_repo_dir(name),
+        None => Config::get_dots_dir(),
+    }
+}
+
+/// Get the dotfiles directories to operate on, ordered from lowest to highest precedence.
+/// If no repository was selected with `--repo`, all configured repositories are returned.
+fn repo_dirs(config: &Config, values: &ArgMatches) -> Result<Vec<PathBuf>> {
+    if values.is_present("repo") {
+        Ok(vec![repo_dir(values)?])
+    } else {
+        Ok(config
+            .repos()
+            .into_iter()
+            .map(|repo| repo.directory)
+            .collect())
+    }
+}
+
+fn app() -> App<'static, 'static> {
     let set_dir_subcommand = App::new("set-dir")
         .about("set path of dotfiles directory")
         .version("1.0")
@@ -36,6 +249,18 @@ fn main() -> Result<(), Error> {
             Arg::with_name("directory")
                 .help("directory to store dotfiles")
                 .required(true),
+        )
+        .arg(
+            Arg::with_name("priority")
+                .help(
+                    "precedence of the repository selected with --repo when several \
+                     repositories store the same dotfile; higher values win",
+                )
+                .long("priority")
+                .value_name("N")
+                .takes_value(true)
+                .allow_hyphen_values(true)
+                .requires("repo"),
         );
 
     let stow_subcommand = App::new("stow")
@@ -47,10 +272,46 @@ fn main() -> Result<(), Error> {
         .display_order(2)
         .arg(
             Arg::with_name("files")
-                .help("path of the file/files to be stored in the dotfiles directory")
+                .help(
+                    "path of the file/files to be stored in the dotfiles directory. A \
+                     directory is stored as a whole and replaced by a single symlink",
+                )
                 .required(true)
                 .multiple(true),
-        );
+        )
+        .arg(
+            Arg::with_name("no-fold")
+                .help(
+                    "store each file below an input directory individually, keeping the \
+                     directory itself in place",
+                )
+                .long("no-fold"),
+        )
+        .arg(
+            Arg::with_name("layout")
+                .help(
+                    "store files from the home directory below a ~ directory to deploy \
+                     them to any user's home, or mirror their full path from /; \
+                     defaults to the layout setting, see `badm config`",
+                )
+                .long("layout")
+                .value_name("LAYOUT")
+                .takes_value(true)
+                .possible_values(&Layout::VARIANTS),
+        )
+        .arg(
+            Arg::with_name("encrypt")
+                .help(
+                    "store an encrypted copy of each file instead of moving it, and \
+                     keep the file in place, readable only by its owner",
+                )
+                .long("encrypt"),
+        )
+        .arg(key_file_arg().requires("encrypt"))
+        .arg(commit_arg())
+        .arg(link_style_arg())
+        .arg(mode_arg().conflicts_with("encrypt"))
+        .arg(exclude_arg());
 
     let deploy_subcommand = App::new("deploy")
         .about(
@@ -63,16 +324,33 @@ fn main() -> Result<(), Error> {
         .arg(
             Arg::with_name("dotfiles")
                 .help("stored dotfile/s to be deployed to system")
-                .required(true)
+                .required_unless("all")
                 .multiple(true),
         )
         .arg(
             Arg::with_name("all")
                 .help("deploy all stored dotfiles")
                 .long("all")
-                .conflicts_with("dotfiles")
-                .required(true),
-        );
+                .conflicts_with("dotfiles"),
+        )
+        .arg(
+            Arg::with_name("on-conflict")
+                .help(
+                    "what to do when a target path is already taken: skip it, move it \
+                     to a timestamped backup, overwrite it, adopt it into the dotfiles \
+                     directory in place of the stored copy, or prompt for each conflict",
+                )
+                .long("on-conflict")
+                .value_name("POLICY")
+                .takes_value(true)
+                .possible_values(&ConflictPolicy::VARIANTS)
+                .default_value("skip"),
+        )
+        .arg(force_arg())
+        .arg(key_file_arg())
+        .arg(link_style_arg())
+        .arg(mode_arg())
+        .arg(exclude_arg());
 
     let restore_subcommand = App::new("restore")
         .about("restore all dotfiles to their original locations")
@@ -83,104 +361,1273 @@ fn main() -> Result<(), Error> {
                 .help("the dotfiles to restore to original locations")
                 .multiple(true)
                 .required(true),
+        )
+        .arg(commit_arg());
+
+    let undeploy_subcommand = App::new("undeploy")
+        .about(
+            "remove deployed dotfiles from the system, leaving the dotfiles directory \
+             untouched. Only symlinks into the dotfiles directory and unmodified \
+             rendered or decrypted files are removed",
+        )
+        .version("0.1")
+        .display_order(5)
+        .arg(
+            Arg::with_name("dotfiles")
+                .help(
+                    "deployed dotfile/s to remove, or stored dotfile/s whose deployed \
+                     copies are removed. A directory selects every dotfile below it",
+                )
+                .required_unless("all")
+                .multiple(true),
+        )
+        .arg(
+            Arg::with_name("all")
+                .help("undeploy all deployed dotfiles")
+                .long("all")
+                .conflicts_with("dotfiles"),
+        )
+        .arg(
+            Arg::with_name("restore-backups")
+                .help("move files backed up when deploying back in place")
+                .long("restore-backups"),
         );
 
-    let matches = App::new("badm")
+    let status_subcommand = App::new("status")
+        .about(
+            "report whether each stored dotfile is linked at its target path, missing, \
+             blocked by another file, or replaced by a foreign or dangling symlink",
+        )
+        .version("0.1")
+        .display_order(6)
+        .arg(
+            Arg::with_name("json")
+                .help("alias of --format json")
+                .long("json"),
+        )
+        .arg(exclude_arg());
+
+    let doctor_subcommand = App::new("doctor")
+        .about(
+            "find symlinks into the dotfiles directory whose targets no longer exist or \
+             that are located at the wrong path, and stored dotfiles that are not linked",
+        )
+        .version("0.1")
+        .display_order(7)
+        .arg(
+            Arg::with_name("roots")
+                .help(
+                    "directories to scan for symlinks [default: target_roots of the \
+                     user config file, or the home directory]",
+                )
+                .multiple(true),
+        )
+        .arg(
+            Arg::with_name("fix")
+                .help(
+                    "relink or remove broken symlinks, and deploy dotfiles that are not \
+                     linked",
+                )
+                .long("fix"),
+        )
+        .arg(link_style_arg())
+        .arg(exclude_arg());
+
+    let recover_subcommand = App::new("recover")
+        .about(
+            "revert the changes of a stow, deploy, or restore run that was interrupted \
+             before completing",
+        )
+        .version("0.1")
+        .display_order(8)
+        .arg(
+            Arg::with_name("resume")
+                .help("run the interrupted command again after reverting its changes")
+                .long("resume"),
+        );
+
+    let git_subcommand = App::new("git")
+        .about("run git with the given arguments inside the dotfiles directory")
+        .version("0.1")
+        .display_order(9)
+        .setting(AppSettings::TrailingVarArg)
+        .setting(AppSettings::AllowLeadingHyphen)
+        .arg(
+            Arg::with_name("args")
+                .help("arguments passed to git")
+                .multiple(true),
+        );
+
+    let init_subcommand = App::new("init")
+        .about(
+            "set up a new machine: clone or copy a dotfiles repository, set it as the \
+             dotfiles directory, and deploy all stored dotfiles",
+        )
+        .version("0.1")
+        .display_order(0)
+        .arg(
+            Arg::with_name("source")
+                .help(
+                    "URL or path of a git repository to clone, or path of a local \
+                     directory to copy",
+                )
+                .required(true),
+        )
+        .arg(
+            Arg::with_name("dir")
+                .help("directory to set up as dotfiles directory [default: ~/.dots]")
+                .long("dir")
+                .value_name("DIRECTORY")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("bootstrap")
+                .help(
+                    "run the repository's .badm-bootstrap script after deploying the \
+                     dotfiles",
+                )
+                .long("bootstrap"),
+        )
+        .arg(
+            Arg::with_name("on-conflict")
+                .help("what to do when a target path is already taken, as for deploy")
+                .long("on-conflict")
+                .value_name("POLICY")
+                .takes_value(true)
+                .possible_values(&ConflictPolicy::VARIANTS)
+                .default_value("skip"),
+        )
+        .arg(key_file_arg())
+        .arg(link_style_arg())
+        .arg(mode_arg())
+        .arg(exclude_arg());
+
+    let config_subcommand = App::new("config")
+        .about(
+            "show and change settings, merged from the repo-local badm.toml, the user \
+             config file, and BADM_* environment variables",
+        )
+        .version("0.1")
+        .display_order(11)
+        .setting(AppSettings::SubcommandRequiredElseHelp)
+        .subcommands(vec![
+            App::new("get")
+                .about("print the value of a setting, e.g. variables.email")
+                .arg(
+                    Arg::with_name("key")
+                        .help("dotted name of the setting")
+                        .required(true),
+                ),
+            App::new("set")
+                .about("set a setting in the user config file")
+                .arg(
+                    Arg::with_name("key")
+                        .help("dotted name of the setting")
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("value")
+                        .help("value of the setting, parsed as TOML if possible")
+                        .required(true)
+                        .allow_hyphen_values(true),
+                ),
+            App::new("list").about("print every setting"),
+            App::new("edit").about("open the user config file in $VISUAL or $EDITOR"),
+        ]);
+
+    let sync_subcommand = App::new("sync")
+        .about(
+            "pull each dotfiles directory that is a git repository, deploy all stored \
+             dotfiles, and push local commits",
+        )
+        .version("0.1")
+        .display_order(10)
+        .arg(
+            Arg::with_name("on-conflict")
+                .help("what to do when a target path is already taken, as for deploy")
+                .long("on-conflict")
+                .value_name("POLICY")
+                .takes_value(true)
+                .possible_values(&ConflictPolicy::VARIANTS)
+                .default_value("skip"),
+        )
+        .arg(force_arg())
+        .arg(key_file_arg())
+        .arg(link_style_arg())
+        .arg(mode_arg())
+        .arg(exclude_arg());
+
+    let adopt_subcommand = App::new("adopt")
+        .about(
+            "copy the changes made to dotfiles deployed as copies back into the \
+             dotfiles directory",
+        )
+        .version("0.1")
+        .display_order(12)
+        .arg(
+            Arg::with_name("dotfiles")
+                .help(
+                    "deployed copies to adopt, or stored dotfile/s whose deployed \
+                     copies are adopted. A directory selects every dotfile below it",
+                )
+                .required_unless("all")
+                .multiple(true),
+        )
+        .arg(
+            Arg::with_name("all")
+                .help("adopt all modified copies")
+                .long("all")
+                .conflicts_with("dotfiles"),
+        )
+        .arg(
+            Arg::with_name("force")
+                .help(
+                    "replace stored dotfiles that were changed since their copies were \
+                     deployed, discarding those changes",
+                )
+                .long("force"),
+        );
+
+    let diff_subcommand = App::new("diff")
+        .about(
+            "show how the content at the target paths of dotfiles differs from the \
+             stored dotfiles, rendered or decrypted as deploying would",
+        )
+        .version("0.1")
+        .display_order(13)
+        .arg(
+            Arg::with_name("paths")
+                .help(
+                    "target paths or stored dotfile/s to compare. A directory selects \
+                     every dotfile below it. Defaults to every dotfile reported as \
+                     outdated, modified, conflicting, or foreign by status",
+                )
+                .multiple(true),
+        )
+        .arg(key_file_arg())
+        .arg(exclude_arg());
+
+    App::new("badm")
         .setting(AppSettings::ArgRequiredElseHelp)
         .about(crate_description!())
         .version(crate_version!())
         .author(crate_authors!())
         .after_help("https://github.com/jakeschurch/badm")
+        .arg(
+            Arg::with_name("repo")
+                .help(
+                    "name of the dotfiles repository to operate on, as configured in \
+                     the user config file",
+                )
+                .long("repo")
+                .value_name("NAME")
+                .takes_value(true)
+                .global(true),
+        )
+        .arg(
+            Arg::with_name("format")
+                .help(
+                    "print the results of the command for humans, or as a single JSON \
+                     object listing the outcome for each path and the actions taken",
+                )
+                .long("format")
+                .value_name("FORMAT")
+                .takes_value(true)
+                .possible_values(&Format::VARIANTS)
+                .default_value("human")
+                .global(true),
+        )
+        .arg(
+            Arg::with_name("dry-run")
+                .help("print the actions a command would take without changing any files")
+                .long("dry-run")
+                .global(true),
+        )
         .subcommands(vec![
+            init_subcommand,
             set_dir_subcommand,
             stow_subcommand,
             deploy_subcommand,
             restore_subcommand,
+            undeploy_subcommand,
+            status_subcommand,
+            doctor_subcommand,
+            recover_subcommand,
+            git_subcommand,
+            sync_subcommand,
+            config_subcommand,
+            adopt_subcommand,
+            diff_subcommand,
         ])
-        .get_matches();
+}
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    let matches = app().get_matches_from(&args);
+    let format = match matches.subcommand() {
+        // `status --json` predates the global option and is kept as an alias of it
+        ("status", Some(status_matches)) if status_matches.is_present("json") => {
+            Format::Json
+        },
+        _ => value_t!(matches, "format", Format).unwrap_or_default(),
+    };
+
+    let mut report = Report::new(
+        matches.subcommand_name().unwrap_or_default(),
+        matches.is_present("dry-run"),
+    );
+    let result = run(&matches, args, &mut report);
+    if let Err(err) = &result {
+        report.fail(err);
+    };
+
+    // git passes its output through as it is
+    let result = match matches.subcommand_name() {
+        Some("git") => result,
+        _ => report.print(format).and(result),
+    };
+
+    if let Err(err) = result {
+        if format == Format::Human {
+            eprintln!("error: {}", err);
+        };
+        process::exit(err.exit_code());
+    };
+}
+
+/// Run the command selected by `matches`, parsed from the command line `args`, and
+/// record its results in `report`.
+fn run(matches: &ArgMatches, args: Vec<String>, report: &mut Report) -> Result<()> {
+    let mut exec = if matches.is_present("dry-run") {
+        Executor::dry_run()
+    } else {
+        Executor::new()
+    };
+
+    let result = match matches.subcommand() {
+        ("init", Some(init_matches)) => init(init_matches, &mut exec, report, args),
+        ("set-dir", Some(set_dir_matches)) => set_dir(set_dir_matches, &exec, report),
+        ("stow", Some(stow_matches)) => {
+            journaled(&mut exec, report, args, |exec, report| {
+                stow(stow_matches, exec, report)
+            })
+            .and_then(|()| commit(stow_matches, "stow", &exec, report))
+        },
+        ("deploy", Some(deploy_matches)) => {
+            journaled(&mut exec, report, args, |exec, report| {
+                deploy(deploy_matches, exec, report)
+            })
+        },
+        ("restore", Some(restore_matches)) => {
+            journaled(&mut exec, report, args, |exec, report| {
+                restore(restore_matches, exec, report)
+            })
+            .and_then(|()| commit(restore_matches, "restore", &exec, report))
+        },
+        ("undeploy", Some(undeploy_matches)) => {
+            journaled(&mut exec, report, args, |exec, report| {
+                undeploy(undeploy_matches, exec, report)
+            })
+        },
+        ("adopt", Some(adopt_matches)) => {
+            journaled(&mut exec, report, args, |exec, report| {
+                adopt(adopt_matches, exec, report)
+            })
+        },
+        ("status", Some(status_matches)) => status(status_matches, report),
+        ("diff", Some(diff_matches)) => diff(diff_matches, report),
+        ("doctor", Some(doctor_matches)) if doctor_matches.is_present("fix") => {
+            journaled(&mut exec, report, args, |exec, report| {
+                doctor(doctor_matches, exec, report)
+            })
+        },
+        ("doctor", Some(doctor_matches)) => doctor(doctor_matches, &mut exec, report),
+        ("recover", Some(recover_matches)) => recover(recover_matches, &exec, report),
+        ("git", Some(git_matches)) => git(git_matches),
+        ("sync", Some(sync_matches)) => sync(sync_matches, &mut exec, report, args),
+        ("config", Some(config_matches)) => config(config_matches, &exec, report),
+        _ => Ok(()),
+    };
+
+    report.actions.extend(exec.actions().iter().cloned());
+    result
+}
+
+fn journal_dir() -> Result<PathBuf> {
+    Journal::default_dir().ok_or(BadmError::HomeNotFound)
+}
+
+/// Run the batch `command` with a journal attached to `exec`, rolling back the changes
+/// it made if it fails.
+fn journaled<F>(
+    exec: &mut Executor,
+    report: &mut Report,
+    args: Vec<String>,
+    command: F,
+) -> Result<()>
+where
+    F: FnOnce(&mut Executor, &mut Report) -> Result<()>,
+{
+    if !exec.is_dry_run() {
+        let journal = Journal::begin(&journal_dir()?, args)?;
+        *exec = mem::take(exec).journal(journal);
+    };
+
+    match command(exec, report) {
+        Ok(()) => exec.commit(),
+        Err(err) => {
+            match exec.rollback() {
+                Ok(()) if !exec.is_dry_run() => {
+                    report.warning("changes have been rolled back")
+                },
+                Ok(()) => {},
+                Err(rollback_err) => report.warning(format!(
+                    "could not roll back changes: {}, run `badm recover` to retry",
+                    rollback_err
+                )),
+            };
+            Err(err)
+        },
+    }
+}
+
+/// Commit the changes `command` made to each dotfiles directory if `--commit` was passed.
+fn commit(
+    values: &ArgMatches,
+    command: &str,
+    exec: &Executor,
+    report: &mut Report,
+) -> Result<()> {
+    if !values.is_present("commit") || exec.is_dry_run() {
+        return Ok(());
+    };
 
-    match matches.subcommand() {
-        ("set-dir", Some(set_dir_matches)) => {
-            let dir_path = set_dir_matches.value_of("directory").unwrap();
-            set_dir(dir_path)?
+    let config = Config::load()?;
+
+    for repo in config.repos() {
+        if commands::commit_changes(&repo.directory, command, exec.actions())? {
+            report.message(format!("committed changes to {:?}", repo.directory));
+        };
+    }
+    Ok(())
+}
+
+fn git(values: &ArgMatches) -> Result<()> {
+    let dots_dir = repo_dir(values)?;
+    let args = values.values_of_os("args").unwrap_or_default();
+
+    let status = Git::new(&dots_dir).status(args)?;
+    if !status.success() {
+        process::exit(status.code().unwrap_or(1));
+    };
+    Ok(())
+}
+
+fn sync(
+    values: &ArgMatches,
+    exec: &mut Executor,
+    report: &mut Report,
+    args: Vec<String>,
+) -> Result<()> {
+    let config = Config::load()?;
+
+    let mut repos = vec![];
+    for dots_dir in repo_dirs(&config, values)? {
+        let git = Git::new(&dots_dir);
+
+        if git.is_repo() {
+            repos.push(git);
+        } else {
+            report.skipped(&dots_dir, "not a git repository");
+        };
+    }
+
+    for git in repos.iter() {
+        if exec.is_dry_run() {
+            report.message(format!("[dry-run] git pull {:?}", git.dir()));
+        } else {
+            git.pull()?;
+        };
+    }
+
+    journaled(exec, report, args, |exec, report| {
+        deploy(values, exec, report)
+    })?;
+
+    for git in repos.iter() {
+        if exec.is_dry_run() {
+            report.message(format!("[dry-run] git push {:?}", git.dir()));
+        } else {
+            git.push()?;
+        };
+    }
+    Ok(())
+}
+
+fn recover(values: &ArgMatches, exec: &Executor, report: &mut Report) -> Result<()> {
+    let journal = match Journal::open(&journal_dir()?)? {
+        Some(journal) => journal,
+        None => {
+            report.message("nothing to recover");
+            return Ok(());
+        },
+    };
+    let args = journal.args().to_vec();
+
+    if exec.is_dry_run() {
+        for entry in journal.entries() {
+            match entry {
+                Entry::Apply { action } => {
+                    report.message(format!("[dry-run] revert  {}", action))
+                },
+                Entry::Preserve { path, .. } => {
+                    report.message(format!("[dry-run] restore {:?}", path))
+                },
+                Entry::Begin { .. } | Entry::Permissions { .. } => {},
+            }
+        }
+        return Ok(());
+    };
+
+    journal.rollback()?;
+    report.message(format!("reverted interrupted run of `{}`", args.join(" ")));
+
+    if values.is_present("resume") && !args.is_empty() {
+        return run(&app().get_matches_from(&args), args, report);
+    };
+    Ok(())
+}
+
+fn config(values: &ArgMatches, exec: &Executor, report: &mut Report) -> Result<()> {
+    match values.subcommand() {
+        ("get", Some(get_matches)) => {
+            let key = get_matches.value_of("key").unwrap();
+
+            match Config::get_setting(key)? {
+                Some(value) => report.data(&value, vec![setting_value(&value)]),
+                None => Err(BadmError::invalid(
+                    Config::user_config_file()?,
+                    format!("`{}` is not set", key),
+                )),
+            }
+        },
+        ("set", Some(set_matches)) => {
+            let key = set_matches.value_of("key").unwrap();
+            let value = set_matches.value_of("value").unwrap();
+
+            if exec.is_dry_run() {
+                report.message(format!("[dry-run] {} would be set to {}", key, value));
+                return Ok(());
+            };
+
+            let config_file = Config::set_setting(key, value)?;
+            report.message(format!("set {} in {:?}", key, config_file));
+            Ok(())
+        },
+        ("list", Some(_)) => {
+            let settings = Config::settings()?;
+
+            let mut lines = vec![];
+            list_settings("", &settings, &mut lines);
+            report.data(&settings, lines)
         },
-        ("stow", Some(stow_matches)) => stow(stow_matches)?,
-        ("deploy", Some(deploy_matches)) => deploy(deploy_matches)?,
-        ("restore", Some(restore_matches)) => restore(restore_matches)?,
-        _ => {},
+        ("edit", Some(_)) => edit_config(exec, report),
+        _ => Ok(()),
+    }
+}
+
+/// Format the setting `value` for humans: strings as they are, anything else as TOML.
+fn setting_value(value: &toml::Value) -> String {
+    match value {
+        toml::Value::String(value) => value.clone(),
+        value => value.to_string(),
+    }
+}
+
+/// Append a `key = value` line for every setting of `settings` to `lines`, with the keys
+/// of nested tables prefixed by `prefix`.
+fn list_settings(prefix: &str, settings: &toml::value::Table, lines: &mut Vec<String>) {
+    for (key, value) in settings {
+        let key = format!("{}{}", prefix, key);
+
+        match value {
+            toml::Value::Table(table) => {
+                list_settings(&format!("{}.", key), table, lines)
+            },
+            value => lines.push(format!("{} = {}", key, value)),
+        }
+    }
+}
+
+/// Open the user config file in the editor set in `VISUAL` or `EDITOR`, falling back to
+/// `vi`, and check the settings once it is closed.
+fn edit_config(exec: &Executor, report: &mut Report) -> Result<()> {
+    let config_file = Config::user_config_file()?;
+
+    if exec.is_dry_run() {
+        report.message(format!("[dry-run] edit {:?}", config_file));
+        return Ok(());
+    };
+
+    if let Some(dir) = config_file.parent() {
+        fs::create_dir_all(dir).at_path(dir)?;
+    };
+
+    let editor = env::var("VISUAL")
+        .or_else(|_| env::var("EDITOR"))
+        .unwrap_or_else(|_| "vi".to_string());
+    // the editor may be given with arguments, e.g. `code --wait`
+    let status = process::Command::new("sh")
+        .arg("-c")
+        .arg(format!("{} \"$1\"", editor))
+        .arg("sh")
+        .arg(&config_file)
+        .status()
+        .at_path(&config_file)?;
+
+    if !status.success() {
+        return Err(BadmError::invalid(
+            &config_file,
+            format!("editor `{}` failed with {}", editor, status),
+        ));
+    };
+
+    // a config file without dotfiles directory is valid until one is needed
+    match Config::load() {
+        Ok(_) | Err(BadmError::ConfigNotFound { .. }) => Ok(()),
+        Err(err) => Err(err),
     }
+}
+
+fn init(
+    values: &ArgMatches,
+    exec: &mut Executor,
+    report: &mut Report,
+    args: Vec<String>,
+) -> Result<()> {
+    let source = values.value_of("source").unwrap();
+    let dots_dir = match values.value_of("dir") {
+        Some(dir) => env::current_dir()?.join(dir),
+        None => dirs::home_dir()
+            .ok_or(BadmError::HomeNotFound)?
+            .join(".dots"),
+    };
+
+    if exec.is_dry_run() {
+        report.message(format!(
+            "[dry-run] {:?} would be set up from {:?}",
+            dots_dir, source
+        ));
+        return Ok(());
+    };
+
+    if commands::init_dots_dir(source, &dots_dir)? {
+        report.message(format!("cloned {:?} into {:?}", source, dots_dir));
+    } else {
+        report.message(format!("copied {:?} to {:?}", source, dots_dir));
+    };
+
+    let dots_dir = match values.value_of("repo") {
+        Some(name) => Config::set_repo_dir(name, &dots_dir, 0)?,
+        None => Config::set_dots_dir(&dots_dir)?,
+    };
+    report.message(format!(
+        "BADM dotfiles path has been set to: {:?}",
+        dots_dir
+    ));
+
+    journaled(exec, report, args, |exec, report| {
+        deploy(values, exec, report)
+    })?;
+
+    if values.is_present("bootstrap") && !commands::run_bootstrap(&dots_dir)? {
+        let script = dots_dir.join(commands::BOOTSTRAP_FILE_NAME);
+        report.skipped(&script, "no bootstrap script");
+    };
     Ok(())
 }
 
-fn set_dir<P: AsRef<Path>>(path: P) -> Result<(), Error> {
-    let path = path.as_ref().to_path_buf();
+fn set_dir(values: &ArgMatches, exec: &Executor, report: &mut Report) -> Result<()> {
+    let path = PathBuf::from(values.value_of("directory").unwrap());
+
+    if exec.is_dry_run() {
+        report.message(format!(
+            "[dry-run] BADM dotfiles path would be set to: {:?}",
+            path
+        ));
+        return Ok(());
+    };
 
-    let set_path = Config::set_dots_dir(path)?;
+    let set_path = match values.value_of("repo") {
+        Some(name) => {
+            let priority = value_t!(values, "priority", i64).unwrap_or(0);
+            Config::set_repo_dir(name, path, priority)?
+        },
+        None => Config::set_dots_dir(path)?,
+    };
 
-    println! {"BADM dotfiles path has been set to: {:?}", set_path};
+    report.message(format!(
+        "BADM dotfiles path has been set to: {:?}",
+        set_path
+    ));
     Ok(())
 }
 
-fn stow(values: &ArgMatches) -> io::Result<()> {
+fn stow(values: &ArgMatches, exec: &mut Executor, report: &mut Report) -> Result<()> {
+    let config = Config::load()?;
+    let dots_dir = repo_dir(values)?;
+    let exclude = exclude_patterns(&dots_dir, values)?;
+    let store_options = StoreOptions {
+        layout: layout(values),
+    };
+    let deploy_options = DeployOptions {
+        link_style: link_style(values),
+        ..DeployOptions::default()
+    };
+
     let mut input_paths = vec![];
 
     for path in values.values_of("files").unwrap() {
-        let paths: Vec<PathBuf> = glob(path).unwrap().filter_map(Result::ok).collect();
+        let paths: Vec<PathBuf> = glob(path)
+            .map_err(|err| {
+                let message = format!("invalid pattern: {}", err.msg);
+                report.failed(Path::new(path), BadmError::invalid(path, message))
+            })?
+            .filter_map(Result::ok)
+            .collect();
         let mut path_vec = validate_paths(paths);
+        if values.is_present("no-fold") || values.is_present("encrypt") {
+            path_vec = expand_dirs(path_vec)?;
+        };
+        path_vec.retain(|path| !exclude.is_excluded(path, path.is_dir()));
 
         input_paths.append(&mut path_vec);
     }
 
-    for path in input_paths.into_iter() {
-        let dst_path = commands::store_dotfile(&path)?;
-        commands::deploy_dotfile(&dst_path, &path)?;
+    let hooks = &config.hooks;
+    run_hooks(hooks, Event::PreStow, &input_paths, exec, report)?;
+
+    if values.is_present("encrypt") {
+        let secret = read_secret(values, true)?;
+
+        for path in input_paths.iter() {
+            let result =
+                commands::store_encrypted(path, &dots_dir, &secret, store_options, exec);
+            let _ = report.record(path, result.map(|_| true), "")?;
+        }
+    } else {
+        for path in input_paths.iter() {
+            let deploy_options = DeployOptions {
+                mode: deploy_mode(&config, values, &dots_dir, path, report),
+                ..deploy_options
+            };
+            let result = commands::stow_dotfile(
+                path,
+                &dots_dir,
+                store_options,
+                deploy_options,
+                exec,
+            );
+            let _ = report.record(path, result.map(|_| true), "")?;
+        }
+    };
+
+    run_hooks(hooks, Event::PostStow, &input_paths, exec, report)
+}
+
+/// Map of deploy paths to stored dotfiles and their dotfiles directory.
+type Targets = BTreeMap<PathBuf, (PathBuf, PathBuf)>;
+
+/// Collect the stored dotfiles to deploy, keyed by their deploy path. If no dotfiles were
+/// passed as arguments, every dotfile in the selected repositories is collected. Fails
+/// if two dotfiles of the same repository would be deployed to the same path.
+fn deploy_targets(config: &Config, values: &ArgMatches) -> Result<Targets> {
+    let system = System::current();
+
+    let input_paths: Option<Vec<PathBuf>> = values
+        .values_of("dotfiles")
+        .map(|paths| validate_paths(paths.map(PathBuf::from).collect()));
+
+    // repositories are ordered from lowest to highest precedence, so dotfiles of later
+    // repositories replace earlier ones
+    let mut targets = Targets::new();
+
+    for dotfiles_dir in repo_dirs(config, values)? {
+        let exclude = exclude_patterns(&dotfiles_dir, values)?;
+
+        let mut dotfiles = scan_repo(&dotfiles_dir, exclude)?;
+
+        // input directories select every dotfile stored below them
+        if let Some(input_paths) = &input_paths {
+            dotfiles.retain(|dotfile| {
+                input_paths.iter().any(|path| dotfile.starts_with(path))
+            });
+        };
+
+        // only deploy the alternate of each dotfile best matching this system
+        let mut repo_targets = Targets::new();
+        for dotfile in system.select(dotfiles) {
+            let dst_path = paths::deploy_path(&dotfiles_dir, &dotfile).map_err(|_| {
+                BadmError::OutsideRepo {
+                    path: dotfile.clone(),
+                }
+            })?;
+
+            if let Some((other, _)) = repo_targets.get(&dst_path) {
+                return Err(BadmError::Conflict {
+                    message: format!(
+                        "would be deployed from both {:?} and {:?}, remove or rename \
+                         one of them",
+                        other, dotfile
+                    ),
+                    path: dst_path,
+                });
+            };
+            let _ = repo_targets.insert(dst_path, (dotfile, dotfiles_dir.clone()));
+        }
+        targets.extend(repo_targets);
     }
-    Ok(())
+
+    Ok(targets)
 }
 
-fn deploy(values: &ArgMatches) -> io::Result<()> {
-    println!("inside of deploy");
-    let dotfiles_dir = Config::get_dots_dir().unwrap();
+fn deploy(values: &ArgMatches, exec: &mut Executor, report: &mut Report) -> Result<()> {
+    let config = Config::load()?;
+    let options = DeployOptions {
+        on_conflict: value_t!(values, "on-conflict", ConflictPolicy).unwrap_or_default(),
+        link_style: link_style(values),
+        force: values.is_present("force"),
+        ..DeployOptions::default()
+    };
+    let variables = template::variables(&config, &System::current());
+    // only ask for the secret once an encrypted dotfile is deployed
+    let mut secret = None;
 
-    let dotfiles = if values.is_present("all") {
-        DirScanner::default()
-            .recursive()
-            .get_entries(&dotfiles_dir)?
-    } else {
-        let paths: Vec<PathBuf> = values
-            .values_of("dotfiles")
-            .unwrap()
-            .map(PathBuf::from)
-            .collect();
+    let targets = deploy_targets(&config, values)?;
+    let target_paths: Vec<PathBuf> = targets.keys().cloned().collect();
+    run_hooks(&config.hooks, Event::PreDeploy, &target_paths, exec, report)?;
+
+    let mut deployed_paths = vec![];
+    for (dst_path, (dotfile, dotfiles_dir)) in targets {
+        let result = if crypto::is_encrypted(&dotfile) {
+            if secret.is_none() {
+                secret = Some(read_secret(values, false)?);
+            };
+
+            commands::deploy_encrypted(
+                &dotfile,
+                &dst_path,
+                &dotfiles_dir,
+                secret.as_ref().unwrap(),
+                options,
+                exec,
+            )
+        } else if template::is_template(&dotfile) {
+            commands::deploy_template(
+                &dotfile,
+                &dst_path,
+                &dotfiles_dir,
+                &variables,
+                options,
+                exec,
+            )
+        } else {
+            let options = DeployOptions {
+                mode: deploy_mode(&config, values, &dotfiles_dir, &dst_path, report),
+                ..options
+            };
+            commands::deploy_dotfile(&dotfile, &dst_path, &dotfiles_dir, options, exec)
+        };
+
+        if report.record(&dst_path, result, "target path already exists")? {
+            deployed_paths.push(dst_path);
+        };
+    }
+
+    run_hooks(
+        &config.hooks,
+        Event::PostDeploy,
+        &deployed_paths,
+        exec,
+        report,
+    )
+}
 
-        validate_paths(paths)
+fn undeploy(values: &ArgMatches, exec: &mut Executor, report: &mut Report) -> Result<()> {
+    let config = Config::load()?;
+    let options = UndeployOptions {
+        restore_backup: values.is_present("restore-backups"),
     };
 
-    for dotfile in dotfiles.into_iter() {
-        println!("{:?}", dotfile);
+    // deployed paths are symlinks, so they are made absolute without resolving them
+    let current_dir = env::current_dir()?;
+    let input_paths: Option<Vec<PathBuf>> = values.values_of("dotfiles").map(|paths| {
+        paths
+            .map(|path| paths::normalize(&current_dir.join(path)))
+            .collect()
+    });
+    let is_selected = |path: &Path| match &input_paths {
+        Some(input_paths) => input_paths.iter().any(|input| path.starts_with(input)),
+        None => true,
+    };
 
-        let dst_path = PathBuf::from("/").join(
-            dotfile
-                .strip_prefix(&dotfiles_dir)
-                .expect("could not strip dotfile path"),
-        );
-        println!("dst path: {:?}", dst_path);
+    let mut targets = BTreeMap::new();
+    for dots_dir in repo_dirs(&config, values)? {
+        for dotfile in Manifest::load(&dots_dir)?.dotfiles {
+            if dotfile.deployed_at.is_none() {
+                continue;
+            };
 
-        commands::deploy_dotfile(&dotfile, &dst_path)?;
+            if is_selected(&dotfile.source)
+                || is_selected(&dots_dir.join(&dotfile.stored))
+            {
+                let _ = targets.insert(dotfile.source, dots_dir.clone());
+            };
+        }
     }
 
+    // symlinks deployed before the manifest recorded them
+    for path in input_paths.iter().flatten() {
+        if !paths::is_symlink(path) || targets.contains_key(path) {
+            continue;
+        };
+        if let Some(dots_dir) = config.find_repo_dir(&paths::resolve_link(path)?) {
+            let _ = targets.insert(path.clone(), dots_dir);
+        };
+    }
+
+    for (dst_path, dots_dir) in targets {
+        let result = commands::undeploy_dotfile(&dst_path, &dots_dir, options, exec);
+
+        // targets that are already gone are not worth reporting
+        let exists = dst_path.exists() || paths::is_symlink(&dst_path);
+        if exists || !matches!(result, Ok(false)) {
+            let reason = format!("not deployed from {:?}", dots_dir);
+            let _ = report.record(&dst_path, result, &reason)?;
+        };
+    }
     Ok(())
 }
 
-fn restore(matches: &ArgMatches) -> io::Result<()> {
-    let dotfiles: Vec<PathBuf> = matches
-        .values_of("dotfiles")
-        .unwrap()
-        .map(PathBuf::from)
+fn adopt(values: &ArgMatches, exec: &mut Executor, report: &mut Report) -> Result<()> {
+    let config = Config::load()?;
+    let force = values.is_present("force");
+
+    let current_dir = env::current_dir()?;
+    let input_paths: Option<Vec<PathBuf>> = values.values_of("dotfiles").map(|paths| {
+        paths
+            .map(|path| paths::normalize(&current_dir.join(path)))
+            .collect()
+    });
+    let is_selected = |path: &Path| match &input_paths {
+        Some(input_paths) => input_paths.iter().any(|input| path.starts_with(input)),
+        None => true,
+    };
+
+    let mut targets = BTreeMap::new();
+    for dots_dir in repo_dirs(&config, values)? {
+        for dotfile in Manifest::load(&dots_dir)?.dotfiles {
+            let stored = dots_dir.join(&dotfile.stored);
+
+            if dotfile.mode == LinkMode::Copy
+                && dotfile.deployed_at.is_some()
+                && (is_selected(&dotfile.source) || is_selected(&stored))
+            {
+                let _ = targets.insert(dotfile.source, (dots_dir.clone(), stored));
+            };
+        }
+    }
+
+    for path in input_paths.iter().flatten() {
+        if !targets.iter().any(|(dst_path, (_, stored))| {
+            dst_path.starts_with(path) || stored.starts_with(path)
+        }) {
+            report.skipped(path, "not deployed as a copy");
+        };
+    }
+
+    for (dst_path, (dots_dir, _)) in targets {
+        let result = commands::adopt_copy(&dst_path, &dots_dir, force, exec);
+        let _ = report.record(&dst_path, result, "not modified since it was deployed")?;
+    }
+    Ok(())
+}
+
+/// Check the state of the target path of every stored dotfile selected by `values`.
+fn dotfile_statuses(config: &Config, values: &ArgMatches) -> Result<Vec<DotfileStatus>> {
+    let mut manifests = BTreeMap::new();
+    let mut statuses: Vec<DotfileStatus> = vec![];
+
+    for (dst_path, (dotfile, dotfiles_dir)) in deploy_targets(config, values)? {
+        if !manifests.contains_key(&dotfiles_dir) {
+            let manifest = Manifest::load(&dotfiles_dir)?;
+            let _ = manifests.insert(dotfiles_dir.clone(), manifest);
+        };
+
+        let stored = dotfile
+            .strip_prefix(&dotfiles_dir)
+            .map_err(|_| BadmError::OutsideRepo {
+                path: dotfile.clone(),
+            })?
+            .to_path_buf();
+        let record = manifests[&dotfiles_dir].get(&stored);
+
+        // a copy is checked as such until it is replaced by a symlink
+        let is_copy = config.deploy_mode(&dst_path) == DeployMode::Copy
+            || record
+                .map(|record| record.mode == LinkMode::Copy && record.source == dst_path)
+                .unwrap_or(false);
+
+        let is_hard_link = config.deploy_mode(&dst_path) == DeployMode::Hard
+            || record
+                .map(|record| record.mode == LinkMode::Hard && record.source == dst_path)
+                .unwrap_or(false);
+
+        if template::is_template(&dotfile) || crypto::is_encrypted(&dotfile) || is_copy {
+            statuses.push(DotfileStatus::generated(dotfile, dst_path, record));
+        } else if is_hard_link {
+            statuses.push(DotfileStatus::hard_link(dotfile, dst_path));
+        } else {
+            statuses.push(DotfileStatus::new(dotfile, dst_path));
+        };
+    }
+
+    Ok(statuses)
+}
+
+fn status(values: &ArgMatches, report: &mut Report) -> Result<()> {
+    let config = Config::load()?;
+    let statuses = dotfile_statuses(&config, values)?;
+
+    let lines = statuses.iter().map(DotfileStatus::to_string).collect();
+    report.data(&statuses, lines)?;
+    Ok(())
+}
+
+fn diff(values: &ArgMatches, report: &mut Report) -> Result<()> {
+    let config = Config::load()?;
+    let variables = template::variables(&config, &System::current());
+    // only ask for the secret once an encrypted dotfile is compared
+    let mut secret = None;
+
+    let format = value_t!(values, "format", Format).unwrap_or_default();
+    let color = format == Format::Human
+        && io::stdout().is_terminal()
+        && env::var_os("NO_COLOR").is_none();
+
+    let current_dir = env::current_dir()?;
+    let input_paths: Option<Vec<PathBuf>> = values.values_of("paths").map(|paths| {
+        paths
+            .map(|path| paths::normalize(&current_dir.join(path)))
+            .collect()
+    });
+
+    let mut statuses = dotfile_statuses(&config, values)?;
+    match &input_paths {
+        Some(input_paths) => {
+            for path in input_paths {
+                if !statuses.iter().any(|status| {
+                    status.target.starts_with(path) || status.stored.starts_with(path)
+                }) {
+                    report.skipped(path, "not a stored dotfile or its target path");
+                };
+            }
+            statuses.retain(|status| {
+                input_paths.iter().any(|path| {
+                    status.target.starts_with(path) || status.stored.starts_with(path)
+                })
+            });
+        },
+        None => statuses.retain(|status| status.status.is_drifted()),
+    };
+
+    let mut diffs = vec![];
+    for status in statuses {
+        let (stored, target) = (&status.stored, &status.target);
+
+        if !target.exists() {
+            report.skipped(target, "nothing exists at the target path");
+            continue;
+        } else if target.is_dir() || stored.is_dir() {
+            report.skipped(target, "directories are not compared");
+            continue;
+        };
+
+        let expected = if crypto::is_encrypted(stored) {
+            if secret.is_none() {
+                secret = Some(read_secret(values, false)?);
+            };
+            let data = fs::read(stored).at_path(stored)?;
+            crypto::decrypt(stored, &data, secret.as_ref().unwrap())?
+        } else if template::is_template(stored) {
+            let template = fs::read_to_string(stored).at_path(stored)?;
+            template::render(stored, &template, &variables)?.into_bytes()
+        } else {
+            fs::read(stored).at_path(stored)?
+        };
+        let actual = fs::read(target).at_path(target)?;
+
+        let diff = FileDiff::new(stored, target, &expected, &actual);
+        if diff.is_changed() {
+            diffs.push(diff);
+        };
+    }
+
+    let lines = diffs
+        .iter()
+        .flat_map(|diff| diff.lines.iter())
+        .map(|line| {
+            if color {
+                diff::colorize(line)
+            } else {
+                line.clone()
+            }
+        })
         .collect();
+    report.data(&diffs, lines)
+}
 
-    for dotfile in dotfiles.into_iter() {
-        commands::restore_dotfile(dotfile)?;
+fn doctor(values: &ArgMatches, exec: &mut Executor, report: &mut Report) -> Result<()> {
+    let config = Config::load()?;
+    let dots_dirs = repo_dirs(&config, values)?;
+
+    let roots = match values.values_of("roots") {
+        Some(roots) => {
+            let current_dir = env::current_dir()?;
+            roots.map(|root| current_dir.join(root)).collect()
+        },
+        None => config.target_roots(),
+    };
+
+    let targets = deploy_targets(&config, values)?;
+    let links = doctor::find_links(&roots, &dots_dirs);
+    let problems = doctor::diagnose(&links, &dots_dirs, &targets);
+
+    let lines = problems.iter().map(doctor::Problem::to_string).collect();
+    report.data(&problems, lines)?;
+
+    if !values.is_present("fix") {
+        return Ok(());
+    };
+
+    let options = DeployOptions {
+        on_conflict: ConflictPolicy::Skip,
+        link_style: link_style(values),
+        ..DeployOptions::default()
+    };
+    for problem in problems.iter() {
+        let mode = match targets.get(problem.path()) {
+            Some((_, dots_dir)) => {
+                deploy_mode(&config, values, dots_dir, problem.path(), report)
+            },
+            None => DeployMode::default(),
+        };
+        let options = DeployOptions { mode, ..options };
+        let result = doctor::fix(problem, &targets, options, exec);
+        let _ = report.record(problem.path(), result.map(|()| true), "")?;
     }
     Ok(())
 }
+
+fn restore(values: &ArgMatches, exec: &mut Executor, report: &mut Report) -> Result<()> {
+    let config = Config::load()?;
+
+    let mut dotfiles: Vec<PathBuf> = vec![];
+
+    for dotfile in values.values_of("dotfiles").unwrap().map(PathBuf::from) {
+        dotfiles.append(&mut restore_entries(&config, dotfile)?);
+    }
+
+    let mut restores = vec![];
+    for dotfile in dotfiles.into_iter() {
+        let dots_dir = if values.is_present("repo") {
+            repo_dir(values)?
+        } else {
+            let stored_path = if paths::is_symlink(&dotfile) {
+                paths::resolve_link(&dotfile)?
+            } else {
+                dotfile.clone()
+            };
+
+            config
+                .find_repo_dir(&stored_path)
+                .ok_or(BadmError::OutsideRepo { path: stored_path })?
+        };
+
+        restores.push((dotfile, dots_dir));
+    }
+
+    // hooks select dotfiles by the path they are restored to
+    let target_paths: Vec<PathBuf> = restores
+        .iter()
+        .map(|(dotfile, dots_dir)| match paths::is_symlink(dotfile) {
+            true => dotfile.clone(),
+            false => {
+                paths::deploy_path(dots_dir, dotfile).unwrap_or_else(|_| dotfile.clone())
+            },
+        })
+        .collect();
+    run_hooks(
+        &config.hooks,
+        Event::PreRestore,
+        &target_paths,
+        exec,
+        report,
+    )?;
+
+    let mut restored_paths = vec![];
+    for (dotfile, dots_dir) in restores.into_iter() {
+        match commands::restore_dotfile(dotfile.clone(), &dots_dir, exec) {
+            Ok(dst_path) => {
+                report.done(&dst_path);
+                restored_paths.push(dst_path);
+            },
+            Err(err) => return Err(report.failed(&dotfile, err)),
+        };
+    }
+
+    run_hooks(
+        &config.hooks,
+        Event::PostRestore,
+        &restored_paths,
+        exec,
+        report,
+    )
+}
+
+/// Get the dotfiles to restore for the input path `dotfile`.
+///
+/// Symlinks, files and folded directories are restored as they are. A directory stored
+/// with `--no-fold` is replaced by the dotfiles stored below it, and a directory on the
+/// system by the symlinks below it that point into a dotfiles directory.
+fn restore_entries(config: &Config, dotfile: PathBuf) -> Result<Vec<PathBuf>> {
+    if !dotfile.is_dir() || paths::is_symlink(&dotfile) {
+        return Ok(vec![dotfile]);
+    };
+
+    let dir = fs::canonicalize(&dotfile).at_path(&dotfile)?;
+
+    match config.find_repo_dir(&dir) {
+        Some(dots_dir) => {
+            let relative_path = dir.strip_prefix(&dots_dir).unwrap_or(&dir);
+            if Manifest::load(&dots_dir)?.is_folded(relative_path) {
+                return Ok(vec![dir]);
+            };
+
+            let mut entries =
+                scan_repo(&dots_dir, ExcludePatterns::from_dots_dir(&dots_dir)?)?;
+            entries.retain(|entry| entry.starts_with(&dir));
+            Ok(entries)
+        },
+        None => {
+            let mut entries = DirScanner::default().recursive().get_entries(&dir)?;
+            entries.retain(|entry| {
+                paths::is_symlink(entry)
+                    && paths::resolve_link(entry)
+                        .map(|link| config.find_repo_dir(&link).is_some())
+                        .unwrap_or(false)
+            });
+            Ok(entries)
+        },
+    }
+}
@@ -0,0 +1,297 @@
+//! Persistent record of the dotfiles managed by badm.
+//!
+//! Every dotfiles directory holds a manifest file, `.badm-manifest.toml`, which is
+//! updated whenever a dotfile is stored, deployed, or restored:
+//!
+//! ```toml
+//! [[dotfile]]
+//! source = "/home/ferris/.gitconfig"
+//! stored = "home/ferris/.gitconfig"
+//! mode = "symlink"
+//! hash = "9f86d081884c7d659a2feaa0c55ad015a3bf4f1b2b0b822cd15d6c15b0f00a08"
+//! stored_at = 1571011200
+//! deployed_at = 1571011200
+//! ```
+use std::fs::{self, File};
+use std::io::{self, prelude::*};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde_derive::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::errors::{BadmError, PathContext, Result};
+
+/// Name of the manifest file located at the top of a dotfiles directory.
+pub const MANIFEST_FILE_NAME: &str = ".badm-manifest.toml";
+
+/// How a stored dotfile is deployed to its source path.
+#[derive(Serialize, Deserialize, Debug, Default, PartialEq, Eq, Copy, Clone)]
+#[serde(rename_all = "lowercase")]
+pub enum LinkMode {
+    /// The source path is a symlink pointing to the stored dotfile.
+    #[default]
+    Symlink,
+    /// The stored dotfile is a template, which is rendered to the source path.
+    Template,
+    /// The stored dotfile is encrypted, and decrypted to the source path.
+    Encrypted,
+    /// The stored dotfile is copied to the source path.
+    Copy,
+    /// The source path is a hard link to the stored dotfile.
+    Hard,
+}
+
+/// A dotfile managed by badm.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+pub struct Dotfile {
+    /// Path the dotfile is deployed to.
+    pub source: PathBuf,
+    /// Path of the stored dotfile, relative to the dotfiles directory.
+    pub stored: PathBuf,
+    /// How the dotfile is deployed to its source path.
+    #[serde(default)]
+    pub mode: LinkMode,
+    /// SHA-256 hash of the stored dotfile's content, hex encoded. For templates and
+    /// copies, this is the hash of the stored dotfile at the time it was last deployed.
+    pub hash: String,
+    /// Unix timestamp of when the dotfile was stored.
+    pub stored_at: u64,
+    /// Unix timestamp of when the dotfile was last deployed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub deployed_at: Option<u64>,
+    /// Whether the dotfile is a directory deployed as a single symlink, instead of each
+    /// file within it being deployed individually.
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub folded: bool,
+    /// Path the file previously found at the source path was moved to when deploying.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub backup: Option<PathBuf>,
+    /// SHA-256 hash of the content last written to the source path, hex encoded, if the
+    /// dotfile is not deployed as a symlink.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub deployed_hash: Option<String>,
+}
+
+pub(crate) fn is_false(value: &bool) -> bool {
+    !*value
+}
+
+impl Dotfile {
+    /// Create a record for the dotfile stored at `stored_path` in `dots_dir`, deployed
+    /// to `source`. If `stored_path` is a directory, the record is marked as folded.
+    pub fn new(source: &Path, stored_path: &Path, dots_dir: &Path) -> Result<Self> {
+        let stored = stored_path
+            .strip_prefix(dots_dir)
+            .map_err(|_| BadmError::OutsideRepo {
+                path: stored_path.to_path_buf(),
+            })?
+            .to_path_buf();
+
+        Ok(Self {
+            source: source.to_path_buf(),
+            stored,
+            mode: LinkMode::default(),
+            hash: hash_path(stored_path)?,
+            stored_at: timestamp(),
+            deployed_at: None,
+            folded: stored_path.is_dir(),
+            backup: None,
+            deployed_hash: None,
+        })
+    }
+}
+
+/// Collection of the dotfiles stored in a dotfiles directory.
+#[derive(Serialize, Deserialize, Debug, Default, PartialEq, Clone)]
+pub struct Manifest {
+    /// Managed dotfiles.
+    #[serde(default, rename = "dotfile")]
+    pub dotfiles: Vec<Dotfile>,
+}
+
+impl Manifest {
+    /// Read the manifest of `dots_dir`. If no manifest has been written yet, an empty
+    /// manifest is returned.
+    pub fn load(dots_dir: &Path) -> Result<Self> {
+        let path = dots_dir.join(MANIFEST_FILE_NAME);
+
+        if !path.exists() {
+            return Ok(Self::default());
+        };
+
+        let toml = crate::paths::read_path(&path)?;
+        toml::from_str(&toml).map_err(|err| {
+            BadmError::invalid(&path, format!("could not read manifest: {}", err))
+        })
+    }
+
+    /// Write the manifest to `dots_dir`, replacing the previous manifest.
+    pub fn save(&self, dots_dir: &Path) -> Result<()> {
+        let path = dots_dir.join(MANIFEST_FILE_NAME);
+        let tmp_path = path.with_extension("toml.tmp");
+
+        let toml = toml::to_string(self).map_err(|err| {
+            BadmError::invalid(&path, format!("could not write manifest: {}", err))
+        })?;
+
+        File::create(&tmp_path)
+            .and_then(|mut file| {
+                file.write_all(toml.as_bytes())?;
+                file.sync_data()
+            })
+            .at_path(&tmp_path)?;
+
+        fs::rename(&tmp_path, &path).at_path(&path)
+    }
+
+    /// Get the record of the dotfile stored at `stored`, relative to the dotfiles
+    /// directory.
+    pub fn get(&self, stored: &Path) -> Option<&Dotfile> {
+        self.dotfiles
+            .iter()
+            .find(|dotfile| dotfile.stored == stored)
+    }
+
+    /// Get a mutable record of the dotfile stored at `stored`, relative to the dotfiles
+    /// directory.
+    pub fn get_mut(&mut self, stored: &Path) -> Option<&mut Dotfile> {
+        self.dotfiles
+            .iter_mut()
+            .find(|dotfile| dotfile.stored == stored)
+    }
+
+    /// Add a record, replacing any record with the same stored path.
+    pub fn insert(&mut self, dotfile: Dotfile) {
+        match self.get_mut(&dotfile.stored) {
+            Some(existing) => *existing = dotfile,
+            None => self.dotfiles.push(dotfile),
+        }
+    }
+
+    /// Stored paths of folded directories, relative to the dotfiles directory.
+    pub fn folded_dirs(&self) -> Vec<PathBuf> {
+        self.dotfiles
+            .iter()
+            .filter(|dotfile| dotfile.folded)
+            .map(|dotfile| dotfile.stored.clone())
+            .collect()
+    }
+
+    /// Returns `true` if `stored`, relative to the dotfiles directory, is a folded
+    /// directory.
+    pub fn is_folded(&self, stored: &Path) -> bool {
+        self.get(stored)
+            .map(|dotfile| dotfile.folded)
+            .unwrap_or(false)
+    }
+
+    /// Remove and return the record of the dotfile stored at `stored`.
+    pub fn remove(&mut self, stored: &Path) -> Option<Dotfile> {
+        let i = self
+            .dotfiles
+            .iter()
+            .position(|dotfile| dotfile.stored == stored)?;
+
+        Some(self.dotfiles.remove(i))
+    }
+}
+
+/// Get the hex encoded SHA-256 hash of the contents of the file at `path`.
+pub fn hash_file(path: &Path) -> Result<String> {
+    let mut hasher = Sha256::new();
+
+    let _ = File::open(path)
+        .and_then(|mut file| io::copy(&mut file, &mut hasher))
+        .at_path(path)?;
+
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+/// Get the hex encoded SHA-256 hash of `bytes`.
+pub fn hash_bytes(bytes: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(bytes))
+}
+
+/// Get the hex encoded SHA-256 hash of the file at `path`, or of the names and contents
+/// of every file below `path` if it is a directory.
+pub fn hash_path(path: &Path) -> Result<String> {
+    if !path.is_dir() {
+        return hash_file(path);
+    };
+
+    let mut hasher = Sha256::new();
+    hash_dir(path, path, &mut hasher)?;
+
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+fn hash_dir(root: &Path, dir: &Path, hasher: &mut Sha256) -> Result<()> {
+    let mut entries = fs::read_dir(dir)
+        .and_then(|entries| {
+            entries
+                .map(|entry| entry.map(|e| e.path()))
+                .collect::<io::Result<Vec<PathBuf>>>()
+        })
+        .at_path(dir)?;
+    entries.sort();
+
+    for path in entries {
+        if path.is_dir() {
+            hash_dir(root, &path, hasher)?;
+        } else {
+            let relative_path = path.strip_prefix(root).unwrap_or(&path);
+            hasher.update(relative_path.to_string_lossy().as_bytes());
+            hasher.update(hash_file(&path)?.as_bytes());
+        }
+    }
+    Ok(())
+}
+
+/// Get the current time as seconds since the Unix epoch.
+pub(crate) fn timestamp() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn manifest_roundtrip_test() -> io::Result<()> {
+        let dots_dir = tempfile::tempdir()?;
+        let dots_dir = dots_dir.path();
+
+        let stored_path = dots_dir.join("home/ferris/.gitconfig");
+        fs::create_dir_all(stored_path.parent().unwrap())?;
+        fs::write(&stored_path, "test")?;
+
+        let dotfile =
+            Dotfile::new(Path::new("/home/ferris/.gitconfig"), &stored_path, dots_dir)?;
+        assert_eq!(
+            dotfile.hash,
+            "9f86d081884c7d659a2feaa0c55ad015a3bf4f1b2b0b822cd15d6c15b0f00a08"
+        );
+
+        let mut manifest = Manifest::load(dots_dir)?;
+        assert!(manifest.dotfiles.is_empty());
+
+        manifest.insert(dotfile.clone());
+        manifest.insert(dotfile.clone());
+        manifest.save(dots_dir)?;
+
+        let mut manifest = Manifest::load(dots_dir)?;
+        assert_eq!(manifest.dotfiles, vec![dotfile.clone()]);
+
+        assert_eq!(
+            manifest.remove(Path::new("home/ferris/.gitconfig")),
+            Some(dotfile)
+        );
+        assert!(manifest.dotfiles.is_empty());
+
+        Ok(())
+    }
+}
@@ -1,8 +1,199 @@
 //! Includes paths/fs-specific helper functions.
+use std::fmt;
 use std::fs::{self, File};
 use std::io;
 use std::io::prelude::*;
-use std::path::{Path, PathBuf, StripPrefixError};
+use std::path::{Component, Path, PathBuf, StripPrefixError};
+use std::str::FromStr;
+
+use serde_derive::{Deserialize, Serialize};
+
+use crate::errors::{PathContext, Result};
+
+/// Name of the directory inside a dotfiles directory standing in for the home directory
+/// when using [`Layout::Home`].
+///
+/// [`Layout::Home`]: enum.Layout.html#variant.Home
+pub const HOME_DIR_PLACEHOLDER: &str = "~";
+
+/// How stored dotfiles are laid out inside a dotfiles directory.
+#[derive(Serialize, Deserialize, Debug, Default, PartialEq, Eq, Copy, Clone)]
+#[serde(rename_all = "lowercase")]
+pub enum Layout {
+    /// The full path of each dotfile is mirrored, starting at `/`, e.g. `~/.gitconfig`
+    /// of user ferris is stored at `home/ferris/.gitconfig`.
+    #[default]
+    Root,
+    /// Dotfiles located in the home directory are stored below a `~` directory, e.g.
+    /// `~/.gitconfig` is stored at `~/.gitconfig`, so that they can be deployed to the
+    /// home directory of any user. Other dotfiles are mirrored starting at `/`.
+    Home,
+}
+
+impl Layout {
+    /// Names accepted by [`Layout::from_str`].
+    ///
+    /// [`Layout::from_str`]: enum.Layout.html#method.from_str
+    pub const VARIANTS: [&'static str; 2] = ["root", "home"];
+
+    /// Returns `true` for [`Layout::Root`].
+    ///
+    /// [`Layout::Root`]: enum.Layout.html#variant.Root
+    pub fn is_root(&self) -> bool {
+        *self == Self::Root
+    }
+
+    /// Get the path the dotfile at `path` is stored at inside `dots_dir`.
+    pub fn stored_path(self, dots_dir: &Path, path: &Path) -> PathBuf {
+        if self == Self::Home {
+            let home_path = dirs::home_dir()
+                .and_then(|home| path.strip_prefix(home).ok().map(Path::to_path_buf));
+
+            if let Some(home_path) = home_path {
+                return dots_dir.join(HOME_DIR_PLACEHOLDER).join(home_path);
+            };
+        };
+
+        join_full_paths(dots_dir, path).unwrap()
+    }
+}
+
+impl FromStr for Layout {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "root" => Ok(Self::Root),
+            "home" => Ok(Self::Home),
+            _ => Err(format!("unknown layout {:?}", s)),
+        }
+    }
+}
+
+impl fmt::Display for Layout {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Root => write!(f, "root"),
+            Self::Home => write!(f, "home"),
+        }
+    }
+}
+
+/// How the target of a deployed dotfile's symlink is written.
+#[derive(Serialize, Deserialize, Debug, Default, PartialEq, Eq, Copy, Clone)]
+#[serde(rename_all = "lowercase")]
+pub enum LinkStyle {
+    /// The symlink holds the absolute path of the stored dotfile.
+    #[default]
+    Absolute,
+    /// The symlink holds the path of the stored dotfile relative to the symlink's parent
+    /// directory, so links keep working when the home and dotfiles directories are
+    /// moved or mounted elsewhere together.
+    Relative,
+}
+
+impl LinkStyle {
+    /// Names accepted by [`LinkStyle::from_str`].
+    ///
+    /// [`LinkStyle::from_str`]: enum.LinkStyle.html#method.from_str
+    pub const VARIANTS: [&'static str; 2] = ["absolute", "relative"];
+
+    /// Returns `true` for [`LinkStyle::Absolute`].
+    ///
+    /// [`LinkStyle::Absolute`]: enum.LinkStyle.html#variant.Absolute
+    pub fn is_absolute(&self) -> bool {
+        *self == Self::Absolute
+    }
+
+    /// Get the target to write into a symlink at `dst` pointing to `src`.
+    ///
+    /// Relative targets are computed between the canonical paths of `src` and of the
+    /// directory of `dst`, as the symlink is resolved against the directory's physical
+    /// path even if `dst` is given through a symlinked directory.
+    pub fn link_target(self, src: &Path, dst: &Path) -> PathBuf {
+        match self {
+            Self::Absolute => src.to_path_buf(),
+            Self::Relative => {
+                let parent = dst.parent().unwrap_or_else(|| Path::new("/"));
+
+                relative_path(&canonicalize(parent), &canonicalize(src))
+            },
+        }
+    }
+}
+
+impl FromStr for LinkStyle {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "absolute" => Ok(Self::Absolute),
+            "relative" => Ok(Self::Relative),
+            _ => Err(format!("unknown link style {:?}", s)),
+        }
+    }
+}
+
+impl fmt::Display for LinkStyle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Absolute => write!(f, "absolute"),
+            Self::Relative => write!(f, "relative"),
+        }
+    }
+}
+
+/// How a stored dotfile is deployed to its target path.
+#[derive(Serialize, Deserialize, Debug, Default, PartialEq, Eq, Copy, Clone)]
+#[serde(rename_all = "lowercase")]
+pub enum DeployMode {
+    /// The target path is a symlink pointing to the stored dotfile.
+    #[default]
+    Symlink,
+    /// The stored dotfile is copied to the target path, for programs that replace or do
+    /// not follow symlinks. Changes on either side are reported by `badm status`.
+    Copy,
+    /// The target path is a hard link to the stored dotfile, which has to be located on
+    /// the same filesystem.
+    Hard,
+}
+
+impl DeployMode {
+    /// Names accepted by [`DeployMode::from_str`].
+    ///
+    /// [`DeployMode::from_str`]: enum.DeployMode.html#method.from_str
+    pub const VARIANTS: [&'static str; 3] = ["symlink", "copy", "hard"];
+
+    /// Returns `true` for [`DeployMode::Symlink`].
+    ///
+    /// [`DeployMode::Symlink`]: enum.DeployMode.html#variant.Symlink
+    pub fn is_symlink(&self) -> bool {
+        *self == Self::Symlink
+    }
+}
+
+impl FromStr for DeployMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "symlink" => Ok(Self::Symlink),
+            "copy" => Ok(Self::Copy),
+            "hard" => Ok(Self::Hard),
+            _ => Err(format!("unknown deploy mode {:?}", s)),
+        }
+    }
+}
+
+impl fmt::Display for DeployMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Symlink => write!(f, "symlink"),
+            Self::Copy => write!(f, "copy"),
+            Self::Hard => write!(f, "hard"),
+        }
+    }
+}
 
 /// Wrapper for `is_symlink` for paths
 pub fn is_symlink(path: &Path) -> bool {
@@ -11,9 +202,53 @@ pub fn is_symlink(path: &Path) -> bool {
         .unwrap_or(false)
 }
 
-pub(crate) fn read_path(path: &Path) -> io::Result<String> {
-    let mut file = File::open(path)?;
-    read_file(&mut file)
+/// Returns `true` if `dst` is a hard link to the file at `src`, i.e. both paths refer to
+/// the same device and inode. Symlinks are never followed.
+pub fn is_hard_linked(src: &Path, dst: &Path) -> bool {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+
+        match (fs::symlink_metadata(src), fs::symlink_metadata(dst)) {
+            (Ok(src), Ok(dst)) => {
+                src.is_file() && src.dev() == dst.dev() && src.ino() == dst.ino()
+            },
+            _ => false,
+        }
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = (src, dst);
+        false
+    }
+}
+
+/// Returns `true` if `path` and `other` are located on the same filesystem. Paths that
+/// do not exist yet are located on the filesystem of their nearest existing parent
+/// directory.
+pub fn is_same_device(path: &Path, other: &Path) -> bool {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+
+        let device = |path: &Path| {
+            path.ancestors()
+                .find_map(|path| fs::symlink_metadata(path).ok())
+                .map(|metadata| metadata.dev())
+        };
+        device(path).is_some() && device(path) == device(other)
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = (path, other);
+        false
+    }
+}
+
+pub(crate) fn read_path(path: &Path) -> Result<String> {
+    File::open(path)
+        .and_then(|mut file| read_file(&mut file))
+        .at_path(path)
 }
 
 pub(crate) fn read_file(file: &mut File) -> io::Result<String> {
@@ -57,6 +292,161 @@ pub fn join_full_paths(
     Ok(path_1.join(path_2))
 }
 
+/// Get the system path a dotfile stored in `dots_dir` is deployed to. Alternate
+/// conditions, the `.tmpl` extension of templates, and the `.enc` extension of encrypted
+/// dotfiles are stripped from the file name.
+///
+/// Dotfiles stored below the `~` directory of [`Layout::Home`] are deployed to the
+/// current user's home directory, all other dotfiles to the path mirrored from `/`.
+///
+/// [`Layout::Home`]: enum.Layout.html#variant.Home
+///
+/// # Examples
+///
+/// ```
+/// use badm::paths::deploy_path;
+/// use std::path::{Path, PathBuf};
+///
+/// assert_eq!(
+///     deploy_path(
+///         Path::new("/home/ferris/.dotfiles"),
+///         Path::new("/home/ferris/.dotfiles/home/ferris/.gitconfig##os.Linux")
+///     ),
+///     Ok(PathBuf::from("/home/ferris/.gitconfig"))
+/// );
+/// ```
+pub fn deploy_path(dots_dir: &Path, dotfile: &Path) -> Result<PathBuf, StripPrefixError> {
+    let stored_path = crate::alternates::base_path(dotfile.strip_prefix(dots_dir)?);
+    let stored_path = crate::template::strip_extension(&stored_path);
+    let stored_path = crate::crypto::strip_extension(&stored_path);
+
+    if let Ok(home_path) = stored_path.strip_prefix(HOME_DIR_PLACEHOLDER) {
+        if let Some(home) = dirs::home_dir() {
+            return Ok(home.join(home_path));
+        };
+    };
+
+    Ok(PathBuf::from("/").join(stored_path))
+}
+
+/// Get the path of `path` relative to the directory `base`. Both paths are expected to
+/// be absolute.
+///
+/// # Examples
+///
+/// ```
+/// use badm::paths::relative_path;
+/// use std::path::{Path, PathBuf};
+///
+/// assert_eq!(
+///     relative_path(
+///         Path::new("/home/ferris/.config"),
+///         Path::new("/home/ferris/.dots/home/ferris/.config/fish")
+///     ),
+///     PathBuf::from("../.dots/home/ferris/.config/fish")
+/// );
+/// ```
+pub fn relative_path(base: &Path, path: &Path) -> PathBuf {
+    let base = normalize(base);
+    let path = normalize(path);
+
+    let common = base
+        .components()
+        .zip(path.components())
+        .take_while(|(a, b)| a == b)
+        .count();
+
+    let mut relative_path = PathBuf::new();
+    for _ in base.components().skip(common) {
+        relative_path.push("..");
+    }
+    for component in path.components().skip(common) {
+        relative_path.push(component);
+    }
+
+    if relative_path.as_os_str().is_empty() {
+        relative_path.push(".");
+    };
+    relative_path
+}
+
+/// Lexically remove `.` and `..` components from `path`, without touching the
+/// filesystem.
+pub fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+
+    for component in path.components() {
+        match component {
+            Component::CurDir => {},
+            Component::ParentDir => {
+                if !normalized.pop() {
+                    normalized.push(component);
+                };
+            },
+            component => normalized.push(component),
+        }
+    }
+    normalized
+}
+
+/// Get the path the symlink at `path` points to. Relative targets are resolved against
+/// the symlink's parent directory, so links written in either [`LinkStyle`] can be
+/// compared to stored dotfile paths.
+///
+/// A relative target is resolved against the canonical parent directory, as the system
+/// does. If resolving it against the parent directory as given names the same file,
+/// e.g. when both are located in a symlinked home directory, that path is returned
+/// instead, so it can be compared to paths given through the same symlinks.
+///
+/// [`LinkStyle`]: enum.LinkStyle.html
+pub fn resolve_link(path: &Path) -> Result<PathBuf> {
+    let link = fs::read_link(path).at_path(path)?;
+
+    if link.is_absolute() {
+        return Ok(link);
+    };
+
+    let parent = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    let resolved = normalize(&canonicalize(parent).join(&link));
+
+    if parent.is_absolute() {
+        let given = normalize(&parent.join(&link));
+
+        if is_same_path(&given, &resolved) {
+            return Ok(given);
+        };
+    };
+    Ok(resolved)
+}
+
+/// Get the canonical form of `path`, with all symlinks resolved. Paths that do not exist
+/// yet are resolved up to their nearest existing ancestor, and are lexically normalized
+/// below it.
+pub fn canonicalize(path: &Path) -> PathBuf {
+    let path = normalize(path);
+
+    for ancestor in path.ancestors() {
+        if let Ok(canonical) = fs::canonicalize(ancestor) {
+            let rest = path
+                .strip_prefix(ancestor)
+                .unwrap_or_else(|_| Path::new(""));
+            return normalize(&canonical.join(rest));
+        };
+    }
+    path
+}
+
+/// Returns `true` if `path` and `other` are equal, or name the same file once their
+/// symlinks are resolved, see [`canonicalize`].
+///
+/// [`canonicalize`]: fn.canonicalize.html
+pub fn is_same_path(path: &Path, other: &Path) -> bool {
+    path == other || canonicalize(path) == canonicalize(other)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -66,7 +456,8 @@ mod tests {
         // mock files
         let builder = tempfile::Builder::new();
 
-        let dir = builder.tempdir()?.into_path();
+        let tmp_dir = builder.tempdir()?;
+        let dir = tmp_dir.path().to_path_buf();
         let (_, file) = builder.tempfile_in(&dir)?.keep()?;
         let symlink_dst = dir.join("symlink_dst");
 
@@ -78,4 +469,85 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn home_layout_test() {
+        let home = dirs::home_dir().unwrap();
+        let dots_dir = Path::new("/dots");
+
+        let stored = Layout::Home.stored_path(dots_dir, &home.join(".config/fish"));
+        assert_eq!(stored, Path::new("/dots/~/.config/fish"));
+        assert_eq!(
+            deploy_path(dots_dir, &stored),
+            Ok(home.join(".config/fish"))
+        );
+
+        let stored = Layout::Home.stored_path(dots_dir, Path::new("/etc/hosts"));
+        assert_eq!(stored, Path::new("/dots/etc/hosts"));
+        assert_eq!(
+            deploy_path(dots_dir, &stored),
+            Ok(PathBuf::from("/etc/hosts"))
+        );
+    }
+
+    #[test]
+    fn relative_link_test() -> io::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let dir = fs::canonicalize(dir.path())?;
+
+        let src = dir.join(".dots/home/.vimrc");
+        let dst = dir.join("home/.vimrc");
+        fs::create_dir_all(src.parent().unwrap())?;
+        fs::create_dir_all(dst.parent().unwrap())?;
+        fs::write(&src, "")?;
+
+        let link = LinkStyle::Relative.link_target(&src, &dst);
+        assert_eq!(link, PathBuf::from("../.dots/home/.vimrc"));
+
+        crate::FileHandler::create_symlink(&link, &dst)?;
+        assert_eq!(resolve_link(&dst)?, src);
+        assert_eq!(fs::read_to_string(&dst)?, "");
+
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn relative_link_symlinked_dir_test() -> io::Result<()> {
+        use std::os::unix::fs::symlink;
+
+        let dir = tempfile::tempdir()?;
+        let dir = fs::canonicalize(dir.path())?;
+        let real = dir.join("real");
+        let home = dir.join("home");
+
+        // a symlinked home directory, holding a config directory linked elsewhere
+        fs::create_dir_all(real.join(".dots/.config/app"))?;
+        fs::create_dir_all(dir.join("config"))?;
+        symlink(&real, &home)?;
+        symlink(dir.join("config"), real.join(".config"))?;
+
+        let src = home.join(".dots/.config/app/settings");
+        fs::write(&src, "settings")?;
+
+        // the target's directory does not exist yet
+        let dst = home.join(".config/app/settings");
+        let link = LinkStyle::Relative.link_target(&src, &dst);
+        assert_eq!(link, PathBuf::from("../../real/.dots/.config/app/settings"));
+
+        fs::create_dir_all(dst.parent().unwrap())?;
+        crate::FileHandler::create_symlink(&link, &dst)?;
+        assert_eq!(fs::read_to_string(&dst)?, "settings");
+        assert!(is_same_path(&resolve_link(&dst)?, &src));
+
+        // links in the symlinked directory itself resolve to the path as given
+        let dst = home.join(".settings");
+        let link = LinkStyle::Relative.link_target(&src, &dst);
+        assert_eq!(link, PathBuf::from(".dots/.config/app/settings"));
+
+        crate::FileHandler::create_symlink(&link, &dst)?;
+        assert_eq!(resolve_link(&dst)?, src);
+
+        Ok(())
+    }
 }
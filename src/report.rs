@@ -0,0 +1,279 @@
+//! Structured results of commands, printed for humans or as JSON.
+//!
+//! Every command records what happened to each path it was given in a [`Report`],
+//! together with the filesystem actions it took. With `--format json`, the report is
+//! printed as a single JSON object once the command has finished:
+//!
+//! ```json
+//! {
+//!   "command": "deploy",
+//!   "dry_run": false,
+//!   "success": true,
+//!   "paths": [
+//!     { "path": "/home/ferris/.gitconfig", "outcome": "done" },
+//!     { "path": "/home/ferris/.vimrc", "outcome": "skipped", "reason": "target path already exists" }
+//!   ],
+//!   "actions": [
+//!     { "action": "symlink", "src": "/home/ferris/.dots/home/ferris/.gitconfig", "dst": "/home/ferris/.gitconfig" }
+//!   ],
+//!   "messages": [],
+//!   "warnings": []
+//! }
+//! ```
+//!
+//! [`Report`]: struct.Report.html
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use serde::Serialize;
+use serde_derive::Serialize;
+
+use crate::errors::{BadmError, Result};
+use crate::executor::Action;
+
+/// How the result of a command is printed.
+#[derive(Debug, Default, PartialEq, Eq, Copy, Clone)]
+pub enum Format {
+    /// Messages on stdout, skipped paths and warnings on stderr.
+    #[default]
+    Human,
+    /// A single JSON object on stdout.
+    Json,
+}
+
+impl Format {
+    /// Names accepted by [`Format::from_str`].
+    ///
+    /// [`Format::from_str`]: enum.Format.html#method.from_str
+    pub const VARIANTS: [&'static str; 2] = ["human", "json"];
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "human" => Ok(Self::Human),
+            "json" => Ok(Self::Json),
+            _ => Err(format!("unknown output format {:?}", s)),
+        }
+    }
+}
+
+impl fmt::Display for Format {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Self::Human => "human",
+            Self::Json => "json",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// What a command did with a single path.
+#[derive(Serialize, Debug, PartialEq, Eq, Copy, Clone)]
+#[serde(rename_all = "snake_case")]
+pub enum Outcome {
+    /// The path was handled.
+    Done,
+    /// The path was left alone.
+    Skipped,
+    /// Handling the path failed, which failed the command.
+    Failed,
+}
+
+/// Outcome of a command for a single path.
+#[derive(Serialize, Debug, PartialEq, Clone)]
+pub struct PathResult {
+    /// The handled path.
+    pub path: PathBuf,
+    /// What happened to the path.
+    pub outcome: Outcome,
+    /// Why the path was skipped or failed.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+}
+
+/// Result of a single command.
+#[derive(Serialize, Debug, PartialEq, Clone)]
+pub struct Report {
+    /// Name of the command, e.g. `deploy`.
+    pub command: String,
+    /// Whether the command ran in dry-run mode, so `actions` were only planned.
+    pub dry_run: bool,
+    /// Whether the command completed.
+    pub success: bool,
+    /// Outcome for each path the command handled.
+    pub paths: Vec<PathResult>,
+    /// Filesystem actions taken, or planned in dry-run mode. If the command failed, the
+    /// actions may have been rolled back.
+    pub actions: Vec<Action>,
+    /// Informational messages.
+    pub messages: Vec<String>,
+    /// Problems that did not fail the command.
+    pub warnings: Vec<String>,
+    /// Command specific results, such as the state of each dotfile reported by `status`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub data: Option<serde_json::Value>,
+    /// Human readable form of `data`, printed in its place.
+    #[serde(skip)]
+    pub lines: Vec<String>,
+    /// Error the command failed with.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl Report {
+    /// Create an empty report of a successful run of `command`.
+    pub fn new(command: &str, dry_run: bool) -> Self {
+        Self {
+            command: command.to_string(),
+            dry_run,
+            success: true,
+            paths: vec![],
+            actions: vec![],
+            messages: vec![],
+            warnings: vec![],
+            data: None,
+            lines: vec![],
+            error: None,
+        }
+    }
+
+    /// Record that `path` was handled.
+    pub fn done(&mut self, path: &Path) {
+        self.push(path, Outcome::Done, None);
+    }
+
+    /// Record that `path` was left alone because of `reason`.
+    pub fn skipped(&mut self, path: &Path, reason: &str) {
+        self.push(path, Outcome::Skipped, Some(reason.to_string()));
+    }
+
+    /// Record the result of handling `path`: done if it is `Ok(true)`, skipped because
+    /// of `reason` if it is `Ok(false)`, and failed otherwise. The error is returned, so
+    /// it can be propagated with `?`.
+    pub fn record(
+        &mut self,
+        path: &Path,
+        result: Result<bool>,
+        reason: &str,
+    ) -> Result<bool> {
+        let handled = result.map_err(|err| self.failed(path, err))?;
+
+        if handled {
+            self.done(path);
+        } else {
+            self.skipped(path, reason);
+        };
+        Ok(handled)
+    }
+
+    /// Record that handling `path` failed with `err`, which is returned.
+    pub fn failed(&mut self, path: &Path, err: BadmError) -> BadmError {
+        self.push(path, Outcome::Failed, Some(err.to_string()));
+        err
+    }
+
+    /// Add an informational message.
+    pub fn message<S: Into<String>>(&mut self, message: S) {
+        self.messages.push(message.into());
+    }
+
+    /// Add a warning about a problem that did not fail the command.
+    pub fn warning<S: Into<String>>(&mut self, warning: S) {
+        self.warnings.push(warning.into());
+    }
+
+    /// Set the command specific results to `data`, printed as `lines` for humans.
+    pub fn data<T: Serialize>(&mut self, data: &T, lines: Vec<String>) -> Result<()> {
+        let data = serde_json::to_value(data).map_err(|err| BadmError::Output {
+            message: err.to_string(),
+        })?;
+
+        self.data = Some(data);
+        self.lines = lines;
+        Ok(())
+    }
+
+    /// Mark the command as failed with `error`.
+    pub fn fail<E: fmt::Display>(&mut self, error: &E) {
+        self.success = false;
+        self.error = Some(error.to_string());
+    }
+
+    /// Print the report in `format`. The error is not printed for humans, as it is
+    /// reported on exit.
+    pub fn print(&self, format: Format) -> Result<()> {
+        match format {
+            Format::Json => {
+                let json = serde_json::to_string_pretty(self).map_err(|err| {
+                    BadmError::Output {
+                        message: err.to_string(),
+                    }
+                })?;
+                println!("{}", json);
+            },
+            Format::Human => {
+                for line in self.lines.iter().chain(self.messages.iter()) {
+                    println!("{}", line);
+                }
+                for result in self.paths.iter() {
+                    if let (Outcome::Skipped, Some(reason)) =
+                        (result.outcome, &result.reason)
+                    {
+                        eprintln!("skipped {:?}: {}", result.path, reason);
+                    };
+                }
+                for warning in self.warnings.iter() {
+                    eprintln!("{}", warning);
+                }
+                // planned actions are listed even if the command failed part way through
+                if self.dry_run {
+                    for action in self.actions.iter() {
+                        println!("[dry-run] {}", action);
+                    }
+                };
+            },
+        };
+        Ok(())
+    }
+
+    fn push(&mut self, path: &Path, outcome: Outcome, reason: Option<String>) {
+        self.paths.push(PathResult {
+            path: path.to_path_buf(),
+            outcome,
+            reason,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn report_test() -> Result<()> {
+        let mut report = Report::new("deploy", false);
+
+        assert!(report.record(Path::new("/a"), Ok(true), "exists")?);
+        assert!(!report.record(Path::new("/b"), Ok(false), "exists")?);
+        let err = BadmError::PermissionDenied {
+            path: PathBuf::from("/c"),
+        };
+        assert!(report.record(Path::new("/c"), Err(err), "exists").is_err());
+        report.fail(&"permission denied: \"/c\"");
+
+        let json = serde_json::to_value(&report).unwrap();
+        assert_eq!(json["success"], false);
+        assert_eq!(json["paths"][0]["outcome"], "done");
+        assert!(json["paths"][0].get("reason").is_none());
+        assert_eq!(json["paths"][1]["reason"], "exists");
+        assert_eq!(json["paths"][2]["outcome"], "failed");
+        assert_eq!(json["paths"][2]["reason"], "permission denied: \"/c\"");
+        assert_eq!(json["error"], "permission denied: \"/c\"");
+
+        Ok(())
+    }
+}
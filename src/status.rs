@@ -0,0 +1,352 @@
+//! Reports whether stored dotfiles are correctly deployed to their target paths.
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde_derive::Serialize;
+
+use crate::manifest::{hash_file, Dotfile, LinkMode};
+use crate::paths::{is_hard_linked, is_same_path, is_symlink, resolve_link};
+
+/// State of a stored dotfile's target path.
+#[derive(Serialize, Debug, PartialEq, Clone)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum LinkStatus {
+    /// Target path is a symlink pointing to the stored dotfile.
+    Linked,
+    /// Target path holds the unmodified output of the last render of the stored
+    /// template, or decryption of the stored encrypted dotfile.
+    Rendered,
+    /// Target path holds an unmodified copy of the stored dotfile.
+    Copied,
+    /// Target path holds the unmodified output generated from an earlier version of the
+    /// stored template, encrypted dotfile, or copied dotfile, is a symlink to the stored
+    /// file itself, or holds the content of a stored dotfile it should be hard linked
+    /// to. Deploying updates it.
+    Outdated,
+    /// Target path holds a copy of the stored dotfile that was modified since it was
+    /// deployed, while the stored dotfile was not. `badm adopt` copies the changes back,
+    /// `badm deploy --force` discards them.
+    Modified,
+    /// Nothing exists at the target path.
+    Missing,
+    /// Target path is a regular file or directory that deploying would clobber, or a
+    /// generated file or copy that was modified since it was deployed while the stored
+    /// dotfile was changed as well.
+    Conflict,
+    /// Target path is a symlink pointing to another existing file.
+    Foreign {
+        /// Path the symlink points to.
+        link: PathBuf,
+    },
+    /// Target path is a symlink pointing to a path that does not exist.
+    Dangling {
+        /// Path the symlink points to.
+        link: PathBuf,
+    },
+}
+
+impl LinkStatus {
+    /// Check the state of `dst`, the target path of the dotfile stored at `src`. Both
+    /// absolute and relative symlinks to `src` count as linked.
+    pub fn check(src: &Path, dst: &Path) -> Self {
+        if !is_symlink(dst) {
+            return if dst.exists() {
+                Self::Conflict
+            } else {
+                Self::Missing
+            };
+        };
+
+        let link = match fs::read_link(dst) {
+            Ok(link) => link,
+            Err(_) => return Self::Missing,
+        };
+
+        if link == src
+            || resolve_link(dst)
+                .map(|resolved| is_same_path(&resolved, src))
+                .unwrap_or(false)
+        {
+            Self::Linked
+        } else if dst.exists() {
+            Self::Foreign { link }
+        } else {
+            Self::Dangling { link }
+        }
+    }
+
+    /// Check the state of `dst`, the target path of the template, encrypted dotfile, or
+    /// copied dotfile stored at `src`, using `record`, the dotfile's manifest entry. A
+    /// regular file at `dst` only counts as rendered or copied if it is unmodified since
+    /// it was last written by badm.
+    pub fn check_generated(src: &Path, dst: &Path, record: Option<&Dotfile>) -> Self {
+        if is_symlink(dst) || !dst.exists() {
+            return match Self::check(src, dst) {
+                Self::Linked => Self::Outdated,
+                status => status,
+            };
+        };
+
+        let record = match record {
+            Some(record) if record.mode != LinkMode::Symlink && record.source == dst => {
+                record
+            },
+            _ => return Self::Conflict,
+        };
+
+        let is_modified = hash_file(dst).ok() != record.deployed_hash;
+        let is_outdated = hash_file(src).ok().as_ref() != Some(&record.hash);
+        let is_copy = record.mode == LinkMode::Copy;
+
+        match (is_modified, is_outdated) {
+            (false, false) if is_copy => Self::Copied,
+            (false, false) => Self::Rendered,
+            (false, true) => Self::Outdated,
+            (true, false) if is_copy => Self::Modified,
+            (true, _) => Self::Conflict,
+        }
+    }
+
+    /// Check the state of `dst`, the target path of the dotfile stored at `src` that is
+    /// deployed as a hard link. A regular file at `dst` only counts as linked if it is
+    /// the same file as `src`, i.e. has the same device and inode numbers. A symlink to
+    /// `src`, as deployed if the paths are located on different filesystems, counts as
+    /// linked as well.
+    pub fn check_hard_link(src: &Path, dst: &Path) -> Self {
+        if is_symlink(dst) || !dst.exists() {
+            return Self::check(src, dst);
+        };
+
+        if is_hard_linked(src, dst) {
+            Self::Linked
+        } else if dst.is_file() && hash_file(dst).ok() == hash_file(src).ok() {
+            // the link was broken, e.g. by a program replacing the file
+            Self::Outdated
+        } else {
+            Self::Conflict
+        }
+    }
+
+    /// Returns `true` if the target path is correctly linked, rendered, or copied.
+    pub fn is_linked(&self) -> bool {
+        matches!(self, Self::Linked | Self::Rendered | Self::Copied)
+    }
+
+    /// Returns `true` if the target path holds content other than the stored dotfile's,
+    /// i.e. it is outdated, modified, conflicting, or a foreign symlink.
+    pub fn is_drifted(&self) -> bool {
+        matches!(
+            self,
+            Self::Outdated | Self::Modified | Self::Conflict | Self::Foreign { .. }
+        )
+    }
+}
+
+impl fmt::Display for LinkStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Linked => write!(f, "linked"),
+            Self::Rendered => write!(f, "rendered"),
+            Self::Copied => write!(f, "copied"),
+            Self::Outdated => write!(f, "outdated"),
+            Self::Modified => write!(f, "modified"),
+            Self::Missing => write!(f, "missing"),
+            Self::Conflict => write!(f, "conflict"),
+            Self::Foreign { .. } => write!(f, "foreign"),
+            Self::Dangling { .. } => write!(f, "dangling"),
+        }
+    }
+}
+
+/// State of a single stored dotfile.
+#[derive(Serialize, Debug, PartialEq, Clone)]
+pub struct DotfileStatus {
+    /// Path of the stored dotfile.
+    pub stored: PathBuf,
+    /// Path the dotfile is deployed to.
+    pub target: PathBuf,
+    /// State of the target path.
+    #[serde(flatten)]
+    pub status: LinkStatus,
+}
+
+impl DotfileStatus {
+    /// Check the state of the target path `target` of the dotfile stored at `stored`.
+    pub fn new(stored: PathBuf, target: PathBuf) -> Self {
+        let status = LinkStatus::check(&stored, &target);
+
+        Self {
+            stored,
+            target,
+            status,
+        }
+    }
+
+    /// Check the state of the target path `target` of the template, encrypted dotfile, or
+    /// copied dotfile stored at `stored`, see [`LinkStatus::check_generated`].
+    ///
+    /// [`LinkStatus::check_generated`]: enum.LinkStatus.html#method.check_generated
+    pub fn generated(stored: PathBuf, target: PathBuf, record: Option<&Dotfile>) -> Self {
+        let status = LinkStatus::check_generated(&stored, &target, record);
+
+        Self {
+            stored,
+            target,
+            status,
+        }
+    }
+
+    /// Check the state of the target path `target` of the dotfile stored at `stored`
+    /// that is deployed as a hard link, see [`LinkStatus::check_hard_link`].
+    ///
+    /// [`LinkStatus::check_hard_link`]: enum.LinkStatus.html#method.check_hard_link
+    pub fn hard_link(stored: PathBuf, target: PathBuf) -> Self {
+        let status = LinkStatus::check_hard_link(&stored, &target);
+
+        Self {
+            stored,
+            target,
+            status,
+        }
+    }
+}
+
+impl fmt::Display for DotfileStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let status = self.status.to_string();
+
+        match &self.status {
+            LinkStatus::Linked | LinkStatus::Rendered | LinkStatus::Copied => {
+                write!(f, "{:<9}{:?}", status, self.target)
+            },
+            LinkStatus::Foreign { link } | LinkStatus::Dangling { link } => write!(
+                f,
+                "{:<9}{:?} -> {:?} (expected {:?})",
+                status, self.target, link, self.stored
+            ),
+            _ => write!(
+                f,
+                "{:<9}{:?} (stored at {:?})",
+                status, self.target, self.stored
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::FileHandler;
+    use std::io;
+
+    #[test]
+    fn check_test() -> io::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let dir = &fs::canonicalize(dir.path())?;
+
+        let stored = dir.join("stored");
+        let other = dir.join("other");
+        fs::write(&stored, "")?;
+        fs::write(&other, "")?;
+
+        let linked = dir.join("linked");
+        FileHandler::create_symlink(&stored, &linked)?;
+        assert_eq!(LinkStatus::check(&stored, &linked), LinkStatus::Linked);
+
+        let relative = dir.join("relative");
+        FileHandler::create_symlink(Path::new("stored"), &relative)?;
+        assert_eq!(LinkStatus::check(&stored, &relative), LinkStatus::Linked);
+
+        assert_eq!(
+            LinkStatus::check(&stored, &dir.join("missing")),
+            LinkStatus::Missing
+        );
+        assert_eq!(LinkStatus::check(&stored, &other), LinkStatus::Conflict);
+
+        let foreign = dir.join("foreign");
+        FileHandler::create_symlink(&other, &foreign)?;
+        assert_eq!(LinkStatus::check(&stored, &foreign), LinkStatus::Foreign {
+            link: other
+        });
+
+        let dangling = dir.join("dangling");
+        FileHandler::create_symlink(&dir.join("gone"), &dangling)?;
+        assert_eq!(
+            LinkStatus::check(&stored, &dangling),
+            LinkStatus::Dangling {
+                link: dir.join("gone")
+            }
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn check_generated_test() -> io::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let dir = &fs::canonicalize(dir.path())?;
+
+        let stored = dir.join(".gitconfig.tmpl");
+        let target = dir.join(".gitconfig");
+        fs::write(&stored, "email = {{ email }}")?;
+        fs::write(&target, "email = ferris@example.com")?;
+
+        let mut record = Dotfile::new(&target, &stored, dir)?;
+        assert_eq!(
+            LinkStatus::check_generated(&stored, &target, Some(&record)),
+            LinkStatus::Conflict
+        );
+
+        record.mode = LinkMode::Template;
+        record.deployed_hash = Some(hash_file(&target)?);
+        assert_eq!(
+            LinkStatus::check_generated(&stored, &target, Some(&record)),
+            LinkStatus::Rendered
+        );
+
+        fs::write(&stored, "email = {{ work_email }}")?;
+        assert_eq!(
+            LinkStatus::check_generated(&stored, &target, Some(&record)),
+            LinkStatus::Outdated
+        );
+
+        fs::write(&target, "email = edited@example.com")?;
+        assert_eq!(
+            LinkStatus::check_generated(&stored, &target, Some(&record)),
+            LinkStatus::Conflict
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn check_copy_test() -> io::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let dir = &fs::canonicalize(dir.path())?;
+
+        let stored = dir.join("stored.json");
+        let target = dir.join("settings.json");
+        fs::write(&stored, "{}")?;
+        fs::write(&target, "{}")?;
+
+        let mut record = Dotfile::new(&target, &stored, dir)?;
+        record.mode = LinkMode::Copy;
+        record.deployed_hash = Some(hash_file(&target)?);
+        let check = |record: &Dotfile| {
+            LinkStatus::check_generated(&stored, &target, Some(record))
+        };
+        assert_eq!(check(&record), LinkStatus::Copied);
+
+        fs::write(&target, "{ \"editor.fontSize\": 14 }")?;
+        assert_eq!(check(&record), LinkStatus::Modified);
+
+        fs::write(&stored, "{ \"editor.fontSize\": 12 }")?;
+        assert_eq!(check(&record), LinkStatus::Conflict);
+
+        fs::write(&target, "{}")?;
+        assert_eq!(check(&record), LinkStatus::Outdated);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,208 @@
+//! Templated dotfiles rendered with per-machine variables.
+//!
+//! A stored dotfile whose name ends in `.tmpl` is not symlinked when deployed. Instead,
+//! every `{{ name }}` placeholder in it is replaced by the value of the variable `name`,
+//! and the result is written to the dotfile's path without the `.tmpl` suffix:
+//!
+//! ```text
+//! [user]
+//!     name = Ferris
+//!     email = {{ email }}
+//! [core]
+//!     excludesfile = {{ home }}/.gitignore
+//! ```
+//!
+//! The following variables are always available:
+//!
+//! - `hostname` - host name of the machine
+//! - `user` - name of the current user
+//! - `os` - operating system name, e.g. `Linux` or `Darwin`
+//! - `arch` - CPU architecture, e.g. `x86_64`
+//! - `home` - path of the current user's home directory
+//!
+//! Further variables are read from the `[variables]` table of `.badm.toml`, and from the
+//! `[host.<HOSTNAME>]` table matching the machine's host name, which takes precedence,
+//! see [`Config`]. Using a variable that is not defined is an error.
+//!
+//! [`Config`]: ../struct.Config.html
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use crate::alternates::{base_path, System};
+use crate::errors::{BadmError, Result};
+use crate::Config;
+
+/// File extension marking a stored dotfile as a template.
+pub const TEMPLATE_EXTENSION: &str = "tmpl";
+
+/// Variables used to render templates, keyed by name.
+pub type Variables = BTreeMap<String, String>;
+
+/// Returns `true` if the stored dotfile at `path` is a template. Alternate conditions in
+/// the file name are ignored.
+pub fn is_template(path: &Path) -> bool {
+    base_path(path).extension().and_then(|ext| ext.to_str()) == Some(TEMPLATE_EXTENSION)
+}
+
+/// Strip the `.tmpl` extension from `path`, if present.
+///
+/// # Examples
+///
+/// ```
+/// use badm::template::strip_extension;
+/// use std::path::{Path, PathBuf};
+///
+/// assert_eq!(
+///     strip_extension(Path::new("home/ferris/.gitconfig.tmpl")),
+///     PathBuf::from("home/ferris/.gitconfig")
+/// );
+/// ```
+pub fn strip_extension(path: &Path) -> PathBuf {
+    if path.extension().and_then(|ext| ext.to_str()) == Some(TEMPLATE_EXTENSION) {
+        path.with_extension("")
+    } else {
+        path.to_path_buf()
+    }
+}
+
+/// Collect the variables available to templates on `system`: the built-in variables,
+/// overridden by the variables of `config`, overridden by the variables configured for
+/// the system's host name.
+pub fn variables(config: &Config, system: &System) -> Variables {
+    let mut variables = Variables::new();
+
+    let home = dirs::home_dir().unwrap_or_default();
+    let builtins = [
+        ("hostname", system.hostname.clone()),
+        ("user", system.user.clone()),
+        ("os", system.os.clone()),
+        ("arch", system.arch.clone()),
+        ("home", home.to_string_lossy().into_owned()),
+    ];
+    for (name, value) in builtins.iter() {
+        let _ = variables.insert(name.to_string(), value.clone());
+    }
+
+    variables.extend(config.variables.clone());
+    if let Some(host_variables) = config.hosts.get(&system.hostname) {
+        variables.extend(host_variables.clone());
+    };
+
+    variables
+}
+
+/// Replace every `{{ name }}` placeholder in `template`, the contents of the stored
+/// dotfile at `path`, with the value of the variable `name`.
+///
+/// # Examples
+///
+/// ```
+/// use badm::template::{render, Variables};
+/// use std::path::Path;
+///
+/// let path = Path::new("/home/ferris/.dots/home/ferris/.gitconfig.tmpl");
+/// let mut variables = Variables::new();
+/// variables.insert("email".to_string(), "ferris@example.com".to_string());
+///
+/// assert_eq!(
+///     render(path, "email = {{ email }}", &variables).unwrap(),
+///     "email = ferris@example.com"
+/// );
+/// assert!(render(path, "name = {{ name }}", &variables).is_err());
+/// ```
+pub fn render(path: &Path, template: &str, variables: &Variables) -> Result<String> {
+    let mut rendered = String::with_capacity(template.len());
+    let mut rest = template;
+
+    while let Some(start) = rest.find("{{") {
+        rendered.push_str(&rest[..start]);
+
+        let offset = template.len() - rest.len() + start;
+        let line = template[..offset].matches('\n').count() + 1;
+        let end = rest[start..].find("}}").ok_or_else(|| {
+            BadmError::invalid(path, format!("unterminated placeholder on line {}", line))
+        })?;
+
+        let name = rest[start + 2..start + end].trim();
+        let value = variables.get(name).ok_or_else(|| {
+            BadmError::invalid(
+                path,
+                format!("undefined variable {:?} on line {}", name, line),
+            )
+        })?;
+        rendered.push_str(value);
+
+        rest = &rest[start + end + 2..];
+    }
+    rendered.push_str(rest);
+
+    Ok(rendered)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn system() -> System {
+        System {
+            os: "Linux".to_string(),
+            arch: "x86_64".to_string(),
+            hostname: "build01".to_string(),
+            user: "ferris".to_string(),
+        }
+    }
+
+    #[test]
+    fn variables_test() {
+        let mut config = Config::default();
+        let _ = config
+            .variables
+            .insert("email".to_string(), "ferris@example.com".to_string());
+        let _ = config
+            .variables
+            .insert("os".to_string(), "GNU/Linux".to_string());
+
+        let mut host = BTreeMap::new();
+        let _ = host.insert("email".to_string(), "ferris@work.example.com".to_string());
+        let _ = config.hosts.insert("build01".to_string(), host);
+
+        let variables = variables(&config, &system());
+
+        assert_eq!(variables["user"], "ferris");
+        assert_eq!(variables["os"], "GNU/Linux");
+        assert_eq!(variables["email"], "ferris@work.example.com");
+    }
+
+    #[test]
+    fn render_test() -> Result<()> {
+        let path = Path::new("/dots/.gitconfig.tmpl");
+        let variables = variables(&Config::default(), &system());
+
+        assert_eq!(
+            render(
+                path,
+                "{{user}}@{{ hostname }}\n{ not a placeholder }",
+                &variables
+            )?,
+            "ferris@build01\n{ not a placeholder }"
+        );
+
+        let err = render(path, "a\nb = {{ missing }}", &variables).unwrap_err();
+        assert!(err.to_string().contains("\"missing\" on line 2"));
+        assert_eq!(err.path(), path);
+        let err = render(path, "{{ user", &variables).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "\"/dots/.gitconfig.tmpl\": unterminated placeholder on line 1"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn is_template_test() {
+        assert!(is_template(Path::new("/dots/.gitconfig.tmpl")));
+        assert!(is_template(Path::new("/dots/.gitconfig.tmpl##os.Linux")));
+        assert!(!is_template(Path::new("/dots/.gitconfig")));
+    }
+}
@@ -9,8 +9,9 @@ use crate::common::{
     badm_config, dotfiles_dir, home_dir, mock_config_file, mock_dotfile_in, stow_dir,
 };
 
+use badm::alternates::System;
 use badm::paths;
-use badm::{Config, FileHandler};
+use badm::{Config, FileHandler, Journal, Manifest};
 
 #[cfg(not(windows))]
 const EXE_PATH: &str = "./target/debug/badm";
@@ -45,7 +46,7 @@ fn run_stow_test() -> io::Result<()> {
 
     let file = mock_dotfile_in(home_dir()).expect("unable to mock input dotfile");
 
-    let expected_stow_path = stow_dir().join(&file.file_name().unwrap());
+    let expected_stow_path = stow_dir().join(file.file_name().unwrap());
 
     let output = mock_command()
         .arg("stow")
@@ -60,6 +61,48 @@ fn run_stow_test() -> io::Result<()> {
     Ok(())
 }
 
+#[ignore]
+#[test]
+fn run_stow_dry_run_test() -> io::Result<()> {
+    mock_config_file()?;
+
+    let file = mock_dotfile_in(home_dir()).expect("unable to mock input dotfile");
+    let stow_path = stow_dir().join(file.file_name().unwrap());
+
+    let output = mock_command()
+        .args(["--dry-run", "stow"])
+        .arg(&file)
+        .output()
+        .expect("failed to execute badm stow");
+
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("[dry-run] move"));
+    assert!(stdout.contains(&format!("[dry-run] symlink {:?} -> {:?}", file, stow_path)));
+    assert!(file.is_file() && !paths::is_symlink(&file));
+    assert!(!stow_path.exists());
+
+    Ok(())
+}
+
+#[ignore]
+#[test]
+fn run_stow_invalid_pattern_test() -> io::Result<()> {
+    mock_config_file()?;
+
+    let output = mock_command()
+        .args(["stow", "[.vimrc"])
+        .output()
+        .expect("failed to execute badm stow");
+
+    assert_eq!(output.status.code(), Some(2));
+    assert!(
+        String::from_utf8_lossy(&output.stderr).contains("\"[.vimrc\": invalid pattern")
+    );
+
+    Ok(())
+}
+
 #[ignore]
 #[test]
 fn run_stow_multiple_test() -> io::Result<()> {
@@ -88,6 +131,163 @@ fn run_stow_multiple_test() -> io::Result<()> {
     Ok(())
 }
 
+#[ignore]
+#[test]
+fn run_stow_exclude_test() -> io::Result<()> {
+    mock_config_file()?;
+
+    let file = mock_dotfile_in(home_dir()).expect("unable to mock input dotfile");
+    let excluded_file = home_dir().join("README.md");
+    fs::write(&excluded_file, "not a dotfile")?;
+
+    mock_command()
+        .arg("stow")
+        .args([&file, &excluded_file])
+        .args(["--exclude", "README*"])
+        .output()
+        .expect("failed to execute badm stow");
+
+    assert!(paths::is_symlink(&file));
+    assert!(!paths::is_symlink(&excluded_file));
+    assert!(!stow_dir().join("README.md").exists());
+
+    fs::remove_file(excluded_file)?;
+    Ok(())
+}
+
+#[ignore]
+#[test]
+fn run_stow_repo_test() -> io::Result<()> {
+    mock_config_file()?;
+
+    let repo_dir = home_dir().join(".work-dots");
+
+    mock_command()
+        .args(["set-dir", "--repo", "work", "--priority", "5"])
+        .arg(&repo_dir)
+        .output()
+        .expect("failed to execute badm set-dir");
+
+    assert_eq!(Config::get_repo_dir("work").ok(), Some(repo_dir.clone()));
+    assert_eq!(Config::get_dots_dir().ok(), Some(dotfiles_dir()));
+
+    let file = mock_dotfile_in(home_dir()).expect("unable to mock input dotfile");
+    let expected_stow_path = paths::join_full_paths(&repo_dir, &file).unwrap();
+
+    let output = mock_command()
+        .args(["stow", "--repo", "work"])
+        .arg(&file)
+        .output()
+        .expect("failed to execute badm stow");
+
+    assert!(output.status.success());
+    assert_eq!(fs::read_link(file).unwrap(), expected_stow_path);
+
+    Ok(())
+}
+
+#[ignore]
+#[test]
+fn run_stow_fold_test() -> io::Result<()> {
+    mock_config_file()?;
+
+    let dir = home_dir().join(".config/badm-fold");
+    let file = mock_dotfile_in(dir.join("nested")).expect("unable to mock input dotfile");
+    let expected_stow_dir = paths::join_full_paths(&dotfiles_dir(), &dir).unwrap();
+
+    let output = mock_command()
+        .arg("stow")
+        .arg(&dir)
+        .output()
+        .expect("failed to execute badm stow");
+
+    assert!(output.status.success());
+    assert_eq!(fs::read_link(&dir)?, expected_stow_dir);
+    assert!(Manifest::load(&dotfiles_dir())?
+        .is_folded(expected_stow_dir.strip_prefix(dotfiles_dir()).unwrap()));
+
+    let output = mock_command()
+        .arg("restore")
+        .arg(&dir)
+        .output()
+        .expect("failed to execute badm restore");
+
+    assert!(output.status.success());
+    assert!(!paths::is_symlink(&dir));
+    assert!(file.is_file());
+    assert!(!expected_stow_dir.exists());
+
+    Ok(())
+}
+
+#[ignore]
+#[test]
+fn run_stow_no_fold_test() -> io::Result<()> {
+    mock_config_file()?;
+
+    let dir = home_dir().join(".config/badm-no-fold");
+    let file = mock_dotfile_in(dir.join("nested")).expect("unable to mock input dotfile");
+    let expected_stow_path = paths::join_full_paths(&dotfiles_dir(), &file).unwrap();
+
+    let output = mock_command()
+        .args(["stow", "--no-fold"])
+        .arg(&dir)
+        .output()
+        .expect("failed to execute badm stow");
+
+    assert!(output.status.success());
+    assert!(!paths::is_symlink(&dir));
+    assert_eq!(fs::read_link(&file)?, expected_stow_path);
+
+    let output = mock_command()
+        .arg("restore")
+        .arg(&dir)
+        .output()
+        .expect("failed to execute badm restore");
+
+    assert!(output.status.success());
+    assert!(!paths::is_symlink(&file));
+    assert!(file.is_file());
+    assert!(!expected_stow_path.exists());
+
+    Ok(())
+}
+
+#[ignore]
+#[test]
+fn run_stow_rollback_test() -> io::Result<()> {
+    mock_config_file()?;
+
+    let file = mock_dotfile_in(home_dir()).expect("unable to mock input dotfile");
+    let blocked = mock_dotfile_in(home_dir().join(".badm-rollback"))
+        .expect("unable to mock input dotfile");
+
+    // storing the second file fails, as its stored parent directory is taken by a file
+    let blocked_dir =
+        paths::join_full_paths(&dotfiles_dir(), blocked.parent().unwrap()).unwrap();
+    fs::create_dir_all(blocked_dir.parent().unwrap())?;
+    fs::write(&blocked_dir, "")?;
+
+    let output = mock_command()
+        .arg("stow")
+        .arg(&file)
+        .arg(&blocked)
+        .output()
+        .expect("failed to execute badm stow");
+
+    fs::remove_file(&blocked_dir)?;
+
+    assert!(!output.status.success());
+    assert!(!paths::is_symlink(&file));
+    assert!(file.is_file());
+    assert!(!paths::join_full_paths(&dotfiles_dir(), &file)
+        .unwrap()
+        .exists());
+    assert!(Journal::open(&Journal::default_dir().unwrap())?.is_none());
+
+    Ok(())
+}
+
 #[ignore]
 #[test]
 fn run_deploy_test() -> io::Result<()> {
@@ -110,6 +310,259 @@ fn run_deploy_test() -> io::Result<()> {
     Ok(())
 }
 
+#[ignore]
+#[test]
+fn run_deploy_alternate_test() -> io::Result<()> {
+    mock_config_file()?;
+
+    let config_dir = stow_dir().join(".config");
+    let dotfile = mock_dotfile_in(config_dir.clone()).expect("failed to mock dotfile");
+    let file_name = dotfile.file_name().unwrap().to_str().unwrap().to_owned();
+
+    let matching = config_dir.join(format!("{}##os.{}", file_name, System::current().os));
+    let other = config_dir.join(format!("{}##os.Plan9", file_name));
+    fs::rename(&dotfile, &matching)?;
+    fs::write(&other, "")?;
+
+    let expected_deploy_path = home_dir().join(".config").join(&file_name);
+
+    mock_command()
+        .args(["deploy", "--all"])
+        .output()
+        .expect("failed to execute badm deploy");
+
+    assert_eq!(fs::read_link(expected_deploy_path).unwrap(), matching);
+
+    Ok(())
+}
+
+#[ignore]
+#[test]
+fn run_deploy_alternate_conflict_test() -> io::Result<()> {
+    mock_config_file()?;
+
+    let dotfile = mock_dotfile_in(stow_dir()).expect("failed to mock dotfile");
+    let file_name = dotfile.file_name().unwrap().to_str().unwrap().to_owned();
+    let template = stow_dir().join(format!("{}.tmpl", file_name));
+    fs::write(&template, "")?;
+
+    let output = mock_command()
+        .args(["deploy", "--all"])
+        .output()
+        .expect("failed to execute badm deploy");
+
+    assert_eq!(output.status.code(), Some(6));
+    assert!(
+        String::from_utf8_lossy(&output.stderr).contains("would be deployed from both")
+    );
+    assert!(!home_dir().join(&file_name).exists());
+
+    // leave no conflict behind for the other tests
+    fs::remove_file(&template)?;
+
+    Ok(())
+}
+
+#[ignore]
+#[test]
+fn run_status_json_test() -> io::Result<()> {
+    mock_config_file()?;
+
+    let linked = mock_dotfile_in(stow_dir()).expect("failed to mock dotfile");
+    let missing = mock_dotfile_in(stow_dir()).expect("failed to mock dotfile");
+
+    let linked_target = home_dir().join(linked.file_name().unwrap());
+    let missing_target = home_dir().join(missing.file_name().unwrap());
+    FileHandler::create_symlink(&linked, &linked_target)?;
+
+    let output = mock_command()
+        .args(["status", "--json"])
+        .output()
+        .expect("failed to execute badm status");
+    assert!(output.status.success());
+
+    let report: serde_json::Value =
+        serde_json::from_slice(&output.stdout).expect("status is not valid JSON");
+    assert_eq!(report["command"], "status");
+    let status_of = |target: &PathBuf| {
+        report["data"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|entry| entry["target"] == target.to_str().unwrap())
+            .map(|entry| entry["status"].as_str().unwrap().to_owned())
+    };
+
+    assert_eq!(status_of(&linked_target), Some("linked".to_owned()));
+    assert_eq!(status_of(&missing_target), Some("missing".to_owned()));
+
+    Ok(())
+}
+
+#[ignore]
+#[test]
+fn run_format_json_test() -> io::Result<()> {
+    mock_config_file()?;
+
+    let file = mock_dotfile_in(home_dir()).expect("unable to mock input dotfile");
+
+    let output = mock_command()
+        .args(["--format", "json", "stow"])
+        .arg(&file)
+        .output()
+        .expect("failed to execute badm stow");
+    assert!(output.status.success());
+
+    let report: serde_json::Value =
+        serde_json::from_slice(&output.stdout).expect("report is not valid JSON");
+    assert_eq!(report["command"], "stow");
+    assert_eq!(report["success"], true);
+    assert_eq!(report["paths"][0]["path"], file.to_str().unwrap());
+    assert_eq!(report["paths"][0]["outcome"], "done");
+    assert!(report["actions"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .any(|action| action["action"] == "symlink"));
+
+    let output = mock_command()
+        .args(["--format", "json", "restore", "/nonexistent"])
+        .output()
+        .expect("failed to execute badm restore");
+    assert!(!output.status.success());
+
+    let report: serde_json::Value =
+        serde_json::from_slice(&output.stdout).expect("report is not valid JSON");
+    assert_eq!(report["success"], false);
+    assert!(report["error"].is_string());
+
+    Ok(())
+}
+
+#[ignore]
+#[test]
+fn run_config_test() -> io::Result<()> {
+    mock_config_file()?;
+    let config = fs::read_to_string(badm_config())?;
+    let repo_config = dotfiles_dir().join("badm.toml");
+    fs::create_dir_all(dotfiles_dir())?;
+    fs::write(
+        &repo_config,
+        "link_style = \"relative\"\n[variables]\nemail = \"team@example.com\"\n",
+    )?;
+
+    let get = |key: &str, link_style: Option<&str>| {
+        let mut command = mock_command();
+        if let Some(link_style) = link_style {
+            let _ = command.env("BADM_LINK_STYLE", link_style);
+        };
+        let output = command
+            .args(["config", "get", key])
+            .output()
+            .expect("failed to execute badm config get");
+        String::from_utf8(output.stdout)
+            .unwrap()
+            .trim_end()
+            .to_owned()
+    };
+
+    // the repo-local config is overridden by the user config file
+    assert_eq!(get("variables.email", None), "team@example.com");
+    let output = mock_command()
+        .args(["config", "set", "variables.email", "ferris@example.com"])
+        .output()
+        .expect("failed to execute badm config set");
+    assert!(output.status.success());
+    assert_eq!(get("variables.email", None), "ferris@example.com");
+
+    // and both by environment variables
+    assert_eq!(get("link_style", None), "relative");
+    assert_eq!(get("link_style", Some("absolute")), "absolute");
+
+    let output = mock_command()
+        .args(["config", "get", "variables.nonexistent"])
+        .output()
+        .expect("failed to execute badm config get");
+    assert!(!output.status.success());
+
+    fs::write(badm_config(), config)?;
+    fs::remove_file(repo_config)?;
+
+    Ok(())
+}
+
+#[ignore]
+#[test]
+fn run_repo_config_hook_test() -> io::Result<()> {
+    mock_config_file()?;
+    let repo_config = dotfiles_dir().join("badm.toml");
+    let marker = home_dir().join("repo-hook-ran");
+    fs::create_dir_all(dotfiles_dir())?;
+    fs::write(
+        &repo_config,
+        format!(
+            "[[hook]]\non = \"pre-deploy\"\nrun = \"touch {}\"\n",
+            marker.display()
+        ),
+    )?;
+
+    // a cloned repository can not run commands on deploy
+    let output = mock_command()
+        .args(["deploy", "--all"])
+        .output()
+        .expect("failed to execute badm deploy");
+    assert!(!output.status.success());
+    assert_eq!(output.status.code(), Some(4));
+    assert!(!marker.exists());
+
+    fs::remove_file(repo_config)?;
+
+    Ok(())
+}
+
+#[ignore]
+#[test]
+fn run_diff_test() -> io::Result<()> {
+    mock_config_file()?;
+
+    let dotfile = mock_dotfile_in(stow_dir()).expect("failed to mock dotfile");
+    fs::write(&dotfile, "set number\nsyntax on\n")?;
+    let target = home_dir().join(dotfile.file_name().unwrap());
+    fs::write(&target, "set number\nsyntax off\n")?;
+
+    let output = mock_command()
+        .args(["diff", target.to_str().unwrap()])
+        .output()
+        .expect("failed to execute badm diff");
+    assert!(output.status.success());
+
+    // output is not colored when it is not printed to a terminal
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert_eq!(stdout.lines().skip(2).collect::<Vec<_>>(), vec![
+        "@@ -1,2 +1,2 @@",
+        " set number",
+        "-syntax on",
+        "+syntax off"
+    ]);
+
+    // the blocking file is reported as drifted as well
+    let output = mock_command()
+        .args(["--format", "json", "diff"])
+        .output()
+        .expect("failed to execute badm diff");
+    let report: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert!(report["data"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .any(|diff| diff["target"] == target.to_str().unwrap()));
+
+    fs::remove_file(target)?;
+    fs::remove_file(dotfile)?;
+
+    Ok(())
+}
+
 #[ignore]
 #[test]
 fn run_restore_dotfile_test() -> io::Result<()> {
@@ -117,10 +570,10 @@ fn run_restore_dotfile_test() -> io::Result<()> {
 
     let dotfile = mock_dotfile_in(stow_dir()).expect("failed to mock dotfile");
 
-    let expected_restore_path = home_dir().join(&dotfile.file_name().unwrap());
+    let expected_restore_path = home_dir().join(dotfile.file_name().unwrap());
 
     mock_command()
-        .args(&["restore", dotfile.to_str().unwrap()])
+        .args(["restore", dotfile.to_str().unwrap()])
         .output()
         .expect("failed to execute badm restore");
 
@@ -136,12 +589,12 @@ fn run_restore_symlink_test() -> io::Result<()> {
     mock_config_file()?;
 
     let dotfile = mock_dotfile_in(stow_dir()).expect("failed to mock dotfile");
-    let expected_restore_path = home_dir().join(&dotfile.file_name().unwrap());
+    let expected_restore_path = home_dir().join(dotfile.file_name().unwrap());
 
     FileHandler::create_symlink(&dotfile, &expected_restore_path)?;
 
     mock_command()
-        .args(&["restore", expected_restore_path.to_str().unwrap()])
+        .args(["restore", expected_restore_path.to_str().unwrap()])
         .output()
         .expect("failed to execute badm restore");
 
@@ -29,8 +29,9 @@ pub fn mock_config_file() -> io::Result<()> {
     } else {
         let config = Config {
             directory: dotfiles_dir(),
+            ..Config::default()
         };
-        config.write_toml_config()
+        Ok(config.write_toml_config()?)
     }
 }
 
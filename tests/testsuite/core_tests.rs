@@ -2,11 +2,19 @@
 
 use std::fs;
 use std::io;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 use badm::commands;
+use badm::crypto::Secret;
+use badm::git::Git;
 use badm::paths;
-use badm::{self, FileHandler};
+use badm::status::LinkStatus;
+use badm::template::Variables;
+use badm::{
+    self, ConflictPolicy, DeployMode, DeployOptions, DirScanner, ExcludePatterns,
+    Executor, FileHandler, Journal, LinkMode, LinkStyle, Manifest, StoreOptions,
+    UndeployOptions,
+};
 
 use crate::common::{
     dotfiles_dir, home_dir, mock_config_file, mock_dotfile_in, stow_dir,
@@ -21,11 +29,26 @@ fn store_dotfiles_test() -> io::Result<()> {
 
     let expected_stow_path = stow_dir().join(dotfile_path.file_name().unwrap());
 
-    let stow_path = commands::store_dotfile(&dotfile_path)?;
+    let stow_path = commands::store_dotfile(
+        &dotfile_path,
+        &dotfiles_dir(),
+        StoreOptions::default(),
+        &mut Executor::new(),
+    )?;
 
     assert!(expected_stow_path.exists());
     assert_eq!(expected_stow_path, stow_path);
 
+    // a file stored before is replaced when it is stored again
+    fs::write(&dotfile_path, "restowed")?;
+    let stow_path = commands::store_dotfile(
+        &dotfile_path,
+        &dotfiles_dir(),
+        StoreOptions::default(),
+        &mut Executor::new(),
+    )?;
+    assert_eq!(fs::read_to_string(stow_path)?, "restowed");
+
     Ok(())
 }
 
@@ -43,7 +66,8 @@ fn restore_dotfile_test() -> io::Result<()> {
     fs::create_dir_all(symlink_path.parent().unwrap())?;
     FileHandler::create_symlink(&dotfile_path, &symlink_path)?;
 
-    let actual_dst_path = commands::restore_dotfile(dotfile_path)?;
+    let actual_dst_path =
+        commands::restore_dotfile(dotfile_path, &dotfiles_dir(), &mut Executor::new())?;
 
     assert!(!paths::is_symlink(&symlink_path));
     assert_eq!(actual_dst_path, symlink_path);
@@ -67,9 +91,707 @@ fn deploy_dotfile_test() -> io::Result<()> {
 
     let expected_symlink_path = PathBuf::from("/").join(stripped_dotfile_path);
 
-    commands::deploy_dotfile(&dotfile_path, &expected_symlink_path)?;
+    commands::deploy_dotfile(
+        &dotfile_path,
+        &expected_symlink_path,
+        &dotfiles_dir(),
+        DeployOptions::default(),
+        &mut Executor::new(),
+    )?;
 
     assert_eq!(fs::read_link(expected_symlink_path)?, dotfile_path);
 
     Ok(())
 }
+
+#[ignore]
+#[test]
+fn manifest_records_dotfile_test() -> io::Result<()> {
+    mock_config_file()?;
+
+    let dotfile_path = mock_dotfile_in(home_dir())?;
+
+    let stow_path = commands::store_dotfile(
+        &dotfile_path,
+        &dotfiles_dir(),
+        StoreOptions::default(),
+        &mut Executor::new(),
+    )?;
+    commands::deploy_dotfile(
+        &stow_path,
+        &dotfile_path,
+        &dotfiles_dir(),
+        DeployOptions::default(),
+        &mut Executor::new(),
+    )?;
+
+    let stored_path = stow_path.strip_prefix(dotfiles_dir()).unwrap();
+    let manifest = Manifest::load(&dotfiles_dir())?;
+    let dotfile = manifest.get(stored_path).expect("dotfile not recorded");
+
+    assert_eq!(dotfile.source, dotfile_path);
+    assert_eq!(dotfile.mode, LinkMode::Symlink);
+    assert!(dotfile.deployed_at.is_some());
+
+    let _ =
+        commands::restore_dotfile(dotfile_path, &dotfiles_dir(), &mut Executor::new())?;
+
+    let manifest = Manifest::load(&dotfiles_dir())?;
+    assert!(manifest.get(stored_path).is_none());
+
+    Ok(())
+}
+
+#[test]
+fn deploy_dotfile_conflict_policy_test() -> io::Result<()> {
+    let dir = tempfile::tempdir()?;
+    let dots_dir = dir.path().join("dots");
+    let stored = dots_dir.join(".gitconfig");
+    let target = dir.path().join(".gitconfig");
+
+    let deploy = |on_conflict| {
+        let options = DeployOptions {
+            on_conflict,
+            ..DeployOptions::default()
+        };
+        commands::deploy_dotfile(
+            &stored,
+            &target,
+            &dots_dir,
+            options,
+            &mut Executor::new(),
+        )
+    };
+
+    fs::create_dir_all(&dots_dir)?;
+    fs::write(&stored, "stored")?;
+    fs::write(&target, "existing")?;
+
+    assert!(!deploy(ConflictPolicy::Skip)?);
+    assert!(!paths::is_symlink(&target));
+
+    assert!(deploy(ConflictPolicy::Backup)?);
+    assert_eq!(fs::read_link(&target)?, stored);
+    let backup = Manifest::load(&dots_dir)?
+        .get(Path::new(".gitconfig"))
+        .and_then(|dotfile| dotfile.backup.clone())
+        .expect("backup not recorded");
+    assert_eq!(fs::read_to_string(backup)?, "existing");
+
+    fs::remove_file(&target)?;
+    fs::write(&target, "overwritten")?;
+    assert!(deploy(ConflictPolicy::Overwrite)?);
+    assert_eq!(fs::read_to_string(&target)?, "stored");
+
+    fs::remove_file(&target)?;
+    fs::write(&target, "adopted")?;
+    assert!(deploy(ConflictPolicy::Adopt)?);
+    assert_eq!(fs::read_link(&target)?, stored);
+    assert_eq!(fs::read_to_string(&stored)?, "adopted");
+
+    Ok(())
+}
+
+#[test]
+fn deploy_dotfile_relative_link_test() -> io::Result<()> {
+    let dir = tempfile::tempdir()?;
+    let dir = fs::canonicalize(dir.path())?;
+    let dots_dir = dir.join("dots");
+    let stored = dots_dir.join("home/.gitconfig");
+    let target = dir.join("home/.gitconfig");
+
+    fs::create_dir_all(stored.parent().unwrap())?;
+    fs::write(&stored, "stored")?;
+
+    let options = DeployOptions {
+        link_style: LinkStyle::Relative,
+        ..DeployOptions::default()
+    };
+    assert!(commands::deploy_dotfile(
+        &stored,
+        &target,
+        &dots_dir,
+        options,
+        &mut Executor::new()
+    )?);
+
+    assert_eq!(
+        fs::read_link(&target)?,
+        Path::new("../dots/home/.gitconfig")
+    );
+    assert!(LinkStatus::check(&stored, &target).is_linked());
+
+    // switching styles replaces the link instead of reporting a conflict
+    assert!(commands::deploy_dotfile(
+        &stored,
+        &target,
+        &dots_dir,
+        DeployOptions::default(),
+        &mut Executor::new()
+    )?);
+    assert_eq!(fs::read_link(&target)?, stored);
+
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn undeploy_dotfile_test() -> io::Result<()> {
+    use std::os::unix::fs::symlink;
+
+    let dir = tempfile::tempdir()?;
+    let dir = fs::canonicalize(dir.path())?;
+    let dots_dir = dir.join("dots");
+    let stored = dots_dir.join(".gitconfig");
+    let target = dir.join(".gitconfig");
+    let foreign = dir.join(".vimrc");
+
+    fs::create_dir_all(&dots_dir)?;
+    fs::write(&stored, "stored")?;
+    fs::write(&target, "existing")?;
+
+    let options = DeployOptions {
+        on_conflict: ConflictPolicy::Backup,
+        ..DeployOptions::default()
+    };
+    assert!(commands::deploy_dotfile(
+        &stored,
+        &target,
+        &dots_dir,
+        options,
+        &mut Executor::new()
+    )?);
+
+    // symlinks pointing outside of the dotfiles directory are left alone
+    symlink(dir.join("elsewhere"), &foreign)?;
+    assert!(!commands::undeploy_dotfile(
+        &foreign,
+        &dots_dir,
+        UndeployOptions::default(),
+        &mut Executor::new()
+    )?);
+    assert!(paths::is_symlink(&foreign));
+
+    let options = UndeployOptions {
+        restore_backup: true,
+    };
+    assert!(commands::undeploy_dotfile(
+        &target,
+        &dots_dir,
+        options,
+        &mut Executor::new()
+    )?);
+
+    assert!(!paths::is_symlink(&target));
+    assert_eq!(fs::read_to_string(&target)?, "existing");
+    assert_eq!(fs::read_to_string(&stored)?, "stored");
+
+    let manifest = Manifest::load(&dots_dir)?;
+    let dotfile = manifest.get(Path::new(".gitconfig")).unwrap();
+    assert_eq!(dotfile.deployed_at, None);
+    assert_eq!(dotfile.backup, None);
+
+    Ok(())
+}
+
+#[test]
+fn deploy_template_test() -> io::Result<()> {
+    let dir = tempfile::tempdir()?;
+    let dir = fs::canonicalize(dir.path())?;
+    let dots_dir = dir.join("dots");
+    let stored = dots_dir.join("home/.gitconfig.tmpl");
+    let target = dir.join("home/.gitconfig");
+
+    fs::create_dir_all(stored.parent().unwrap())?;
+    fs::write(&stored, "email = {{ email }}\n")?;
+
+    let mut variables = Variables::new();
+    let _ = variables.insert("email".to_string(), "ferris@example.com".to_string());
+
+    let deploy = |variables: &Variables| {
+        commands::deploy_template(
+            &stored,
+            &target,
+            &dots_dir,
+            variables,
+            DeployOptions::default(),
+            &mut Executor::new(),
+        )
+    };
+
+    assert!(deploy(&variables)?);
+    assert!(!paths::is_symlink(&target));
+    assert_eq!(fs::read_to_string(&target)?, "email = ferris@example.com\n");
+
+    let manifest = Manifest::load(&dots_dir)?;
+    let record = manifest.get(Path::new("home/.gitconfig.tmpl")).unwrap();
+    assert_eq!(record.mode, LinkMode::Template);
+    assert_eq!(
+        LinkStatus::check_generated(&stored, &target, Some(record)),
+        LinkStatus::Rendered
+    );
+
+    // unmodified renders are replaced when variables change
+    let _ = variables.insert("email".to_string(), "ferris@work.example.com".to_string());
+    assert!(deploy(&variables)?);
+    assert_eq!(
+        fs::read_to_string(&target)?,
+        "email = ferris@work.example.com\n"
+    );
+
+    // edited renders are conflicts
+    fs::write(&target, "email = edited@example.com\n")?;
+    assert!(!deploy(&variables)?);
+    assert_eq!(fs::read_to_string(&target)?, "email = edited@example.com\n");
+
+    assert!(deploy(&Variables::new()).is_err());
+
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn deploy_copy_test() -> io::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir = tempfile::tempdir()?;
+    let dir = fs::canonicalize(dir.path())?;
+    let dots_dir = dir.join("dots");
+    let stored = dots_dir.join("home/.config/app/settings.json");
+    let target = dir.join("home/.config/app/settings.json");
+
+    fs::create_dir_all(stored.parent().unwrap())?;
+    fs::write(&stored, "{}")?;
+    fs::set_permissions(&stored, fs::Permissions::from_mode(0o640))?;
+
+    let options = DeployOptions {
+        mode: DeployMode::Copy,
+        ..DeployOptions::default()
+    };
+    let deploy = |options: DeployOptions| {
+        commands::deploy_dotfile(
+            &stored,
+            &target,
+            &dots_dir,
+            options,
+            &mut Executor::new(),
+        )
+    };
+    let status = || {
+        let manifest = Manifest::load(&dots_dir).unwrap();
+        let record = manifest.get(Path::new("home/.config/app/settings.json"));
+        LinkStatus::check_generated(&stored, &target, record)
+    };
+
+    assert!(deploy(options)?);
+    assert!(!paths::is_symlink(&target));
+    assert_eq!(fs::read_to_string(&target)?, "{}");
+    assert_eq!(fs::metadata(&target)?.permissions().mode() & 0o777, 0o640);
+    assert_eq!(status(), LinkStatus::Copied);
+
+    // changes to the copy are adopted into the dotfiles directory
+    fs::write(&target, "{ \"fontSize\": 14 }")?;
+    assert_eq!(status(), LinkStatus::Modified);
+    assert!(!deploy(options)?);
+    assert!(commands::adopt_copy(
+        &target,
+        &dots_dir,
+        false,
+        &mut Executor::new()
+    )?);
+    assert_eq!(fs::read_to_string(&stored)?, "{ \"fontSize\": 14 }");
+    assert_eq!(status(), LinkStatus::Copied);
+    assert!(!commands::adopt_copy(
+        &target,
+        &dots_dir,
+        false,
+        &mut Executor::new()
+    )?);
+
+    // changes to the stored dotfile are deployed
+    fs::write(&stored, "{ \"fontSize\": 12 }")?;
+    assert_eq!(status(), LinkStatus::Outdated);
+    assert!(deploy(options)?);
+    assert_eq!(fs::read_to_string(&target)?, "{ \"fontSize\": 12 }");
+
+    // changes on both sides are only replaced when forced
+    fs::write(&target, "{ \"fontSize\": 16 }")?;
+    fs::write(&stored, "{ \"fontSize\": 10 }")?;
+    assert_eq!(status(), LinkStatus::Conflict);
+    assert!(
+        commands::adopt_copy(&target, &dots_dir, false, &mut Executor::new()).is_err()
+    );
+    assert!(!deploy(options)?);
+    assert!(deploy(DeployOptions {
+        force: true,
+        ..options
+    })?);
+    assert_eq!(fs::read_to_string(&target)?, "{ \"fontSize\": 10 }");
+
+    // an unmodified copy is replaced when switching to a symlink
+    assert!(deploy(DeployOptions::default())?);
+    assert_eq!(fs::read_link(&target)?, stored);
+
+    Ok(())
+}
+
+#[test]
+fn deploy_hard_link_test() -> io::Result<()> {
+    let dir = tempfile::tempdir()?;
+    let dir = fs::canonicalize(dir.path())?;
+    let dots_dir = dir.join("dots");
+    let stored = dots_dir.join("home/.var/app/config.ini");
+    let target = dir.join("home/.var/app/config.ini");
+
+    fs::create_dir_all(stored.parent().unwrap())?;
+    fs::write(&stored, "[app]")?;
+    assert!(paths::is_same_device(&dots_dir, &target));
+
+    let options = DeployOptions {
+        mode: DeployMode::Hard,
+        ..DeployOptions::default()
+    };
+    let deploy = |options: DeployOptions| {
+        commands::deploy_dotfile(
+            &stored,
+            &target,
+            &dots_dir,
+            options,
+            &mut Executor::new(),
+        )
+    };
+
+    assert!(deploy(options)?);
+    assert!(paths::is_hard_linked(&stored, &target));
+    assert_eq!(
+        LinkStatus::check_hard_link(&stored, &target),
+        LinkStatus::Linked
+    );
+    let manifest = Manifest::load(&dots_dir)?;
+    let record = manifest.get(Path::new("home/.var/app/config.ini")).unwrap();
+    assert_eq!(record.mode, LinkMode::Hard);
+
+    // a link broken by replacing the file is relinked if nothing would be lost
+    fs::remove_file(&target)?;
+    fs::write(&target, "[app]")?;
+    assert_eq!(
+        LinkStatus::check_hard_link(&stored, &target),
+        LinkStatus::Outdated
+    );
+    assert!(deploy(options)?);
+    assert!(paths::is_hard_linked(&stored, &target));
+
+    fs::remove_file(&target)?;
+    fs::write(&target, "[app]\nedited = true")?;
+    assert_eq!(
+        LinkStatus::check_hard_link(&stored, &target),
+        LinkStatus::Conflict
+    );
+    assert!(!deploy(options)?);
+    assert_eq!(fs::read_to_string(&stored)?, "[app]");
+
+    // a hard link is replaced when switching to a symlink, and removed on undeploy
+    fs::remove_file(&target)?;
+    assert!(deploy(options)?);
+    assert!(deploy(DeployOptions::default())?);
+    assert!(paths::is_symlink(&target));
+    assert!(deploy(options)?);
+    assert!(commands::undeploy_dotfile(
+        &target,
+        &dots_dir,
+        UndeployOptions::default(),
+        &mut Executor::new()
+    )?);
+    assert!(!target.exists());
+    assert!(stored.exists());
+
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn store_encrypted_test() -> io::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir = tempfile::tempdir()?;
+    let dir = fs::canonicalize(dir.path())?;
+    let dots_dir = dir.join("dots");
+    let path = dir.join("home/.netrc");
+
+    fs::create_dir_all(path.parent().unwrap())?;
+    fs::create_dir_all(&dots_dir)?;
+    fs::write(&path, "token = hunter2\n")?;
+
+    let secret = Secret::new("correct horse battery staple")?;
+    let journal_dir = dir.join("journal");
+    let mut exec = Executor::new().journal(Journal::begin(&journal_dir, vec![])?);
+    let stored = commands::store_encrypted(
+        &path,
+        &dots_dir,
+        &secret,
+        StoreOptions::default(),
+        &mut exec,
+    )?;
+
+    // no copy of the plaintext is preserved in the journal
+    for entry in fs::read_dir(&journal_dir)? {
+        let contents = fs::read(entry?.path())?;
+        assert!(!String::from_utf8_lossy(&contents).contains("hunter2"));
+    }
+    exec.commit()?;
+
+    assert!(stored.starts_with(&dots_dir) && stored.ends_with("home/.netrc.enc"));
+    assert_ne!(fs::read(&stored)?, b"token = hunter2\n");
+    assert_eq!(fs::read_to_string(&path)?, "token = hunter2\n");
+    assert_eq!(fs::metadata(&path)?.permissions().mode() & 0o777, 0o600);
+
+    fs::remove_file(&path)?;
+    assert!(commands::deploy_encrypted(
+        &stored,
+        &path,
+        &dots_dir,
+        &secret,
+        DeployOptions::default(),
+        &mut Executor::new()
+    )?);
+    assert_eq!(fs::read_to_string(&path)?, "token = hunter2\n");
+    assert_eq!(fs::metadata(&path)?.permissions().mode() & 0o777, 0o600);
+
+    let manifest = Manifest::load(&dots_dir)?;
+    let record = manifest
+        .get(stored.strip_prefix(&dots_dir).unwrap())
+        .unwrap();
+    assert_eq!(record.mode, LinkMode::Encrypted);
+    assert!(LinkStatus::check_generated(&stored, &path, Some(record)).is_linked());
+
+    fs::remove_file(&path)?;
+    assert!(commands::deploy_encrypted(
+        &stored,
+        &path,
+        &dots_dir,
+        &Secret::new("wrong")?,
+        DeployOptions::default(),
+        &mut Executor::new()
+    )
+    .is_err());
+    assert!(!path.exists());
+
+    Ok(())
+}
+
+#[test]
+fn commit_changes_test() -> io::Result<()> {
+    let dir = tempfile::tempdir()?;
+    let dir = fs::canonicalize(dir.path())?;
+    let dots_dir = dir.join("dots");
+    let clone = dir.join("clone");
+
+    let git = Git::new(&dir);
+    let _ = git.output(["init", "--quiet", "--bare", "remote.git"])?;
+    for checkout in ["dots", "clone"].iter() {
+        let _ = git.output(["clone", "--quiet", "remote.git", checkout])?;
+        let checkout = Git::new(&dir.join(checkout));
+        let _ = checkout.output(["config", "user.name", "Ferris"])?;
+        let _ = checkout.output(["config", "user.email", "ferris@example.com"])?;
+    }
+
+    let dotfile = dir.join("home/.gitconfig");
+    fs::create_dir_all(dotfile.parent().unwrap())?;
+    fs::write(&dotfile, "[user]")?;
+    fs::write(dots_dir.join("notes"), "not a dotfile")?;
+
+    let mut exec = Executor::new();
+    let stored =
+        commands::store_dotfile(&dotfile, &dots_dir, StoreOptions::default(), &mut exec)?;
+    assert!(commands::commit_changes(&dots_dir, "stow", exec.actions())?);
+    assert!(!commands::commit_changes(
+        &dots_dir,
+        "stow",
+        exec.actions()
+    )?);
+
+    let repo = Git::new(&dots_dir);
+    let stored = stored.strip_prefix(&dots_dir).unwrap();
+    assert_eq!(
+        repo.output(["log", "-1", "--format=%s"])?.trim(),
+        format!("badm stow: {}", stored.display())
+    );
+    assert_eq!(repo.output(["status", "--porcelain"])?, "?? notes\n");
+
+    repo.push()?;
+    Git::new(&clone).pull()?;
+    assert!(clone.join(stored).is_file());
+    assert!(clone.join(badm::manifest::MANIFEST_FILE_NAME).is_file());
+
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn init_dots_dir_test() -> io::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir = tempfile::tempdir()?;
+    let dir = fs::canonicalize(dir.path())?;
+    let source = dir.join("source");
+
+    fs::create_dir_all(source.join("~"))?;
+    fs::write(source.join("~/.vimrc"), "set number")?;
+    fs::write(
+        source.join(commands::BOOTSTRAP_FILE_NAME),
+        "#!/bin/sh\ntouch bootstrapped\n",
+    )?;
+    fs::set_permissions(
+        source.join(commands::BOOTSTRAP_FILE_NAME),
+        fs::Permissions::from_mode(0o755),
+    )?;
+
+    // plain directories are copied
+    let copy = dir.join("copy");
+    assert!(!commands::init_dots_dir(source.to_str().unwrap(), &copy)?);
+    assert!(!Git::new(&copy).is_repo());
+    assert_eq!(fs::read_to_string(copy.join("~/.vimrc"))?, "set number");
+    assert!(commands::init_dots_dir(source.to_str().unwrap(), &copy).is_err());
+
+    // repositories are cloned
+    let git = Git::new(&source);
+    let _ = git.output(["init", "--quiet"])?;
+    let _ = git.output(["add", "--all"])?;
+    let _ = git.output([
+        "-c",
+        "user.name=Ferris",
+        "-c",
+        "user.email=ferris@example.com",
+        "commit",
+        "--quiet",
+        "-m",
+        "Add dotfiles",
+    ])?;
+    let _ =
+        Git::new(&dir).output(["clone", "--quiet", "--bare", "source", "remote.git"])?;
+
+    let clone = dir.join("clone");
+    assert!(commands::init_dots_dir(
+        dir.join("remote.git").to_str().unwrap(),
+        &clone
+    )?);
+    assert!(Git::new(&clone).is_repo());
+    assert_eq!(fs::read_to_string(clone.join("~/.vimrc"))?, "set number");
+
+    assert!(commands::run_bootstrap(&clone)?);
+    assert!(clone.join("bootstrapped").exists());
+    assert!(!commands::run_bootstrap(&dir)?);
+
+    let exclude = ExcludePatterns::from_dots_dir(&clone)?;
+    assert!(exclude.is_excluded(Path::new(commands::BOOTSTRAP_FILE_NAME), false));
+
+    Ok(())
+}
+
+#[test]
+fn dir_scanner_exclude_test() -> io::Result<()> {
+    let dots_dir = tempfile::tempdir()?;
+    let dots_dir = dots_dir.path();
+
+    fs::write(dots_dir.join(".badmignore"), "README*\n.github/\n")?;
+    fs::create_dir_all(dots_dir.join(".github/workflows"))?;
+    fs::write(dots_dir.join(".github/workflows/ci.yml"), "")?;
+    fs::write(dots_dir.join("README.md"), "")?;
+
+    let dotfile = mock_dotfile_in(dots_dir.join("home/ferris"))?;
+
+    let entries = DirScanner::default()
+        .recursive()
+        .exclude(ExcludePatterns::from_dots_dir(dots_dir)?)
+        .get_entries(dots_dir)?;
+
+    assert_eq!(entries, vec![dotfile]);
+
+    Ok(())
+}
+
+#[test]
+fn dir_scanner_folded_test() -> io::Result<()> {
+    let dots_dir = tempfile::tempdir()?;
+    let dots_dir = dots_dir.path();
+
+    let folded_dir = dots_dir.join("home/ferris/.config/nvim");
+    let _ = mock_dotfile_in(folded_dir.clone())?;
+    let dotfile = mock_dotfile_in(dots_dir.join("home/ferris/.config/fish"))?;
+
+    let mut entries = DirScanner::default()
+        .recursive()
+        .folded(vec![PathBuf::from("home/ferris/.config/nvim")])
+        .get_entries(dots_dir)?;
+    entries.sort();
+
+    assert_eq!(entries, vec![dotfile, folded_dir]);
+
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn move_file_preserves_content_and_metadata_test() -> io::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+    use std::time::{Duration, SystemTime};
+
+    let dir = tempfile::tempdir()?;
+    let contents: &[u8] = &[0x00, 0x9f, 0x92, 0x96, 0xff, 0xfe];
+    let modified = SystemTime::UNIX_EPOCH + Duration::from_secs(1_500_000_000);
+
+    let mock_file = |name: &str| -> io::Result<PathBuf> {
+        let path = dir.path().join(name);
+        fs::write(&path, contents)?;
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o600))?;
+        fs::File::open(&path)?.set_modified(modified)?;
+        Ok(path)
+    };
+
+    // extended attributes are only checked where the filesystem supports them
+    let xattrs = match xattr::set(dir.path(), "user.badm", b"") {
+        Err(ref err) if err.kind() == io::ErrorKind::Unsupported => false,
+        result => result.map(|_| true)?,
+    };
+    let mock_file = |name: &str| -> io::Result<PathBuf> {
+        let path = mock_file(name)?;
+        if xattrs {
+            xattr::set(&path, "user.badm.origin", b"ferris")?;
+        };
+        Ok(path)
+    };
+
+    let assert_preserved = |path: &PathBuf| -> io::Result<()> {
+        let metadata = fs::metadata(path)?;
+        assert_eq!(fs::read(path)?, contents);
+        assert_eq!(metadata.permissions().mode() & 0o777, 0o600);
+        assert_eq!(metadata.modified()?, modified);
+        if xattrs {
+            assert_eq!(
+                xattr::get(path, "user.badm.origin")?,
+                Some(b"ferris".to_vec())
+            );
+        };
+        Ok(())
+    };
+
+    // moving within a filesystem renames the file
+    let src = mock_file("config")?;
+    let dst = dir.path().join("moved");
+    FileHandler::move_file(&src, &dst)?;
+
+    assert!(!src.exists());
+    assert_preserved(&dst)?;
+
+    // copying is used as a fallback for moves across filesystems
+    let src = mock_file("ssh_config")?;
+    let dst = dir.path().join("copied");
+    // left behind by an interrupted copy
+    fs::write(dir.path().join(".copied.badm-tmp"), "partial")?;
+    FileHandler::copy_file(&src, &dst)?;
+
+    assert_preserved(&src)?;
+    assert_preserved(&dst)?;
+
+    Ok(())
+}